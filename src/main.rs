@@ -2,30 +2,470 @@
 // https://www.brycevandyk.com/debug-rust-on-windows-with-visual-studio-code-and-the-msvc-debugger/
 
 fn main() {
-    let _ = run_game();
+    let args: Vec<String> = std::env::args().collect();
+    if args.iter().any(|a| a == "--ai-match") {
+        run_ai_match(&args);
+        return;
+    }
+    if args.iter().any(|a| a == "--net-test") {
+        run_net_test(&args);
+        return;
+    }
+    if args.iter().any(|a| a == "--s4-check") {
+        run_s4_check(&args);
+        return;
+    }
+    if args.iter().any(|a| a == "--gfx-check") {
+        run_gfx_check(&args);
+        return;
+    }
+    if args.iter().any(|a| a == "--fairness") {
+        run_fairness(&args);
+        return;
+    }
+    if args.iter().any(|a| a == "--validate") {
+        run_validate(&args);
+        return;
+    }
+    if let Err(e) = run_game() {
+        eprintln!("Can't start the game. {}", error_message(&e));
+        std::process::exit(1);
+    }
+}
+
+// Computer gegen Computer ohne Fenster, für Balance-Tests:
+// --ai-match [--seed N] [--players N] [--size N] [--symmetry S] [--ticks N] [--record DATEI]
+fn run_ai_match(args: &[String]) {
+    let option = |name: &str, default: u64| number_option(args, name).unwrap_or(default);
+    let (report, replay) = match types::ai::run_match(
+        load_production(args),
+        option("--seed", 1),
+        option("--players", 2) as usize,
+        option("--size", 64) as u16,
+        symmetry_option(args),
+        option("--ticks", 12000),
+    ) {
+        Ok(result) => result,
+        Err(e) => {
+            eprintln!("Can't start the match: {}", e);
+            std::process::exit(1);
+        }
+    };
+    print!("{}", report);
+    if let Some(path) = string_option(args, "--record") {
+        if let Err(e) = replay.save(path) {
+            eprintln!("Can't save replay to {}: {}", path, e);
+        }
+    }
+}
+
+// Spielt ein Computerspiel über Netzwerkclients auf diesem Rechner nach und prüft, dass
+// alle Clients dasselbe ausrechnen:
+// --net-test [--seed N] [--players N] [--size N] [--symmetry S] [--ticks N]
+fn run_net_test(args: &[String]) {
+    let option = |name: &str, default: u64| number_option(args, name).unwrap_or(default);
+    let seed = option("--seed", 1);
+    let ticks = option("--ticks", 2000);
+    let production = load_production(args);
+    let (_, replay) = match types::ai::run_match(
+        production.clone(),
+        seed,
+        option("--players", 2) as usize,
+        option("--size", 48) as u16,
+        symmetry_option(args),
+        ticks,
+    ) {
+        Ok(result) => result,
+        Err(e) => {
+            eprintln!("Can't start the match: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let worlds = match types::net::run_local_match(&replay, &production, ticks) {
+        Ok(worlds) => worlds,
+        Err(e) => {
+            eprintln!("Network test failed: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let report = MatchReport {
+        seed,
+        ticks,
+        players: replay
+            .setup
+            .starts
+            .iter()
+            .map(|(player, _, _)| worlds[0].player_report(*player))
+            .collect(),
+    };
+    print!("{}", report);
+    // Die Clients vergleichen ihre Hashes schon unterwegs, hier noch einmal am Ende
+    let hash = worlds[0].state_hash();
+    for (i, world) in worlds.iter().enumerate().skip(1) {
+        if let Some(subsystem) = hash.first_difference(&world.state_hash()) {
+            eprintln!(
+                "Clients out of sync after {} ticks in {}",
+                ticks,
+                subsystem.name()
+            );
+            // Beide Stände zum Vergleichen mit diff
+            for (n, world) in [(0, &worlds[0]), (i, world)] {
+                let path = types::desync::dump_path(
+                    std::path::Path::new(types::desync::DESYNC_DIR),
+                    &format!("net_test_client_{}", n),
+                    ticks,
+                );
+                if world.dump_state(&path).is_ok() {
+                    eprintln!("State written to {}", path.display());
+                }
+            }
+            std::process::exit(1);
+        }
+    }
+    println!(
+        "{} clients in sync after {} ticks, state hash {:016x}",
+        worlds.len(),
+        ticks,
+        hash.combined()
+    );
+}
+
+// Prüft den Import von Siedler-4-Karten mit allen .map- und .edm-Dateien im
+// Verzeichnis. Jede Datei, die sich nicht lesen lässt, lässt die Prüfung scheitern:
+// --s4-check [VERZEICHNIS]
+fn run_s4_check(args: &[String]) {
+    let dir = match string_option(args, "--s4-check").filter(|dir| !dir.starts_with("--")) {
+        Some(dir) => Some(std::path::PathBuf::from(dir)),
+        None => asset_manager(args).resolve(assets::S4_FIXTURES).ok(),
+    };
+    let mut failed = false;
+    let paths = dir
+        .map(|dir| types::s4map::fixture_paths(&dir))
+        .unwrap_or_default();
+    for path in paths {
+        match S4Map::load(&path) {
+            Ok(s4) => println!(
+                "{}: {}x{}, {} players",
+                path.display(),
+                s4.map.width,
+                s4.map.height,
+                s4.starts.len()
+            ),
+            Err(e) => {
+                println!("{}: {}", path.display(), e);
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        std::process::exit(1);
+    }
+}
+
+// Vergleicht die Startpunkte einer Karte, selbst gebaut oder erzeugt. Schlägt fehl,
+// wenn ein Start zu weit hinter den anderen liegt:
+// --fairness [KARTE] oder --fairness [--seed N] [--players N] [--size N] [--symmetry S]
+fn run_fairness(args: &[String]) {
+    let (map, starts) = match string_option(args, "--fairness").filter(|p| !p.starts_with("--")) {
+        Some(path) => match S4Map::load_with_resources(path) {
+            Ok(s4) => (s4.map, s4.starts),
+            Err(e) => {
+                eprintln!("Can't load {}: {}", path, e);
+                std::process::exit(1);
+            }
+        },
+        None => {
+            let option = |name: &str, default: u64| number_option(args, name).unwrap_or(default);
+            let symmetry = symmetry_option(args);
+            let size = option("--size", 64) as u16;
+            let map = Map::generate_symmetric(size, size, option("--seed", 1), symmetry);
+            let starts = map.symmetric_starts(symmetry, option("--players", 2) as usize);
+            (map, starts)
+        }
+    };
+    let report = map.analyze_fairness(&starts);
+    print!("{}", report);
+    if !report.unfair().is_empty() {
+        std::process::exit(1);
+    }
+}
+
+// Prüft eine Karte vor dem Veröffentlichen und listet alle Warnungen mit ihren Tiles:
+// --validate KARTE
+fn run_validate(args: &[String]) {
+    let path = match string_option(args, "--validate").filter(|p| !p.starts_with("--")) {
+        Some(path) => path,
+        None => {
+            eprintln!("Usage: --validate MAP");
+            std::process::exit(1);
+        }
+    };
+    let s4 = match S4Map::load_with_resources(path) {
+        Ok(s4) => s4,
+        Err(e) => {
+            eprintln!("Can't load {}: {}", path, e);
+            std::process::exit(1);
+        }
+    };
+    let report = s4.map.validate(&s4.starts);
+    print!("{}", report);
+    if !report.warnings.is_empty() {
+        std::process::exit(1);
+    }
+}
+
+// Prüft das Lesen der Originalgrafiken einer Installation. --export schreibt dabei
+// jedes Bild als PNG, um die Nummern für res/data/gfx.ron zu finden:
+// --gfx-check INSTALLATION [--export VERZEICHNIS]
+fn run_gfx_check(args: &[String]) {
+    let install = match string_option(args, "--gfx-check").filter(|dir| !dir.starts_with("--")) {
+        Some(install) => install,
+        None => {
+            eprintln!("Usage: --gfx-check INSTALLATION [--export DIR]");
+            std::process::exit(1);
+        }
+    };
+    let mut failed = false;
+    let export = string_option(args, "--export").map(std::path::Path::new);
+    let dir = std::path::Path::new(install).join(types::gfx::GFX_DIR);
+    for number in 0..=MAX_GFX_FILE {
+        let mut images = Vec::new();
+        if dir.join(format!("{}.gfx", number)).exists() {
+            let archive = match GfxArchive::open(&dir, number) {
+                Ok(archive) => archive,
+                Err(e) => {
+                    println!("{}.gfx: {}", number, e);
+                    failed = true;
+                    continue;
+                }
+            };
+            let mut broken = 0;
+            for i in 0..archive.len() {
+                match archive.image(i) {
+                    Ok(image) => images.push((format!("{}", i), image)),
+                    Err(_) => broken += 1,
+                }
+            }
+            println!(
+                "{}.gfx: {} images, {} unreadable, {} jobs",
+                number,
+                archive.len(),
+                broken,
+                archive.job_count()
+            );
+            failed |= broken > 0;
+        }
+        let textured = dir.join(format!("{}.gh6", number)).exists()
+            | dir.join(format!("{}.gh5", number)).exists();
+        if textured {
+            match types::gfx::load_textures(&dir, number) {
+                Ok(textures) => {
+                    println!("{}.gh6: {} textures", number, textures.len());
+                    images.extend(
+                        textures
+                            .into_iter()
+                            .enumerate()
+                            .map(|(i, texture)| (format!("texture_{}", i), texture)),
+                    );
+                }
+                Err(e) => {
+                    println!("{}.gh6: {}", number, e);
+                    failed = true;
+                }
+            }
+        }
+        if let Some(export) = export.filter(|_| !images.is_empty()) {
+            let out = export.join(format!("{}", number));
+            let written = std::fs::create_dir_all(&out).and_then(|_| {
+                images.iter().try_for_each(|(name, image)| {
+                    let pixels: Vec<u8> = image.pixels.iter().flatten().copied().collect();
+                    image::save_buffer(
+                        out.join(format!("{}.png", name)),
+                        &pixels,
+                        image.width as u32,
+                        image.height as u32,
+                        image::RGBA(8),
+                    )
+                })
+            });
+            if let Err(e) = written {
+                println!("Can't export to {}: {}", out.display(), e);
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        std::process::exit(1);
+    }
+}
+
+// --mods VERZEICHNIS ersetzt das Standardverzeichnis für Mods
+fn asset_manager(args: &[String]) -> Assets {
+    Assets::new(string_option(args, "--mods").map(std::path::PathBuf::from))
+}
+
+fn load_production(args: &[String]) -> ProductionDefs {
+    match ProductionDefs::load(&mut asset_manager(args)) {
+        Ok(production) => production,
+        Err(e) => {
+            eprintln!("Can't load production data: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+// --symmetry rot2, mirror2, rot3, mirror4, rot6 oder nur die Zahl der Teile
+fn symmetry_option(args: &[String]) -> Symmetry {
+    match string_option(args, "--symmetry") {
+        Some(name) => Symmetry::parse(name).unwrap_or_else(|| {
+            let names: Vec<&str> = SYMMETRIES.iter().map(|s| s.name()).collect();
+            eprintln!(
+                "Unknown symmetry {}, use one of {} or 2, 3, 4, 6",
+                name,
+                names.join(", ")
+            );
+            std::process::exit(1);
+        }),
+        None => Symmetry::None,
+    }
+}
+
+fn number_option(args: &[String], name: &str) -> Option<u64> {
+    string_option(args, name).and_then(|v| v.parse().ok())
+}
+
+fn string_option<'a>(args: &'a [String], name: &str) -> Option<&'a str> {
+    args.iter()
+        .position(|a| a == name)
+        .and_then(|i| args.get(i + 1))
+        .map(|v| v.as_str())
+}
+
+// Ohne --seed jedes Mal eine andere Karte
+fn time_seed() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(1, |d| d.as_secs())
 }
 
 pub mod types;
 
 use crate::types::{
+    ai::MatchReport,
+    assets::{self, Assets},
+    building::{BuildingType, BUILDING_TYPES},
     camera_controller::CameraController,
+    command::Command,
     drawable::{DrawParameter, Drawable},
+    editor::{Brush, MapEditor, EDITOR_FILE, EDITOR_MAP_SIZE, TOOL_BRUSHES},
+    gfx::{Atlas, GfxArchive, GfxManifest, SpriteSheet},
+    hextile::TILE_TYPES,
     map::Map,
+    net::{Host, LockstepClient},
+    player::PlayerId,
+    production::ProductionDefs,
+    replay::{GameSetup, MapSource, Playback, Replay},
+    road::RoadNetwork,
+    s4map::S4Map,
+    save::{CameraPosition, SaveGame, AUTOSAVE_FILE, AUTOSAVE_MINUTES, QUICKSAVE_FILE},
+    symmetry::{Symmetry, SYMMETRIES},
+    world::World,
 };
 use coffee::{
-    graphics::{
-        self, Color, Frame, HorizontalAlignment, Mesh, VerticalAlignment, Window, WindowSettings,
-    },
-    input::KeyboardAndMouse,
-    load::{loading_screen::ProgressBar, Task},
-    ui::{self, button, Align, Button, Column, Element, Justify, Renderer, Text, UserInterface},
+    graphics::{self, Color, Frame, Mesh, Window, WindowSettings},
+    input::{keyboard::KeyCode, KeyboardAndMouse},
+    load::{loading_screen::ProgressBar, Join, Task},
+    ui::{button, Button, Column, Element, Renderer, Row, Text, UserInterface},
     Game, Result, Timer,
 };
-use std::time;
+use std::{
+    cell::RefCell,
+    rc::Rc,
+    time::{Duration, Instant},
+};
 
 pub const FIELDWIDTH: u16 = 17u16;
 pub const FIELDHEIGHT: u16 = 8u16;
 
+// Der Spieler an diesem Rechner
+pub const LOCAL_PLAYER: PlayerId = 0;
+
+// Kartengröße im Netzwerkspiel
+pub const NET_MAP_SIZE: u16 = 40;
+
+// Hierhin speichert F9 die Wiederholung des laufenden Spiels
+pub const REPLAY_DIR: &str = "replays";
+
+// Pfeil links/rechts springt beim Abspielen so weit (30 Sekunden)
+pub const SEEK_TICKS: u64 = 600;
+
+// Schnellster Vorlauf beim Abspielen, in Ticks pro Update
+pub const MAX_PLAYBACK_SPEED: u64 = 32;
+
+// So weit reichen die Nummern im Gfx-Verzeichnis des Originals
+pub const MAX_GFX_FILE: u16 = 50;
+
+// So oft wird mit --hot-reload nach geänderten Assets gesucht
+pub const HOT_RELOAD_INTERVAL: Duration = Duration::from_secs(1);
+
+fn load_image(assets: &mut Assets, gpu: &mut graphics::Gpu) -> Result<graphics::Image> {
+    let data = assets.read(assets::EXAMPLE_IMAGE)?;
+    graphics::Image::from_image(gpu, &image::load_from_memory(&data)?)
+}
+
+// None, wenn das Manifest keine Bilder nennt
+fn load_sprites(
+    assets: &mut Assets,
+    gpu: &mut graphics::Gpu,
+    dir: &std::path::Path,
+) -> Result<Option<SpriteSheet>> {
+    let atlas = Atlas::build(dir, &GfxManifest::load(assets)?)?;
+    if atlas.sprites.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(SpriteSheet::upload(gpu, atlas)?))
+}
+
+// Was die Ladeschritte nacheinander zusammentragen, bis das Spiel daraus entsteht
+#[derive(Default)]
+struct Loading {
+    assets: Option<Assets>,
+    production: Option<ProductionDefs>,
+    image: Option<graphics::Image>,
+    sprites: Option<SpriteSheet>,
+    s4_dir: Option<std::path::PathBuf>,
+    replay: Option<Replay>,
+    setup: Option<GameSetup>,
+    net: Option<LockstepClient>,
+    edited: Option<(MapEditor, Map)>,
+    game: Option<MyGame>,
+}
+
+// Ein Schritt im Ladebildschirm. Geht er schief, sagt die Meldung, wobei.
+fn load_step<F>(loading: &Rc<RefCell<Loading>>, name: &'static str, step: F) -> Task<()>
+where
+    F: 'static + FnOnce(&mut Loading, &mut graphics::Gpu) -> Result<()>,
+{
+    let loading = Rc::clone(loading);
+    Task::using_gpu(move |gpu| {
+        step(&mut loading.borrow_mut(), gpu).map_err(|e| {
+            coffee::Error::IO(std::io::Error::other(format!(
+                "{} failed: {}",
+                name,
+                error_message(&e)
+            )))
+        })
+    })
+}
+
+// Ohne das "IO error: " davor, das coffee jedem Dateifehler voranstellt
+fn error_message(e: &coffee::Error) -> String {
+    match e {
+        coffee::Error::IO(e) => e.to_string(),
+        e => e.to_string(),
+    }
+}
+
 pub fn run_game() -> Result<()> {
     <MyGame as UserInterface>::run(WindowSettings {
         title: String::from("A caffeinated game"),
@@ -38,13 +478,31 @@ pub fn run_game() -> Result<()> {
 
 pub struct MyGame {
     // Your game state and assets go here...
-    image: graphics::Image,
-    value: i32,
-    increment_button: button::State,
-    decrement_button: button::State,
-    map: Map,
-    last_update: time::Instant,
-    interval: time::Duration,
+    _image: graphics::Image,
+    // Nur mit --s4-dir, sonst wird alles mit einfachen Formen gemalt
+    sprites: Option<SpriteSheet>,
+    assets: Assets,
+    s4_dir: Option<std::path::PathBuf>,
+    // Nur mit --hot-reload: wann zuletzt nach geänderten Assets gesucht wurde
+    last_reload: Option<Instant>,
+    building_buttons: Vec<button::State>,
+    road_button: button::State,
+    demolish_button: button::State,
+    attack_button: button::State,
+    cancel_button: button::State,
+    resources_button: button::State,
+    world: World,
+    // Woraus die Welt entstanden ist, für die Wiederholung
+    setup: GameSetup,
+    // Nur beim Abspielen einer Wiederholung
+    playback: Option<PlaybackController>,
+    // Nur im Netzwerkspiel, dann kommen alle Befehle über den Host
+    net: Option<LockstepClient>,
+    // Nur im Karteneditor, dann läuft die Simulation nicht
+    editor: Option<EditorController>,
+    local_player: PlayerId,
+    // 0 heißt kein automatisches Speichern
+    autosave_ticks: u64,
     lmb_down: bool,
     rmb_down: bool,
     player: PlayerInstanceController,
@@ -53,47 +511,534 @@ pub struct MyGame {
 pub struct PlayerInstanceController {
     camera: CameraController,
     input: InputController,
+    tool: Tool,
+    // Straße, die gerade gezeichnet wird
+    road_path: Vec<(u16, u16)>,
+    // Rohstoff-Ansicht an/aus
+    show_resources: bool,
+}
+
+pub struct EditorController {
+    editor: MapEditor,
+    terrain_buttons: Vec<button::State>,
+    brush_buttons: Vec<button::State>,
+    smaller_button: button::State,
+    larger_button: button::State,
+    undo_button: button::State,
+    redo_button: button::State,
+    check_button: button::State,
+    save_button: button::State,
+}
+
+impl EditorController {
+    fn new(editor: MapEditor) -> Self {
+        EditorController {
+            editor,
+            terrain_buttons: TILE_TYPES.iter().map(|_| button::State::new()).collect(),
+            brush_buttons: TOOL_BRUSHES.iter().map(|_| button::State::new()).collect(),
+            smaller_button: button::State::new(),
+            larger_button: button::State::new(),
+            undo_button: button::State::new(),
+            redo_button: button::State::new(),
+            check_button: button::State::new(),
+            save_button: button::State::new(),
+        }
+    }
+}
+
+pub struct PlaybackController {
+    playback: Playback,
+    paused: bool,
+    // Ticks pro Update
+    speed: u64,
 }
 
 pub struct InputController {
     last_xpos: u16,
     last_ypos: u16,
+    hover: Option<(u16, u16)>,
 }
 
-const TARGET_FPS: u16 = 100;
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Tool {
+    None,
+    PlaceBuilding(BuildingType),
+    BuildRoad,
+    Demolish,
+    Attack,
+}
+
+impl MyGame {
+    fn select_tool(&mut self, tool: Tool) {
+        self.player.tool = tool;
+        self.player.road_path.clear();
+    }
+
+    // Prüft gleich, damit der Spieler sofort Rückmeldung bekommt. Ausgeführt wird im
+    // nächsten Tick.
+    fn send(&mut self, command: Command) -> bool {
+        match self.world.check_command(self.local_player, &command) {
+            Ok(()) => {
+                match self.net.as_mut() {
+                    Some(net) => net.queue(command),
+                    None => self.world.queue_command(self.local_player, command),
+                }
+                true
+            }
+            Err(e) => {
+                println!("Can't do {:?}: {:?}", command, e);
+                false
+            }
+        }
+    }
+
+    // Speichert den ganzen Stand der Simulation mit Kamera, geladen geht es genau hier weiter
+    fn save_game(&self, path: &str) {
+        let game = SaveGame {
+            setup: self.setup.clone(),
+            local_player: self.local_player,
+            camera: CameraPosition {
+                x: self.player.camera.cameraoffset_x,
+                y: self.player.camera.cameraoffset_y,
+            },
+            world: self.world.clone(),
+        };
+        match game.save(path) {
+            Ok(()) => println!("Game saved to {}", path),
+            Err(e) => println!("Can't save game: {}", e),
+        }
+    }
+
+    // Geht etwas schief, läuft das alte Spiel weiter
+    fn load_game(&mut self, path: &str) {
+        match SaveGame::load(path, self.world.production.clone()) {
+            Ok(game) => {
+                self.resume(game);
+                println!("Game loaded from {}", path);
+            }
+            Err(e) => println!("Can't load {}: {}", path, e),
+        }
+    }
+
+    fn resume(&mut self, game: SaveGame) {
+        self.world = game.world;
+        self.setup = game.setup;
+        self.local_player = game.local_player;
+        self.player.camera.cameraoffset_x = game.camera.x;
+        self.player.camera.cameraoffset_y = game.camera.y;
+        self.select_tool(Tool::None);
+    }
+
+    // Lädt geänderte Assets neu. Die Produktionsdaten nur im Spiel allein, sonst
+    // rechnen die Mitspieler und die Wiederholung nicht mehr dasselbe.
+    fn reload_assets(&mut self, window: &mut Window) {
+        match self.last_reload {
+            Some(last) if last.elapsed() >= HOT_RELOAD_INTERVAL => {}
+            _ => return,
+        }
+        self.last_reload = Some(Instant::now());
+        let local = self.playback.is_none() & self.net.is_none();
+        for name in self.assets.changed() {
+            let result = match name.as_str() {
+                assets::EXAMPLE_IMAGE => {
+                    load_image(&mut self.assets, window.gpu()).map(|image| self._image = image)
+                }
+                assets::PRODUCTION if local => ProductionDefs::load(&mut self.assets)
+                    .map(|production| self.world.production = production)
+                    .map_err(coffee::Error::from),
+                assets::GFX_MANIFEST => match &self.s4_dir {
+                    Some(dir) => load_sprites(&mut self.assets, window.gpu(), dir)
+                        .map(|sprites| self.sprites = sprites),
+                    None => Ok(()),
+                },
+                _ => continue,
+            };
+            match result {
+                Ok(()) => println!("Reloaded {}", name),
+                Err(e) => println!("Can't reload {}: {}", name, e),
+            }
+        }
+    }
+
+    // Speichert alle Befehle bis jetzt, damit sich das Spiel nachstellen lässt
+    fn save_replay(&self) {
+        let path = format!("{}/replay_{}.ron", REPLAY_DIR, time_seed());
+        let result = std::fs::create_dir_all(REPLAY_DIR)
+            .and_then(|_| Replay::record(&self.setup, &self.world).save(&path));
+        match result {
+            Ok(()) => println!("Replay saved to {}", path),
+            Err(e) => println!("Can't save replay: {}", e),
+        }
+    }
+
+    // Leertaste hält an, Pfeil hoch/runter ändert das Tempo, links/rechts springt
+    fn control_playback(&mut self, keyboard: &coffee::input::keyboard::Keyboard) {
+        let control = match self.playback.as_mut() {
+            Some(control) => control,
+            None => return,
+        };
+        if keyboard.was_key_released(KeyCode::Space) {
+            control.paused = !control.paused;
+        }
+        if keyboard.was_key_released(KeyCode::Up) {
+            control.speed = (control.speed * 2).min(MAX_PLAYBACK_SPEED);
+        }
+        if keyboard.was_key_released(KeyCode::Down) {
+            control.speed = (control.speed / 2).max(1);
+        }
+        if keyboard.was_key_released(KeyCode::Left) {
+            let target = self.world.tick.saturating_sub(SEEK_TICKS);
+            control.playback.seek(&mut self.world, target);
+        }
+        if keyboard.was_key_released(KeyCode::Right) {
+            let target = self.world.tick + SEEK_TICKS;
+            control.playback.seek(&mut self.world, target);
+        }
+    }
+
+    // Speichert trotz Warnungen, zeigt sie aber gleich mit an
+    fn save_map(&mut self) {
+        if let Some(control) = &self.editor {
+            match control.editor.save(&self.world.map) {
+                Ok(()) => println!("Map saved to {}", control.editor.path),
+                Err(e) => println!("Can't save map: {}", e),
+            }
+        }
+        self.check_map();
+    }
+
+    fn check_map(&mut self) {
+        if let Some(control) = self.editor.as_mut() {
+            print!("{}", control.editor.check(&self.world.map));
+        }
+    }
+
+    // [ und ] ändern die Pinselgröße, F5 speichert die Karte, F6 prüft sie. Strg+Z nimmt
+    // zurück, Strg+Y oder Strg+Umschalt+Z stellt wieder her.
+    fn control_editor(&mut self, keyboard: &coffee::input::keyboard::Keyboard) {
+        let control = match self.editor.as_mut() {
+            Some(control) => control,
+            None => return,
+        };
+        let ctrl =
+            keyboard.is_key_pressed(KeyCode::LControl) | keyboard.is_key_pressed(KeyCode::RControl);
+        let shift =
+            keyboard.is_key_pressed(KeyCode::LShift) | keyboard.is_key_pressed(KeyCode::RShift);
+        if ctrl & keyboard.was_key_released(KeyCode::Z) & !shift {
+            control.editor.undo(&mut self.world.map);
+        }
+        if ctrl
+            & (keyboard.was_key_released(KeyCode::Y)
+                | (keyboard.was_key_released(KeyCode::Z) & shift))
+        {
+            control.editor.redo(&mut self.world.map);
+        }
+        if keyboard.was_key_released(KeyCode::LBracket) {
+            control.editor.resize_brush(-1);
+        }
+        if keyboard.was_key_released(KeyCode::RBracket) {
+            control.editor.resize_brush(1);
+        }
+        if keyboard.was_key_released(KeyCode::F5) {
+            self.save_map();
+        }
+        if keyboard.was_key_released(KeyCode::F6) {
+            self.check_map();
+        }
+    }
+
+    fn on_left_click(&mut self, x: u16, y: u16) {
+        // Eine Wiederholung nimmt keine Befehle an, im Editor wird nur gemalt
+        if self.playback.is_some() | self.editor.is_some() {
+            return;
+        }
+        match self.player.tool {
+            Tool::None => {}
+            Tool::PlaceBuilding(bt) => {
+                self.send(Command::PlaceBuilding {
+                    building_type: bt,
+                    x,
+                    y,
+                });
+            }
+            Tool::BuildRoad => {
+                if self.player.road_path.is_empty() {
+                    // Start an einer vorhandenen Flagge oder eine neue setzen
+                    if self.world.map.tile(x, y).flag.is_some()
+                        || self.send(Command::PlaceFlag { x, y })
+                    {
+                        self.player.road_path.push((x, y));
+                    }
+                } else if self.player.road_path.last() == Some(&(x, y)) {
+                    let path = self.player.road_path.clone();
+                    // Gleich an der neuen Flagge weiterbauen
+                    if self.send(Command::BuildRoad { path }) {
+                        self.player.road_path = vec![(x, y)];
+                    }
+                }
+            }
+            Tool::Demolish => {
+                let tile = self.world.map.tile(x, y);
+                let command = if let Some(building) = tile.building {
+                    Command::Demolish { building }
+                } else if let Some(flag) = tile.flag {
+                    Command::RemoveFlag { flag }
+                } else if let Some(road) = tile.road {
+                    Command::RemoveRoad { road }
+                } else {
+                    return;
+                };
+                self.send(command);
+            }
+            // Alle verfügbaren Soldaten auf das Gebäude unter der Maus
+            Tool::Attack => {
+                if let Some(target) = self.world.map.tile(x, y).building {
+                    self.send(Command::Attack {
+                        target,
+                        count: u16::MAX,
+                    });
+                }
+            }
+        }
+    }
+}
 
 // https://docs.rs/coffee/0.4.1/coffee/trait.Game.html
-impl<'a> Game for MyGame {
-    const TICKS_PER_SECOND: u16 = 200;
+impl Game for MyGame {
+    // Simulationsschritte pro Sekunde, alle Zeiten in der Simulation sind in Ticks
+    const TICKS_PER_SECOND: u16 = 20;
     type Input = KeyboardAndMouse;
     type LoadingScreen = ProgressBar; // No loading screen
 
     fn load(_window: &Window) -> Task<MyGame> {
-        // Load your game assets here. Check out the `load` module!
-        graphics::Image::load("res\\img\\example.png").map(|image| MyGame {
-            image,
-            value: 0,
-            increment_button: button::State::new(),
-            decrement_button: button::State::new(),
-            map: Map::new(10, 30),
-            last_update: time::Instant::now(),
-            interval: time::Duration::from_millis((1000 / TARGET_FPS).into()),
-            lmb_down: false,
-            rmb_down: false,
-            player: PlayerInstanceController {
-                camera: CameraController {
-                    cameraoffset_x: 0,
-                    cameraoffset_y: 0,
-                    window_height: 0,
-                    window_width: 0,
-                    mesh: Mesh::new(),
-                },
-                input: InputController {
-                    last_xpos: 0,
-                    last_ypos: 0,
-                },
-            },
-        })
+        // --replay DATEI spielt eine Wiederholung ab, --seed N wählt die Karte.
+        // --host ADRESSE [--players N] [--symmetry S] macht ein Netzwerkspiel auf,
+        // --join ADRESSE tritt bei.
+        // --load DATEI setzt einen Spielstand fort, --autosave MINUTEN (0 = aus).
+        // --map DATEI spielt auf einer Karte von Siedler 4, allein oder als Gastgeber.
+        // --s4-dir INSTALLATION malt mit den Grafiken des Originals.
+        // --mods VERZEICHNIS sucht Assets zuerst dort, --hot-reload lädt geänderte neu.
+        // --edit [DATEI] [--size N] öffnet den Karteneditor, ohne Datei eine neue Karte.
+        let args: Vec<String> = std::env::args().collect();
+        let mut assets = asset_manager(&args);
+        let hot_reload = args.iter().any(|a| a == "--hot-reload");
+        let s4_dir = string_option(&args, "--s4-dir").map(std::path::PathBuf::from);
+        let map_path = string_option(&args, "--map").map(String::from);
+        let load_path = string_option(&args, "--load").map(String::from);
+        let autosave_minutes = number_option(&args, "--autosave").unwrap_or(AUTOSAVE_MINUTES);
+        let replay_path = string_option(&args, "--replay").map(String::from);
+        let seed = number_option(&args, "--seed").unwrap_or_else(time_seed);
+        let host_addr = string_option(&args, "--host").map(String::from);
+        let join_addr = string_option(&args, "--join").map(String::from);
+        let players = number_option(&args, "--players").unwrap_or(2) as usize;
+        let symmetry = symmetry_option(&args);
+        let is_host = host_addr.is_some();
+        let edit_path = args.iter().any(|a| a == "--edit").then(|| {
+            string_option(&args, "--edit")
+                .filter(|path| !path.starts_with("--"))
+                .unwrap_or(EDITOR_FILE)
+                .to_string()
+        });
+        let edit_size = number_option(&args, "--size").map_or(EDITOR_MAP_SIZE, |n| n as u16);
+        // Jeder Schritt legt hier ab, was die späteren brauchen
+        let loading = Rc::new(RefCell::new(Loading::default()));
+        (
+            Task::stage(
+                "Loading data definitions...",
+                load_step(&loading, "Loading data definitions", move |loading, _| {
+                    loading.production = Some(ProductionDefs::load(&mut assets)?);
+                    loading.assets = Some(assets);
+                    Ok(())
+                }),
+            ),
+            Task::stage(
+                "Loading textures...",
+                (
+                    load_step(&loading, "Loading images", |loading, gpu| {
+                        let assets = loading.assets.as_mut().unwrap();
+                        loading.image = Some(load_image(assets, gpu)?);
+                        Ok(())
+                    }),
+                    load_step(
+                        &loading,
+                        "Loading Settlers 4 graphics",
+                        move |loading, gpu| {
+                            if let Some(dir) = &s4_dir {
+                                let assets = loading.assets.as_mut().unwrap();
+                                loading.sprites = load_sprites(assets, gpu, dir)?;
+                            }
+                            loading.s4_dir = s4_dir;
+                            Ok(())
+                        },
+                    ),
+                )
+                    .join(),
+            ),
+            Task::stage(
+                "Preparing map...",
+                (
+                    load_step(&loading, "Loading replay", move |loading, _| {
+                        if let Some(path) = replay_path {
+                            loading.replay = Some(Replay::load(path)?);
+                        }
+                        Ok(())
+                    }),
+                    load_step(&loading, "Loading map", move |loading, _| {
+                        if let Some(path) = edit_path {
+                            loading.edited = Some(MapEditor::open(&path, edit_size)?);
+                        }
+                        if let Some(path) = &map_path {
+                            let players = if is_host { players } else { 1 };
+                            loading.setup = Some(GameSetup::s4_file(path, players)?);
+                        }
+                        Ok(())
+                    }),
+                    // Wartet, bis alle Spieler da sind
+                    load_step(&loading, "Joining network game", move |loading, _| {
+                        loading.net = match (host_addr, join_addr) {
+                            (Some(addr), _) => {
+                                let setup = loading.setup.take().unwrap_or_else(|| {
+                                    GameSetup::generated(
+                                        NET_MAP_SIZE,
+                                        NET_MAP_SIZE,
+                                        seed,
+                                        players,
+                                        symmetry,
+                                    )
+                                });
+                                let host = Host::bind(addr, setup)?;
+                                let port = host.local_addr()?.port();
+                                std::thread::spawn(move || host.run());
+                                Some(LockstepClient::join(("127.0.0.1", port))?)
+                            }
+                            (None, Some(addr)) => Some(LockstepClient::join(addr)?),
+                            (None, None) => None,
+                        };
+                        Ok(())
+                    }),
+                )
+                    .join(),
+            ),
+            Task::stage(
+                "Starting simulation...",
+                load_step(&loading, "Starting the game", move |loading, _| {
+                    let production = loading.production.take().unwrap();
+                    let setup = match (&loading.replay, &loading.net, loading.setup.take()) {
+                        (Some(replay), _, _) => replay.setup.clone(),
+                        (None, Some(net), _) => net.setup.clone(),
+                        (None, None, Some(setup)) => setup,
+                        (None, None, None) => GameSetup {
+                            map: MapSource::Generated {
+                                width: 10,
+                                height: 30,
+                                seed,
+                                symmetry: Symmetry::None,
+                            },
+                            starts: vec![(LOCAL_PLAYER, 5, 10)],
+                        },
+                    };
+                    // Im Editor eine Welt ohne Spieler, nur zum Malen
+                    let (editor, map) = match loading.edited.take() {
+                        Some((editor, map)) => (Some(EditorController::new(editor)), Some(map)),
+                        None => (None, None),
+                    };
+                    let save = match load_path.filter(|_| editor.is_none()) {
+                        Some(path) => Some(SaveGame::load(path, production.clone())?),
+                        None => None,
+                    };
+                    // Ohne Spielstand entsteht die Welt neu, dafür muss die Karte da sein
+                    let world = match (map, &save) {
+                        (Some(map), _) => World::new(map, production),
+                        (None, Some(save)) => save.world.clone(),
+                        (None, None) => setup.create_world(production)?,
+                    };
+                    let show_resources = editor.is_some();
+                    let net = loading.net.take();
+                    let mut game = MyGame {
+                        _image: loading.image.take().unwrap(),
+                        sprites: loading.sprites.take(),
+                        assets: loading.assets.take().unwrap(),
+                        s4_dir: loading.s4_dir.take(),
+                        last_reload: hot_reload.then(Instant::now),
+                        building_buttons: BUILDING_TYPES
+                            .iter()
+                            .map(|_| button::State::new())
+                            .collect(),
+                        road_button: button::State::new(),
+                        demolish_button: button::State::new(),
+                        attack_button: button::State::new(),
+                        cancel_button: button::State::new(),
+                        resources_button: button::State::new(),
+                        world,
+                        setup,
+                        playback: loading.replay.take().map(|replay| PlaybackController {
+                            playback: Playback::new(replay),
+                            paused: false,
+                            speed: 1,
+                        }),
+                        local_player: net.as_ref().map_or(LOCAL_PLAYER, |net| net.player),
+                        net,
+                        editor,
+                        autosave_ticks: autosave_minutes * 60 * Self::TICKS_PER_SECOND as u64,
+                        lmb_down: false,
+                        rmb_down: false,
+                        player: PlayerInstanceController {
+                            camera: CameraController {
+                                cameraoffset_x: 0,
+                                cameraoffset_y: 0,
+                                window_height: 0,
+                                window_width: 0,
+                                mesh: Mesh::new(),
+                            },
+                            input: InputController {
+                                last_xpos: 0,
+                                last_ypos: 0,
+                                hover: None,
+                            },
+                            tool: Tool::None,
+                            road_path: Vec::new(),
+                            show_resources,
+                        },
+                    };
+                    if let Some(save) = save {
+                        game.resume(save);
+                    }
+                    loading.game = Some(game);
+                    Ok(())
+                }),
+            ),
+        )
+            .join()
+            .map(move |_| loading.borrow_mut().game.take().unwrap())
+    }
+
+    fn update(&mut self, _window: &Window) {
+        if self.editor.is_some() {
+            return;
+        }
+        if let Some(control) = self.playback.as_mut() {
+            if !control.paused {
+                control
+                    .playback
+                    .fast_forward(&mut self.world, control.speed);
+            }
+            return;
+        }
+        if let Some(net) = self.net.as_mut() {
+            // Ohne die Eingaben aller Spieler bleibt die Welt stehen
+            if let Err(e) = net.advance(&mut self.world) {
+                println!("Network game ended: {}", e);
+                self.net = None;
+            }
+            return;
+        }
+        self.world.tick();
+        for (_, command, e) in self.world.rejected.drain(..) {
+            println!("{:?} failed: {:?}", command, e);
+        }
+        if (self.autosave_ticks > 0) & self.world.tick.is_multiple_of(self.autosave_ticks) {
+            self.save_game(AUTOSAVE_FILE);
+        }
     }
 
     fn draw(&mut self, frame: &mut Frame, _timer: &Timer) {
@@ -108,28 +1053,92 @@ impl<'a> Game for MyGame {
         let mut param = DrawParameter {
             camera: &mut self.player.camera,
             frame,
+            // Beim Abspielen und im Editor ohne Nebel
+            viewer: match (&self.playback, &self.editor) {
+                (None, None) => Some(self.local_player),
+                _ => None,
+            },
         };
-        self.map.draw(&mut param);
+        self.world.draw(&mut param);
+        if self.player.show_resources {
+            self.world.draw_resource_overlay(&mut param);
+        }
+        if let Some(control) = &self.editor {
+            control
+                .editor
+                .draw(&mut param, &self.world.map, self.player.input.hover);
+        }
+        if let (Tool::PlaceBuilding(bt), Some((x, y))) = (self.player.tool, self.player.input.hover)
+        {
+            self.world
+                .draw_ghost(&mut param, self.local_player, bt, x, y);
+        }
+        if self.player.tool == Tool::BuildRoad {
+            let path = self.player.road_path.clone();
+            self.world
+                .draw_road_ghost(&mut param, self.local_player, &path);
+        }
+        if let Some(sheet) = self.sprites.as_mut() {
+            self.world.draw_sprites(&mut param, sheet);
+        }
         self.player.camera.mesh.draw(&mut frame.as_target());
+        if let Some(sheet) = &self.sprites {
+            sheet.batch.draw(&mut frame.as_target());
+        }
     }
 
     fn interact(&mut self, _input: &mut Self::Input, _window: &mut Window) {
+        if _input.keyboard().was_key_released(KeyCode::Escape) {
+            self.select_tool(Tool::None);
+        }
+        if _input.keyboard().was_key_released(KeyCode::R) {
+            self.player.show_resources = !self.player.show_resources;
+        }
+        // Speichern und Laden nur im Spiel allein
+        let local = self.playback.is_none() & self.net.is_none() & self.editor.is_none();
+        if _input.keyboard().was_key_released(KeyCode::F5) & local {
+            self.save_game(QUICKSAVE_FILE);
+        }
+        if _input.keyboard().was_key_released(KeyCode::F8) & local {
+            self.load_game(QUICKSAVE_FILE);
+        }
+        if _input.keyboard().was_key_released(KeyCode::F9) & local {
+            self.save_replay();
+        }
+        self.control_editor(_input.keyboard());
+        // Auch wenn die Taste über der Leiste losgelassen wurde
+        let painting = _input
+            .mouse()
+            .is_button_pressed(coffee::input::mouse::Button::Left);
+        if let Some(control) = self.editor.as_mut().filter(|_| !painting) {
+            control.editor.end_stroke(&self.world.map);
+        }
+        self.control_playback(_input.keyboard());
+        self.reload_assets(_window);
+        self.player.input.hover = None;
         if _input.mouse().is_cursor_within_window() & !_input.mouse().is_cursor_taken() {
+            let (hx, hy) = self.world.map.get_tile_pos_from_coords(
+                _input.mouse().cursor_position(),
+                self.player.camera.cameraoffset_x,
+                self.player.camera.cameraoffset_y,
+            );
+            self.player.input.hover = Some((hx, hy));
+            // Straße folgt der Maus über die Nachbar-Tiles
+            if (self.player.tool == Tool::BuildRoad) & !self.player.road_path.is_empty() {
+                RoadNetwork::extend_path(&self.world.map, &mut self.player.road_path, hx, hy);
+            }
             if _input
                 .mouse()
                 .is_button_pressed(coffee::input::mouse::Button::Left)
             {
+                // Im Editor wird gemalt, solange die Taste gedrückt ist
+                if let Some(control) = self.editor.as_mut() {
+                    control.editor.paint(&mut self.world.map, hx, hy);
+                }
                 // Left Click
                 if !self.lmb_down {
                     self.lmb_down = true;
-                    let point = _input.mouse().cursor_position();
-                    self.map
-                        .get_tile_from_coords(
-                            point,
-                            self.player.camera.cameraoffset_x,
-                            self.player.camera.cameraoffset_y,
-                        )
-                        .randomize();
+                    self.on_left_click(hx, hy);
                 }
             } else {
                 self.lmb_down = false;
@@ -153,14 +1162,13 @@ impl<'a> Game for MyGame {
                 // Check, dass die Karte nicht nach rechts rausläuft
                 if (self.player.camera.cameraoffset_x + xdiv) <= 0 {
                     // Check, das die Karte nicht nach links rausläuft (größe + offset + xdiv > Fenster)
-                    if (((self.map.width + (self.map.height as f32 * 0.5f32) as u16)
+                    if (((self.world.map.width + (self.world.map.height as f32 * 0.5f32) as u16)
                         * crate::FIELDWIDTH) as i16
                         + self.player.camera.cameraoffset_x
                         + xdiv)
                         > _window.width() as i16
                     {
-                        self.player.camera.cameraoffset_x =
-                            self.player.camera.cameraoffset_x + xdiv;
+                        self.player.camera.cameraoffset_x += xdiv;
                     }
                 }
 
@@ -170,13 +1178,12 @@ impl<'a> Game for MyGame {
                 // Check, dass die Karte nicht nach unten rausläuft
                 if (self.player.camera.cameraoffset_y + ydiv) <= 0 {
                     // Check, dass die Karte nicht nach oben rausläuft
-                    if (((self.map.height as f32 + 0.5f32) * 20f32 * 0.75f32) as i16
+                    if (((self.world.map.height as f32 + 0.5f32) * 20f32 * 0.75f32) as i16
                         + self.player.camera.cameraoffset_y
                         + ydiv)
                         > _window.height() as i16
                     {
-                        self.player.camera.cameraoffset_y =
-                            self.player.camera.cameraoffset_y + ydiv;
+                        self.player.camera.cameraoffset_y += ydiv;
                     }
                 }
 
@@ -191,8 +1198,18 @@ impl<'a> Game for MyGame {
 
 #[derive(Debug, Clone, Copy)]
 pub enum Message {
-    IncrementPressed,
-    DecrementPressed,
+    SelectBuilding(BuildingType),
+    SelectRoadTool,
+    SelectDemolishTool,
+    SelectAttackTool,
+    CancelTool,
+    ToggleResources,
+    SelectBrush(Brush),
+    ResizeBrush(i32),
+    Undo,
+    Redo,
+    CheckMap,
+    SaveMap,
 }
 
 impl UserInterface for MyGame {
@@ -201,46 +1218,187 @@ impl UserInterface for MyGame {
 
     fn react(&mut self, message: Message, _window: &mut Window) {
         match message {
-            Message::IncrementPressed => {
-                self.value += 1;
+            Message::SelectBuilding(bt) => {
+                self.select_tool(Tool::PlaceBuilding(bt));
+            }
+            Message::SelectRoadTool => {
+                self.select_tool(Tool::BuildRoad);
+            }
+            Message::SelectDemolishTool => {
+                self.select_tool(Tool::Demolish);
+            }
+            Message::SelectAttackTool => {
+                self.select_tool(Tool::Attack);
+            }
+            Message::CancelTool => {
+                self.select_tool(Tool::None);
             }
-            Message::DecrementPressed => {
-                self.value -= 1;
+            Message::ToggleResources => {
+                self.player.show_resources = !self.player.show_resources;
             }
+            Message::SelectBrush(brush) => {
+                if let Some(control) = self.editor.as_mut() {
+                    control.editor.brush = brush;
+                }
+            }
+            Message::ResizeBrush(delta) => {
+                if let Some(control) = self.editor.as_mut() {
+                    control.editor.resize_brush(delta);
+                }
+            }
+            Message::Undo => {
+                if let Some(control) = self.editor.as_mut() {
+                    control.editor.undo(&mut self.world.map);
+                }
+            }
+            Message::Redo => {
+                if let Some(control) = self.editor.as_mut() {
+                    control.editor.redo(&mut self.world.map);
+                }
+            }
+            Message::CheckMap => self.check_map(),
+            Message::SaveMap => self.save_map(),
         }
     }
 
-    fn layout(&mut self, window: &Window) -> Element<Message> {
-        Column::new().into()
-
-        /*let text = format!(
-            "This is an image {} {}",
-            self.map.height.to_string(),
-            self.map.width.to_string()
-        );
-        Column::new()
-            .width(window.width() as u32)
-            .height(window.height() as u32)
-            .align_items(Align::Center)
-            .justify_content(Justify::Center)
-            .spacing(20)
+    fn layout(&mut self, _window: &Window) -> Element<'_, Message> {
+        if let Some(control) = self.editor.as_mut() {
+            return editor_layout(control);
+        }
+        // Bauleiste
+        let mut toolbar = Row::new().padding(5).spacing(5);
+        for (bt, state) in BUILDING_TYPES.iter().zip(self.building_buttons.iter_mut()) {
+            toolbar = toolbar.push(
+                Button::new(state, bt.name())
+                    .class(button::Class::Secondary)
+                    .on_press(Message::SelectBuilding(*bt)),
+            );
+        }
+        let tool_text = match self.player.tool {
+            Tool::None => String::from("-"),
+            Tool::PlaceBuilding(bt) => bt.name().to_string(),
+            Tool::BuildRoad => String::from("Road"),
+            Tool::Demolish => String::from("Demolish"),
+            Tool::Attack => String::from("Attack"),
+        };
+        let toolbar = toolbar
+            .push(Button::new(&mut self.road_button, "Road").on_press(Message::SelectRoadTool))
+            .push(
+                Button::new(&mut self.demolish_button, "Demolish")
+                    .class(button::Class::Secondary)
+                    .on_press(Message::SelectDemolishTool),
+            )
             .push(
-                Text::new(&text)
-                    .size(50)
-                    .height(60)
-                    .horizontal_alignment(HorizontalAlignment::Center)
-                    .vertical_alignment(VerticalAlignment::Center),
+                Button::new(&mut self.attack_button, "Attack")
+                    .class(button::Class::Secondary)
+                    .on_press(Message::SelectAttackTool),
             )
-            .push(ui::Image::new(&self.image).height(250))
-            .push(Button::new(&mut self.increment_button, "+").on_press(Message::IncrementPressed))
+            .push(Button::new(&mut self.cancel_button, "X").on_press(Message::CancelTool))
             .push(
-                Text::new(&self.value.to_string())
-                    .size(50)
-                    .height(60)
-                    .horizontal_alignment(HorizontalAlignment::Center)
-                    .vertical_alignment(VerticalAlignment::Center),
+                Button::new(&mut self.resources_button, "Resources")
+                    .class(button::Class::Secondary)
+                    .on_press(Message::ToggleResources),
             )
-            .push(Button::new(&mut self.decrement_button, "-").on_press(Message::DecrementPressed))
-            .into()*/
+            .push(Text::new(&tool_text).size(20));
+
+        // Lagerbestand: vorhanden (+ unterwegs ins Lager / - schon versprochen)
+        let mut stock_panel = Column::new().padding(5).spacing(2).push(
+            Text::new(&format!(
+                "Siedler: {}",
+                self.world.population(self.local_player)
+            ))
+            .size(16),
+        );
+        if let Some(desync) = self
+            .playback
+            .as_ref()
+            .and_then(|c| c.playback.desync.as_ref())
+        {
+            stock_panel = stock_panel.push(Text::new(&desync.to_string()).size(16));
+        }
+        for (good, count) in self.world.player_stock(self.local_player) {
+            stock_panel = stock_panel.push(
+                Text::new(&format!(
+                    "{}: {} (+{} / -{})",
+                    good.name(),
+                    count.stored,
+                    count.inbound,
+                    count.outbound
+                ))
+                .size(16),
+            );
+        }
+        Column::new().push(toolbar).push(stock_panel).into()
+    }
+}
+
+// Böden, dann die übrigen Pinsel, darunter Pinselgröße, Prüfen und Speichern
+fn editor_layout(control: &mut EditorController) -> Element<'_, Message> {
+    let brush = control.editor.brush;
+    let button = |state, b: Brush| {
+        Button::new(state, b.name())
+            .class(if b == brush {
+                button::Class::Primary
+            } else {
+                button::Class::Secondary
+            })
+            .on_press(Message::SelectBrush(b))
+    };
+    let mut terrain = Row::new().padding(5).spacing(5);
+    for (tt, state) in TILE_TYPES.iter().zip(control.terrain_buttons.iter_mut()) {
+        terrain = terrain.push(button(state, Brush::Terrain(*tt)));
+    }
+    let mut tools = Row::new().padding(5).spacing(5);
+    for (b, state) in TOOL_BRUSHES.iter().zip(control.brush_buttons.iter_mut()) {
+        tools = tools.push(button(state, *b));
+    }
+    let settings = Row::new()
+        .padding(5)
+        .spacing(5)
+        .push(Button::new(&mut control.smaller_button, "-").on_press(Message::ResizeBrush(-1)))
+        .push(Text::new(&format!("Brush {}", control.editor.radius)).size(20))
+        .push(Button::new(&mut control.larger_button, "+").on_press(Message::ResizeBrush(1)))
+        .push(history_button(
+            &mut control.undo_button,
+            "Undo",
+            control.editor.history.can_undo(),
+            Message::Undo,
+        ))
+        .push(history_button(
+            &mut control.redo_button,
+            "Redo",
+            control.editor.history.can_redo(),
+            Message::Redo,
+        ))
+        .push(Button::new(&mut control.check_button, "Check").on_press(Message::CheckMap))
+        .push(Button::new(&mut control.save_button, "Save").on_press(Message::SaveMap))
+        .push(
+            Text::new(&format!(
+                "{} players, {} warnings, {}",
+                control.editor.starts.len(),
+                control.editor.validation.warnings.len(),
+                control.editor.path
+            ))
+            .size(20),
+        );
+    Column::new()
+        .push(terrain)
+        .push(tools)
+        .push(settings)
+        .into()
+}
+
+// Ohne Schritt in dieser Richtung ausgegraut und ohne Wirkung
+fn history_button<'a>(
+    state: &'a mut button::State,
+    label: &str,
+    enabled: bool,
+    message: Message,
+) -> Button<'a, Message> {
+    let button = Button::new(state, label).class(button::Class::Secondary);
+    if enabled {
+        button.on_press(message)
+    } else {
+        button
     }
 }
@@ -0,0 +1,655 @@
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    fmt, io,
+};
+
+use super::{
+    building::{BuildingId, BuildingType},
+    command::{Command, CommandOutcome},
+    goods::Good,
+    hextile::MapObject,
+    map::Map,
+    military::ATTACK_RANGE,
+    player::PlayerId,
+    production::{ProductionDefs, WorkKind},
+    replay::{GameSetup, Replay},
+    resources::ResourceKind,
+    road::{FlagId, RoadNetwork},
+    settler::Job,
+    symmetry::Symmetry,
+    world::World,
+};
+
+// So oft denkt ein Computergegner nach
+pub const AI_THINK_TICKS: u64 = 40;
+
+// So oft prüft er, ob sich ein Angriff lohnt
+pub const AI_ATTACK_TICKS: u64 = 400;
+
+// Mehr Baustellen gleichzeitig fängt er nicht an
+pub const MAX_SITES: usize = 2;
+
+// Längste Straße, die beim Anschließen eines Gebäudes gesucht wird
+pub const MAX_ROAD_LENGTH: usize = 14;
+
+// So viele Soldaten mehr als der Verteidiger braucht ein Angriff
+pub const ATTACK_MARGIN: usize = 2;
+
+// Weniger freie Siedler, und es wird zuerst ein Wohnhaus gebaut
+pub const MIN_IDLE_SETTLERS: usize = 3;
+
+// Ab dieser Länge bekommt eine neue Straße Flaggen in der Mitte
+pub const ROAD_FLAG_LENGTH: usize = 6;
+
+// So viele Bauplätze werden pro Gebäude ausprobiert
+const MAX_CANDIDATES: usize = 8;
+
+// Wirtschaftsplan: Gebäude und wie viele es davon insgesamt geben soll, der Reihe nach
+const ECONOMY_PLAN: [(BuildingType, usize); 22] = [
+    (BuildingType::Woodcutter, 1),
+    (BuildingType::Residence, 1),
+    (BuildingType::Stonecutter, 1),
+    (BuildingType::Woodcutter, 2),
+    (BuildingType::Sawmill, 1),
+    (BuildingType::GuardHouse, 1),
+    (BuildingType::Fisher, 1),
+    (BuildingType::Woodcutter, 3),
+    (BuildingType::GuardHouse, 2),
+    (BuildingType::Farm, 1),
+    (BuildingType::Mill, 1),
+    (BuildingType::Bakery, 1),
+    (BuildingType::Stonecutter, 2),
+    (BuildingType::Residence, 2),
+    (BuildingType::CoalMine, 1),
+    (BuildingType::IronMine, 1),
+    (BuildingType::Tower, 1),
+    (BuildingType::Sawmill, 2),
+    (BuildingType::GuardHouse, 4),
+    (BuildingType::Residence, 3),
+    (BuildingType::Fisher, 2),
+    (BuildingType::Tower, 2),
+];
+
+// Regelbasierter Computergegner. Er benutzt nur die Befehle, die auch ein Mensch hat.
+pub struct AiPlayer {
+    pub player: PlayerId,
+    // Gebäude, für die zuletzt kein Platz war. Wächst das Gebiet, wird es neu versucht.
+    pub blocked: BTreeSet<BuildingType>,
+    pub territory: usize,
+}
+
+impl AiPlayer {
+    pub fn new(player: PlayerId) -> Self {
+        AiPlayer {
+            player,
+            blocked: BTreeSet::new(),
+            territory: 0,
+        }
+    }
+
+    pub fn think(&mut self, world: &mut World) {
+        if !world.tick.is_multiple_of(AI_THINK_TICKS) {
+            return;
+        }
+        if !world.buildings.values().any(|b| b.owner == self.player) {
+            return;
+        }
+        let territory = world.territory_size(self.player);
+        if territory != self.territory {
+            self.territory = territory;
+            self.blocked.clear();
+        }
+        if world.tick.is_multiple_of(AI_ATTACK_TICKS) {
+            self.attack(world);
+        }
+        let sites = world
+            .buildings
+            .values()
+            .filter(|b| (b.owner == self.player) & b.site().is_some())
+            .count();
+        if sites >= MAX_SITES {
+            return;
+        }
+        if let Some((bt, focus)) = self.next_building(world) {
+            if !self.build(world, bt, focus) {
+                self.blocked.insert(bt);
+            }
+        }
+    }
+
+    // Eigene Gebäude dieser Art, Baustellen mitgezählt
+    fn count(&self, world: &World, bt: BuildingType) -> usize {
+        world
+            .buildings
+            .values()
+            .filter(|b| (b.owner == self.player) & (b.building_type == bt))
+            .count()
+    }
+
+    fn stored(&self, world: &World, good: Good) -> u16 {
+        world.stock_count(self.player, good).stored
+    }
+
+    // Was als nächstes gebaut wird und wohin es zieht. Erst Verteidigung, dann
+    // Engpässe in den Ketten, dann der Plan und zuletzt Ausbreitung.
+    fn next_building(&self, world: &World) -> Option<(BuildingType, Option<(u16, u16)>)> {
+        let open = |bt: BuildingType| !self.blocked.contains(&bt);
+
+        if let Some(threat) = self.threat(world) {
+            let building_military = world.buildings.values().any(|b| {
+                (b.owner == self.player)
+                    & b.site().is_some()
+                    & b.building_type.territory_radius().is_some()
+            });
+            if !building_military & open(BuildingType::GuardHouse) {
+                return Some((BuildingType::GuardHouse, Some(threat)));
+            }
+        }
+
+        let count = |bt| self.count(world, bt);
+        let stored = |good| self.stored(world, good);
+        let balance = [
+            (
+                BuildingType::Sawmill,
+                (stored(Good::Log) >= 8)
+                    & (count(BuildingType::Sawmill) < count(BuildingType::Woodcutter)),
+            ),
+            (
+                BuildingType::Woodcutter,
+                (stored(Good::Board) < 8)
+                    & (stored(Good::Log) < 4)
+                    & (count(BuildingType::Woodcutter) < 2 * count(BuildingType::Sawmill) + 2),
+            ),
+            (
+                BuildingType::Mill,
+                (stored(Good::Grain) >= 6)
+                    & (count(BuildingType::Mill) < count(BuildingType::Farm)),
+            ),
+            (
+                BuildingType::Bakery,
+                (stored(Good::Flour) >= 6)
+                    & (count(BuildingType::Bakery) < count(BuildingType::Mill)),
+            ),
+            (
+                BuildingType::Residence,
+                self.idle_settlers(world) < MIN_IDLE_SETTLERS,
+            ),
+        ];
+        if let Some((bt, _)) = balance.iter().find(|(bt, needed)| *needed & open(*bt)) {
+            return Some((*bt, None));
+        }
+
+        if let Some((bt, _)) = ECONOMY_PLAN
+            .iter()
+            .find(|(bt, n)| (count(*bt) < *n) & open(*bt))
+        {
+            return Some((*bt, None));
+        }
+        if open(BuildingType::GuardHouse) {
+            return Some((BuildingType::GuardHouse, None));
+        }
+        None
+    }
+
+    fn idle_settlers(&self, world: &World) -> usize {
+        world
+            .settlers
+            .values()
+            .filter(|s| (s.owner == self.player) & (s.job == Job::Settler) & s.workplace.is_none())
+            .count()
+    }
+
+    // Sichtbarer Feind in der Nähe: Soldaten im eigenen Gebiet oder fremde
+    // Militärgebäude in Angriffsweite
+    fn threat(&self, world: &World) -> Option<(u16, u16)> {
+        let soldier = world
+            .visible_settlers(self.player)
+            .into_iter()
+            .find(|s| {
+                (s.owner != self.player)
+                    & (s.job == Job::Soldier)
+                    & (world.map.tile(s.x, s.y).owner == Some(self.player))
+            })
+            .map(|s| (s.x, s.y));
+        if soldier.is_some() {
+            return soldier;
+        }
+        let own: Vec<(u16, u16)> = world
+            .buildings
+            .values()
+            .filter(|b| (b.owner == self.player) & b.building_type.territory_radius().is_some())
+            .map(|b| (b.x, b.y))
+            .collect();
+        world
+            .buildings
+            .values()
+            .filter(|b| {
+                (b.owner != self.player)
+                    & b.building_type.territory_radius().is_some()
+                    & world.is_visible(self.player, b.x, b.y)
+            })
+            .find(|b| {
+                own.iter()
+                    .any(|(x, y)| Map::hex_distance(*x, *y, b.x, b.y) <= ATTACK_RANGE)
+            })
+            .map(|b| (b.x, b.y))
+    }
+
+    // Greift das schwächste sichtbare Militärgebäude an, wenn genug Soldaten da sind
+    fn attack(&self, world: &mut World) {
+        let mut targets: Vec<(usize, BuildingId)> = world
+            .buildings
+            .values()
+            .filter(|b| {
+                (b.owner != self.player)
+                    & b.is_finished()
+                    & (b.building_type.garrison_capacity() > 0)
+                    & world.is_visible(self.player, b.x, b.y)
+            })
+            .map(|b| (b.garrison.len(), b.id))
+            .collect();
+        targets.sort();
+        for (defenders, target) in targets {
+            let (tx, ty) = {
+                let b = &world.buildings[&target];
+                (b.x, b.y)
+            };
+            let available: usize = world
+                .buildings
+                .values()
+                .filter(|b| {
+                    (b.owner == self.player)
+                        & (b.building_type.garrison_capacity() > 0)
+                        & (Map::hex_distance(b.x, b.y, tx, ty) <= ATTACK_RANGE)
+                })
+                .map(|b| world.present_soldiers(b.id).len().saturating_sub(1))
+                .sum();
+            if available >= defenders + ATTACK_MARGIN {
+                let _ = world.execute(
+                    self.player,
+                    Command::Attack {
+                        target,
+                        count: available as u16,
+                    },
+                );
+                return;
+            }
+        }
+    }
+
+    // Sucht einen Bauplatz, setzt die Baustelle und schließt sie ans Straßennetz an
+    fn build(&self, world: &mut World, bt: BuildingType, focus: Option<(u16, u16)>) -> bool {
+        for (x, y) in self.candidates(world, bt, focus) {
+            let command = Command::PlaceBuilding {
+                building_type: bt,
+                x,
+                y,
+            };
+            let id = match world.execute(self.player, command) {
+                Ok(CommandOutcome::Building(id)) => id,
+                _ => continue,
+            };
+            let flag = world.buildings[&id].flag;
+            if self.connect(world, flag) {
+                if bt.territory_radius().is_some() {
+                    let _ = world.execute(
+                        self.player,
+                        Command::SetPriority {
+                            building: id,
+                            priority: 2,
+                        },
+                    );
+                }
+                return true;
+            }
+            let _ = world.execute(self.player, Command::RemoveFlag { flag });
+        }
+        false
+    }
+
+    // Bauplätze im eigenen Gebiet, die besten zuerst
+    fn candidates(
+        &self,
+        world: &World,
+        bt: BuildingType,
+        focus: Option<(u16, u16)>,
+    ) -> Vec<(u16, u16)> {
+        let home = world
+            .castle_of(self.player)
+            .or_else(|| world.buildings.values().find(|b| b.owner == self.player))
+            .map_or((0, 0), |b| (b.x, b.y));
+        let mut scored: Vec<(Reverse<i32>, u16, u16)> = Vec::new();
+        for x in 0..world.map.width {
+            for y in 0..world.map.height {
+                if world.map.tile(x, y).owner != Some(self.player) {
+                    continue;
+                }
+                if world.check_placement(self.player, bt, x, y).is_err() {
+                    continue;
+                }
+                let value = match site_value(world, self.player, bt, x, y) {
+                    Some(value) => value,
+                    None => continue,
+                };
+                let (fx, fy) = focus.unwrap_or(home);
+                let distance = Map::hex_distance(x, y, fx, fy) as i32;
+                let score = if focus.is_some() {
+                    -distance
+                } else {
+                    value * 4 - distance
+                };
+                scored.push((Reverse(score), x, y));
+            }
+        }
+        scored.sort();
+        scored
+            .into_iter()
+            .take(MAX_CANDIDATES)
+            .map(|(_, x, y)| (x, y))
+            .collect()
+    }
+
+    // Straße von der Flagge zur nächsten angeschlossenen Flagge oder Straße (Breitensuche)
+    fn connect(&self, world: &mut World, flag: FlagId) -> bool {
+        if !world.roads.neighbours(flag).is_empty() {
+            return true;
+        }
+        let start = {
+            let f = &world.roads.flags[&flag];
+            (f.x, f.y)
+        };
+        let mut came_from: BTreeMap<(u16, u16), (u16, u16)> = BTreeMap::new();
+        let mut queue = VecDeque::new();
+        queue.push_back((start, 0));
+        came_from.insert(start, start);
+        let mut goal = None;
+        'search: while let Some((pos, length)) = queue.pop_front() {
+            if length >= MAX_ROAD_LENGTH {
+                continue;
+            }
+            for next in world.map.neighbours(pos.0, pos.1) {
+                if came_from.contains_key(&next) {
+                    continue;
+                }
+                let tile = world.map.tile(next.0, next.1);
+                if (tile.owner != Some(self.player)) | !tile.tile_type.is_walkable() {
+                    continue;
+                }
+                came_from.insert(next, pos);
+                if tile.flag.is_some_and(|f| self.is_connected(world, f)) {
+                    goal = Some(next);
+                    break 'search;
+                }
+                if tile.road.is_some()
+                    & RoadNetwork::check_flag(&world.map, self.player, next.0, next.1).is_ok()
+                {
+                    goal = Some(next);
+                    break 'search;
+                }
+                let blocked = tile.flag.is_some()
+                    | tile.road.is_some()
+                    | tile.building.is_some()
+                    | tile.object.is_some();
+                if !blocked {
+                    queue.push_back((next, length + 1));
+                }
+            }
+        }
+        let goal = match goal {
+            Some(goal) => goal,
+            None => return false,
+        };
+        let mut path = vec![goal];
+        let mut current = goal;
+        while current != start {
+            current = came_from[&current];
+            path.push(current);
+        }
+        path.reverse();
+        let inner: Vec<(u16, u16)> = path[1..path.len() - 1].to_vec();
+        if world
+            .execute(self.player, Command::BuildRoad { path })
+            .is_err()
+        {
+            return false;
+        }
+        // Lange Straßen bekommen Flaggen unterwegs, damit mehr Träger darauf arbeiten.
+        // Jede Flagge kostet aber einen Träger mehr.
+        if inner.len() >= ROAD_FLAG_LENGTH {
+            for (i, (x, y)) in inner.into_iter().enumerate() {
+                if i % 3 == 2 {
+                    let _ = world.execute(self.player, Command::PlaceFlag { x, y });
+                }
+            }
+        }
+        true
+    }
+
+    // Flagge mit Straßen oder vor einem Lager
+    fn is_connected(&self, world: &World, flag: FlagId) -> bool {
+        let f = &world.roads.flags[&flag];
+        (f.owner == self.player)
+            & (!world.roads.neighbours(flag).is_empty()
+                | f.building
+                    .and_then(|b| world.buildings.get(&b))
+                    .is_some_and(|b| b.is_storage()))
+    }
+}
+
+// Wie gut ein Platz für das Gebäude ist. None, wenn dort nichts zu holen ist.
+fn site_value(world: &World, player: PlayerId, bt: BuildingType, x: u16, y: u16) -> Option<i32> {
+    let map = &world.map;
+    if let Some(radius) = bt.territory_radius() {
+        // Militär lohnt sich, wo viel fremdes oder freies Land liegt
+        let foreign = map
+            .spiral(x, y, radius)
+            .iter()
+            .filter(|(tx, ty)| map.tile(*tx, *ty).owner != Some(player))
+            .count() as i32;
+        return if foreign > 5 { Some(foreign / 4) } else { None };
+    }
+    let def = match world.production.get(bt) {
+        Some(def) => def,
+        None => return Some(0),
+    };
+    let area = map.spiral(x, y, def.work_radius);
+    let count =
+        |f: &dyn Fn(u16, u16) -> bool| area.iter().filter(|(tx, ty)| f(*tx, *ty)).count() as i32;
+    let value = match def.work {
+        WorkKind::InPlace => return Some(0),
+        WorkKind::FellTree => count(&|tx, ty| map.tile(tx, ty).object == Some(MapObject::Tree)) - 3,
+        WorkKind::CutStone => {
+            count(&|tx, ty| matches!(map.tile(tx, ty).object, Some(MapObject::Stone(_))))
+        }
+        WorkKind::Farm => {
+            count(&|tx, ty| {
+                let tile = map.tile(tx, ty);
+                tile.tile_type.is_buildable()
+                    & tile.object.is_none()
+                    & tile.building.is_none()
+                    & tile.road.is_none()
+            }) - 6
+        }
+        WorkKind::Fish => count(&|tx, ty| {
+            map.tile(tx, ty)
+                .deposit
+                .is_some_and(|d| (d.kind == ResourceKind::Fish) & (d.amount > 0))
+        }),
+        WorkKind::Mine(kind) => count(&|tx, ty| {
+            map.tile(tx, ty)
+                .deposit
+                .is_some_and(|d| (d.kind == kind) & (d.amount > 0))
+        }),
+    };
+    if value > 0 {
+        Some(value)
+    } else {
+        None
+    }
+}
+
+impl World {
+    pub fn territory_size(&self, player: PlayerId) -> usize {
+        self.map
+            .tiles
+            .iter()
+            .flatten()
+            .filter(|t| t.owner == Some(player))
+            .count()
+    }
+}
+
+// Ergebnis eines Spiels ohne Fenster, Computer gegen Computer
+pub struct MatchReport {
+    pub seed: u64,
+    pub ticks: u64,
+    pub players: Vec<PlayerReport>,
+}
+
+pub struct PlayerReport {
+    pub player: PlayerId,
+    pub territory: usize,
+    pub population: usize,
+    pub soldiers: usize,
+    pub buildings: BTreeMap<BuildingType, usize>,
+    pub stock: BTreeMap<Good, u16>,
+}
+
+impl MatchReport {
+    // Wer das meiste Land hat, Gleichstand über die Bevölkerung
+    pub fn leader(&self) -> Option<PlayerId> {
+        self.players
+            .iter()
+            .max_by_key(|p| (p.territory, p.population, Reverse(p.player)))
+            .map(|p| p.player)
+    }
+}
+
+impl fmt::Display for MatchReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Seed {}, {} ticks", self.seed, self.ticks)?;
+        for p in &self.players {
+            writeln!(
+                f,
+                "Player {}: territory {}, population {}, soldiers {}",
+                p.player, p.territory, p.population, p.soldiers
+            )?;
+            let buildings: Vec<String> = p
+                .buildings
+                .iter()
+                .map(|(bt, n)| format!("{} {}", bt.name(), n))
+                .collect();
+            writeln!(f, "  buildings: {}", buildings.join(", "))?;
+            let stock: Vec<String> = p
+                .stock
+                .iter()
+                .map(|(good, n)| format!("{} {}", good.name(), n))
+                .collect();
+            writeln!(f, "  stock: {}", stock.join(", "))?;
+        }
+        if let Some(leader) = self.leader() {
+            writeln!(f, "Leader: player {}", leader)?;
+        }
+        Ok(())
+    }
+}
+
+// Computer gegen Computer auf einer erzeugten Karte, ohne Fenster. Gleiche Eingaben
+// liefern das gleiche Ergebnis, dafür ist es da. Die Wiederholung enthält alle Befehle.
+pub fn run_match(
+    production: ProductionDefs,
+    seed: u64,
+    players: usize,
+    size: u16,
+    symmetry: Symmetry,
+    ticks: u64,
+) -> io::Result<(MatchReport, Replay)> {
+    let setup = GameSetup::generated(size, size, seed, players, symmetry);
+    let mut world = setup.create_world(production)?;
+    let mut ais: Vec<AiPlayer> = setup
+        .starts
+        .iter()
+        .map(|(player, _, _)| AiPlayer::new(*player))
+        .collect();
+    for _ in 0..ticks {
+        for ai in ais.iter_mut() {
+            ai.think(&mut world);
+        }
+        world.tick();
+    }
+    let report = MatchReport {
+        seed,
+        ticks,
+        players: ais
+            .iter()
+            .map(|ai| world.player_report(ai.player))
+            .collect(),
+    };
+    Ok((report, Replay::record(&setup, &world)))
+}
+
+impl World {
+    pub fn player_report(&self, player: PlayerId) -> PlayerReport {
+        let mut buildings = BTreeMap::new();
+        for b in self
+            .buildings
+            .values()
+            .filter(|b| (b.owner == player) & b.is_finished())
+        {
+            *buildings.entry(b.building_type).or_insert(0) += 1;
+        }
+        PlayerReport {
+            player,
+            territory: self.territory_size(player),
+            population: self.population(player),
+            soldiers: self
+                .settlers
+                .values()
+                .filter(|s| (s.owner == player) & (s.job == Job::Soldier))
+                .count(),
+            buildings,
+            stock: self
+                .player_stock(player)
+                .into_iter()
+                .map(|(good, count)| (good, count.stored))
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::testing;
+
+    fn short_match(seed: u64) -> (MatchReport, Replay) {
+        run_match(testing::production(), seed, 2, 40, Symmetry::None, 800).unwrap()
+    }
+
+    #[test]
+    fn computer_players_build_and_repeat_themselves() {
+        let (report, replay) = short_match(5);
+        for player in 0..2 {
+            assert!(
+                replay
+                    .commands
+                    .iter()
+                    .any(|c| (c.player == player)
+                        & matches!(c.command, Command::PlaceBuilding { .. }))
+            );
+        }
+        let (again, replayed) = short_match(5);
+        assert_eq!(replayed, replay);
+        assert_eq!(again.to_string(), report.to_string());
+        assert_ne!(short_match(6).1.commands, replay.commands);
+    }
+
+    #[test]
+    fn tiny_maps_are_an_error() {
+        for size in [0, 3, 6, 9] {
+            let result = run_match(testing::production(), 1, 2, size, Symmetry::None, 10);
+            assert!(result.is_err(), "size {}", size);
+        }
+    }
+}
@@ -0,0 +1,142 @@
+use std::{
+    collections::BTreeMap,
+    env, fs, io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+// Namen der Assets. Getrennt wird immer mit /, egal auf welchem System.
+pub const EXAMPLE_IMAGE: &str = "img/example.png";
+pub const PRODUCTION: &str = "data/production.ron";
+pub const GFX_MANIFEST: &str = "data/gfx.ron";
+// Eigene Siedler-4-Karten zum Prüfen des Imports, liegen nicht im Repository
+pub const S4_FIXTURES: &str = "maps/s4";
+
+// Hier sucht das Spiel nach Mods, wenn nichts anderes angegeben ist
+pub const MODS_ENV: &str = "SETTLERS_RS_MODS";
+
+const RES_DIR: &str = "res";
+
+// Findet Assets über ihren Namen in einer Reihe von Verzeichnissen. Das erste, in dem
+// die Datei liegt, gewinnt, so können Mods die Dateien des Spiels ersetzen.
+pub struct Assets {
+    pub roots: Vec<PathBuf>,
+    // Woher jedes gelesene Asset kam und wann die Datei zuletzt geändert wurde
+    loaded: BTreeMap<String, (PathBuf, Option<SystemTime>)>,
+}
+
+impl Assets {
+    // Mods zuerst, dann res im Arbeitsverzeichnis, dann res neben dem Programm
+    pub fn new(mods: Option<PathBuf>) -> Self {
+        let mut roots: Vec<PathBuf> = mods.or_else(default_mods_dir).into_iter().collect();
+        roots.push(PathBuf::from(RES_DIR));
+        if let Some(dir) = env::current_exe()
+            .ok()
+            .and_then(|exe| exe.parent().map(Path::to_path_buf))
+        {
+            roots.push(dir.join(RES_DIR));
+        }
+        roots.dedup();
+        Self::with_roots(roots)
+    }
+
+    pub fn with_roots(roots: Vec<PathBuf>) -> Self {
+        Assets {
+            roots,
+            loaded: BTreeMap::new(),
+        }
+    }
+
+    pub fn resolve(&self, name: &str) -> io::Result<PathBuf> {
+        find(&self.roots, &relative_path(name)?).ok_or_else(|| {
+            let searched: Vec<String> = self
+                .roots
+                .iter()
+                .map(|root| root.display().to_string())
+                .collect();
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("asset {} not found in {}", name, searched.join(", ")),
+            )
+        })
+    }
+
+    // Merkt sich die Datei, damit changed sie später melden kann
+    pub fn read(&mut self, name: &str) -> io::Result<Vec<u8>> {
+        let path = self.resolve(name)?;
+        let data = fs::read(&path)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+        let modified = modified(&path);
+        self.loaded.insert(String::from(name), (path, modified));
+        Ok(data)
+    }
+
+    pub fn read_to_string(&mut self, name: &str) -> io::Result<String> {
+        String::from_utf8(self.read(name)?).map_err(|e| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("asset {}: {}", name, e))
+        })
+    }
+
+    // Gelesene Assets, deren Datei sich seitdem geändert hat oder die jetzt woanders
+    // gefunden werden, etwa weil ein Mod dazukam. Zum Neuladen während der Entwicklung.
+    pub fn changed(&mut self) -> Vec<String> {
+        let mut changed = Vec::new();
+        for (name, (path, time)) in self.loaded.iter_mut() {
+            // Der Name war beim Lesen gültig
+            let relative = relative_path(name).unwrap();
+            let now = match find(&self.roots, &relative) {
+                Some(now) => now,
+                // Gelöscht, bis es wieder da ist bleibt die alte Fassung
+                None => continue,
+            };
+            let modified = modified(&now);
+            if (now != *path) | (modified != *time) {
+                *path = now;
+                *time = modified;
+                changed.push(name.clone());
+            }
+        }
+        changed
+    }
+}
+
+fn find(roots: &[PathBuf], relative: &Path) -> Option<PathBuf> {
+    roots
+        .iter()
+        .map(|root| root.join(relative))
+        .find(|path| path.exists())
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+// Aus "img/example.png" oder "img\example.png" wird ein Pfad für dieses System. Nur
+// Namen unterhalb der Suchverzeichnisse sind erlaubt.
+fn relative_path(name: &str) -> io::Result<PathBuf> {
+    let parts: Vec<&str> = name.split(['/', '\\']).collect();
+    let bad = parts
+        .iter()
+        .any(|part| part.is_empty() | (*part == ".") | (*part == "..") | part.contains(':'));
+    if bad {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is not a valid asset name", name),
+        ));
+    }
+    Ok(parts.iter().collect())
+}
+
+// SETTLERS_RS_MODS, sonst settlers_rs/mods in den Anwendungsdaten des Benutzers
+pub fn default_mods_dir() -> Option<PathBuf> {
+    if let Some(dir) = env::var_os(MODS_ENV) {
+        return Some(PathBuf::from(dir));
+    }
+    let data = env::var_os("APPDATA")
+        .or_else(|| env::var_os("XDG_DATA_HOME"))
+        .map(PathBuf::from)
+        .or_else(|| {
+            env::var_os("HOME").map(|home| Path::new(&home).join(".local").join("share"))
+        })?;
+    Some(data.join("settlers_rs").join("mods"))
+}
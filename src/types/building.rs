@@ -0,0 +1,416 @@
+use coffee::graphics::Color;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use super::{
+    construction::ConstructionSite,
+    goods::Good,
+    hextile::TileType,
+    map::Map,
+    player::PlayerId,
+    production::Workshop,
+    road::{FlagId, RoadNetwork},
+    settler::SettlerId,
+};
+
+pub type BuildingId = u32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuildingSize {
+    Small,
+    Medium,
+    Large,
+}
+
+impl BuildingSize {
+    // Relative axiale Koordinaten, die das Gebäude belegt. (0, 0) ist die Tür.
+    pub fn footprint(self) -> &'static [(i32, i32)] {
+        match self {
+            BuildingSize::Small => &[(0, 0)],
+            BuildingSize::Medium => &[(0, 0), (0, -1), (1, -1)],
+            BuildingSize::Large => &[(0, 0), (1, 0), (0, 1), (-1, 1), (-1, 0), (0, -1), (1, -1)],
+        }
+    }
+
+    // Position der Flagge vor der Tür
+    pub fn flag_offset(self) -> (i32, i32) {
+        match self {
+            BuildingSize::Small | BuildingSize::Medium => (0, 1),
+            BuildingSize::Large => (0, 2),
+        }
+    }
+
+    // Maximaler Höhenunterschied innerhalb der Grundfläche
+    pub fn max_slope(self) -> u8 {
+        match self {
+            BuildingSize::Small => 3,
+            BuildingSize::Medium => 2,
+            BuildingSize::Large => 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TerrainRequirement {
+    // Normaler bebaubarer Boden
+    Buildable,
+    // Bebaubarer Boden mit Wasser in der Nähe
+    Coast,
+    // Nur im Gebirge
+    Mountain,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum BuildingType {
+    Castle,
+    Warehouse,
+    Residence,
+    Woodcutter,
+    Stonecutter,
+    Sawmill,
+    Farm,
+    Mill,
+    Bakery,
+    Fisher,
+    CoalMine,
+    IronMine,
+    GoldMine,
+    SulfurMine,
+    GuardHouse,
+    Tower,
+    Fortress,
+}
+
+pub const BUILDING_TYPES: [BuildingType; 17] = [
+    BuildingType::Castle,
+    BuildingType::Warehouse,
+    BuildingType::Residence,
+    BuildingType::Woodcutter,
+    BuildingType::Stonecutter,
+    BuildingType::Sawmill,
+    BuildingType::Farm,
+    BuildingType::Mill,
+    BuildingType::Bakery,
+    BuildingType::Fisher,
+    BuildingType::CoalMine,
+    BuildingType::IronMine,
+    BuildingType::GoldMine,
+    BuildingType::SulfurMine,
+    BuildingType::GuardHouse,
+    BuildingType::Tower,
+    BuildingType::Fortress,
+];
+
+impl BuildingType {
+    pub fn name(self) -> &'static str {
+        match self {
+            BuildingType::Castle => "Castle",
+            BuildingType::Warehouse => "Warehouse",
+            BuildingType::Residence => "Residence",
+            BuildingType::Woodcutter => "Woodcutter",
+            BuildingType::Stonecutter => "Stonecutter",
+            BuildingType::Sawmill => "Sawmill",
+            BuildingType::Farm => "Farm",
+            BuildingType::Mill => "Mill",
+            BuildingType::Bakery => "Bakery",
+            BuildingType::Fisher => "Fisher",
+            BuildingType::CoalMine => "Coal mine",
+            BuildingType::IronMine => "Iron mine",
+            BuildingType::GoldMine => "Gold mine",
+            BuildingType::SulfurMine => "Sulfur mine",
+            BuildingType::GuardHouse => "Guard house",
+            BuildingType::Tower => "Tower",
+            BuildingType::Fortress => "Fortress",
+        }
+    }
+
+    pub fn size(self) -> BuildingSize {
+        match self {
+            BuildingType::Residence
+            | BuildingType::Woodcutter
+            | BuildingType::Stonecutter
+            | BuildingType::Fisher
+            | BuildingType::CoalMine
+            | BuildingType::IronMine
+            | BuildingType::GoldMine
+            | BuildingType::SulfurMine
+            | BuildingType::GuardHouse => BuildingSize::Small,
+            BuildingType::Warehouse
+            | BuildingType::Sawmill
+            | BuildingType::Mill
+            | BuildingType::Bakery
+            | BuildingType::Tower => BuildingSize::Medium,
+            BuildingType::Castle | BuildingType::Farm | BuildingType::Fortress => {
+                BuildingSize::Large
+            }
+        }
+    }
+
+    pub fn terrain(self) -> TerrainRequirement {
+        match self {
+            BuildingType::Fisher => TerrainRequirement::Coast,
+            BuildingType::CoalMine
+            | BuildingType::IronMine
+            | BuildingType::GoldMine
+            | BuildingType::SulfurMine => TerrainRequirement::Mountain,
+            _ => TerrainRequirement::Buildable,
+        }
+    }
+
+    // Baukosten
+    pub fn cost(self) -> &'static [(Good, u8)] {
+        match self {
+            BuildingType::Castle => &[(Good::Board, 8), (Good::Stone, 8)],
+            BuildingType::Warehouse => &[(Good::Board, 4), (Good::Stone, 4)],
+            BuildingType::Residence => &[(Good::Board, 2), (Good::Stone, 2)],
+            BuildingType::Woodcutter | BuildingType::Fisher => {
+                &[(Good::Board, 2), (Good::Stone, 1)]
+            }
+            BuildingType::Stonecutter
+            | BuildingType::CoalMine
+            | BuildingType::IronMine
+            | BuildingType::GoldMine
+            | BuildingType::SulfurMine => &[(Good::Board, 3), (Good::Stone, 1)],
+            BuildingType::Sawmill | BuildingType::Mill | BuildingType::Bakery => {
+                &[(Good::Board, 3), (Good::Stone, 3)]
+            }
+            BuildingType::Farm => &[(Good::Board, 4), (Good::Stone, 3)],
+            BuildingType::GuardHouse => &[(Good::Board, 2), (Good::Stone, 3)],
+            BuildingType::Tower => &[(Good::Board, 3), (Good::Stone, 5)],
+            BuildingType::Fortress => &[(Good::Board, 5), (Good::Stone, 8)],
+        }
+    }
+
+    pub fn color(self) -> Color {
+        match self {
+            BuildingType::Castle => Color::from_rgb(120, 0, 0),
+            BuildingType::Warehouse => Color::from_rgb(150, 90, 60),
+            BuildingType::Residence => Color::from_rgb(200, 80, 80),
+            BuildingType::Woodcutter => Color::from_rgb(90, 60, 20),
+            BuildingType::Stonecutter => Color::from_rgb(140, 140, 140),
+            BuildingType::Sawmill => Color::from_rgb(200, 150, 80),
+            BuildingType::Farm => Color::from_rgb(230, 220, 90),
+            BuildingType::Mill => Color::from_rgb(240, 240, 200),
+            BuildingType::Bakery => Color::from_rgb(210, 120, 40),
+            BuildingType::Fisher => Color::from_rgb(40, 80, 160),
+            BuildingType::CoalMine => Color::from_rgb(30, 30, 30),
+            BuildingType::IronMine => Color::from_rgb(120, 50, 30),
+            BuildingType::GoldMine => Color::from_rgb(200, 170, 0),
+            BuildingType::SulfurMine => Color::from_rgb(160, 180, 30),
+            BuildingType::GuardHouse => Color::from_rgb(110, 110, 130),
+            BuildingType::Tower => Color::from_rgb(80, 80, 110),
+            BuildingType::Fortress => Color::from_rgb(50, 50, 80),
+        }
+    }
+
+    // Wie weit das fertige Gebäude Land beansprucht. None für zivile Gebäude.
+    pub fn territory_radius(self) -> Option<u16> {
+        match self {
+            BuildingType::Castle => Some(9),
+            BuildingType::GuardHouse => Some(6),
+            BuildingType::Tower => Some(8),
+            BuildingType::Fortress => Some(11),
+            _ => None,
+        }
+    }
+
+    // So viele Soldaten passen hinein
+    pub fn garrison_capacity(self) -> u16 {
+        match self {
+            BuildingType::Castle => 6,
+            BuildingType::GuardHouse => 2,
+            BuildingType::Tower => 4,
+            BuildingType::Fortress => 8,
+            _ => 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlacementError {
+    OutOfMap,
+    Terrain,
+    Slope,
+    Occupied,
+    Territory,
+    Flag,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum BuildingState {
+    ConstructionSite(ConstructionSite),
+    Finished,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Building {
+    pub id: BuildingId,
+    pub building_type: BuildingType,
+    pub owner: PlayerId,
+    pub x: u16,
+    pub y: u16,
+    pub flag: FlagId,
+    pub state: BuildingState,
+    // Waren im Gebäude
+    pub stock: BTreeMap<Good, u16>,
+    // Fertige Waren, die auf Abholung warten
+    pub output: BTreeMap<Good, u16>,
+    // Schon einer Anfrage versprochen, aber noch nicht an der Flagge
+    pub reserved: BTreeMap<Good, u16>,
+    // Höher wird zuerst beliefert
+    pub priority: u8,
+    // Nur bei fertigen Produktionsgebäuden
+    pub workshop: Option<Workshop>,
+    // Wie viele Siedler ein Wohnhaus schon hervorgebracht hat
+    pub residents: u16,
+    // Soldaten, die hier stationiert sind, auch wenn sie gerade angreifen
+    pub garrison: Vec<SettlerId>,
+}
+
+impl Building {
+    pub fn is_finished(&self) -> bool {
+        matches!(self.state, BuildingState::Finished)
+    }
+
+    pub fn site(&self) -> Option<&ConstructionSite> {
+        match &self.state {
+            BuildingState::ConstructionSite(site) => Some(site),
+            BuildingState::Finished => None,
+        }
+    }
+
+    // Lager nehmen alles auf, was gerade niemand braucht, und bedienen Anfragen
+    pub fn is_storage(&self) -> bool {
+        matches!(
+            self.building_type,
+            BuildingType::Castle | BuildingType::Warehouse
+        )
+    }
+
+    pub fn stock_of(&self, good: Good) -> u16 {
+        self.stock.get(&good).copied().unwrap_or(0)
+    }
+
+    pub fn output_of(&self, good: Good) -> u16 {
+        self.output.get(&good).copied().unwrap_or(0)
+    }
+
+    pub fn reserved_of(&self, good: Good) -> u16 {
+        self.reserved.get(&good).copied().unwrap_or(0)
+    }
+
+    // Was dieses Gebäude von einer Ware abgeben kann: Lager ihren Bestand, alle anderen
+    // ihre Produktion. Was schon versprochen ist, zählt nicht mehr.
+    pub fn available(&self, good: Good) -> u16 {
+        let goods = if self.is_storage() {
+            self.stock_of(good)
+        } else {
+            self.output_of(good)
+        };
+        goods.saturating_sub(self.reserved_of(good))
+    }
+
+    pub fn reserve(&mut self, good: Good) {
+        *self.reserved.entry(good).or_insert(0) += 1;
+    }
+
+    pub fn unreserve(&mut self, good: Good) {
+        if let Some(count) = self.reserved.get_mut(&good) {
+            *count -= 1;
+            if *count == 0 {
+                self.reserved.remove(&good);
+            }
+        }
+    }
+
+    // Eine reservierte Ware verlässt das Gebäude
+    pub fn take_reserved(&mut self, good: Good) {
+        self.unreserve(good);
+        let goods = if self.is_storage() {
+            &mut self.stock
+        } else {
+            &mut self.output
+        };
+        if let Some(count) = goods.get_mut(&good) {
+            *count -= 1;
+            if *count == 0 {
+                goods.remove(&good);
+            }
+        }
+    }
+
+    pub fn site_mut(&mut self) -> Option<&mut ConstructionSite> {
+        match &mut self.state {
+            BuildingState::ConstructionSite(site) => Some(site),
+            BuildingState::Finished => None,
+        }
+    }
+
+    // Liefert die belegten Tiles, wenn das Gebäude an (x, y) stehen darf
+    pub fn check_placement(
+        map: &Map,
+        player: PlayerId,
+        bt: BuildingType,
+        x: u16,
+        y: u16,
+    ) -> Result<Vec<(u16, u16)>, PlacementError> {
+        let size = bt.size();
+        let mut tiles = Vec::new();
+        for (dx, dy) in size.footprint() {
+            match map.offset(x, y, *dx, *dy) {
+                Some(pos) => tiles.push(pos),
+                None => return Err(PlacementError::OutOfMap),
+            }
+        }
+
+        let mut min_elevation = u8::MAX;
+        let mut max_elevation = u8::MIN;
+        for (tx, ty) in &tiles {
+            let tile = map.tile(*tx, *ty);
+            let terrain_ok = match bt.terrain() {
+                TerrainRequirement::Buildable | TerrainRequirement::Coast => {
+                    tile.tile_type.is_buildable()
+                }
+                TerrainRequirement::Mountain => tile.tile_type == TileType::TtMountain,
+            };
+            if !terrain_ok {
+                return Err(PlacementError::Terrain);
+            }
+            if tile.building.is_some()
+                | tile.flag.is_some()
+                | tile.road.is_some()
+                | tile.object.is_some()
+            {
+                return Err(PlacementError::Occupied);
+            }
+            if tile.owner != Some(player) {
+                return Err(PlacementError::Territory);
+            }
+            min_elevation = min_elevation.min(tile.elevation);
+            max_elevation = max_elevation.max(tile.elevation);
+        }
+
+        if max_elevation - min_elevation > size.max_slope() {
+            return Err(PlacementError::Slope);
+        }
+
+        if bt.terrain() == TerrainRequirement::Coast
+            && !map.spiral(x, y, 2).iter().any(|(tx, ty)| {
+                let tt = map.tile(*tx, *ty).tile_type;
+                (tt == TileType::TtOcean) | (tt == TileType::TtRiver)
+            })
+        {
+            return Err(PlacementError::Terrain);
+        }
+
+        // Vor die Tür muss eine eigene Flagge, vorhanden oder neu
+        let (fx, fy) = size.flag_offset();
+        let (fx, fy) = map.offset(x, y, fx, fy).ok_or(PlacementError::OutOfMap)?;
+        let flag_tile = map.tile(fx, fy);
+        if flag_tile.flag.is_none() | (flag_tile.owner != Some(player)) {
+            RoadNetwork::check_flag(map, player, fx, fy).map_err(|_| PlacementError::Flag)?;
+        }
+
+        Ok(tiles)
+    }
+}
@@ -0,0 +1,311 @@
+use serde::{Deserialize, Serialize};
+
+use super::{
+    building::{BuildingId, BuildingType, PlacementError},
+    military::AttackError,
+    player::PlayerId,
+    road::{FlagId, RoadError, RoadId, RoadNetwork},
+    world::World,
+};
+
+// Alles, was ein Spieler an der Welt ändern darf. Oberfläche, KI, Wiederholungen und
+// Mehrspieler gehen alle über diese Befehle.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Command {
+    PlaceBuilding {
+        building_type: BuildingType,
+        x: u16,
+        y: u16,
+    },
+    PlaceFlag {
+        x: u16,
+        y: u16,
+    },
+    // Beginnt an einer Flagge, am Ende wird bei Bedarf eine gesetzt
+    BuildRoad {
+        path: Vec<(u16, u16)>,
+    },
+    Demolish {
+        building: BuildingId,
+    },
+    RemoveFlag {
+        flag: FlagId,
+    },
+    RemoveRoad {
+        road: RoadId,
+    },
+    SetPriority {
+        building: BuildingId,
+        priority: u8,
+    },
+    Attack {
+        target: BuildingId,
+        count: u16,
+    },
+}
+
+// Ein ausgeführter Befehl mit dem Tick, nach dem er kam. Daraus wird eine Wiederholung.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TimedCommand {
+    pub tick: u64,
+    pub player: PlayerId,
+    pub command: Command,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandError {
+    Placement(PlacementError),
+    Road(RoadError),
+    Attack(AttackError),
+    // Koordinate außerhalb der Karte
+    OutOfMap,
+    // Gebäude, Flagge oder Straße gibt es nicht (mehr)
+    NotFound,
+    // Gehört einem anderen Spieler
+    NotOwner,
+}
+
+// Was ein Befehl neu angelegt hat
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandOutcome {
+    Building(BuildingId),
+    Flag(FlagId),
+    Road(RoadId),
+    // So viele Soldaten sind losgelaufen
+    Attack(u16),
+    Done,
+}
+
+impl World {
+    // Wird am Anfang des nächsten Ticks ausgeführt
+    pub fn queue_command(&mut self, player: PlayerId, command: Command) {
+        self.commands.push((player, command));
+    }
+
+    // Befehle kommen auch aus dem Netz und aus Dateien und können alles enthalten. Erst
+    // wenn jede Koordinate auf der Karte liegt und es jedes Gebäude, jede Flagge und
+    // jede Straße gibt, prüfen die Teilsysteme weiter.
+    fn check_references(&self, command: &Command) -> Result<(), CommandError> {
+        let on_map = |(x, y): &(u16, u16)| self.map.contains(*x as i32, *y as i32);
+        let known = match command {
+            Command::PlaceBuilding { x, y, .. } | Command::PlaceFlag { x, y } => {
+                return on_map(&(*x, *y))
+                    .then_some(())
+                    .ok_or(CommandError::OutOfMap);
+            }
+            Command::BuildRoad { path } => {
+                return path
+                    .iter()
+                    .all(on_map)
+                    .then_some(())
+                    .ok_or(CommandError::OutOfMap);
+            }
+            Command::Demolish { building } | Command::SetPriority { building, .. } => {
+                self.buildings.contains_key(building)
+            }
+            Command::Attack { target, .. } => self.buildings.contains_key(target),
+            Command::RemoveFlag { flag } => self.roads.flags.contains_key(flag),
+            Command::RemoveRoad { road } => self.roads.roads.contains_key(road),
+        };
+        known.then_some(()).ok_or(CommandError::NotFound)
+    }
+
+    // Prüft, ob der Befehl jetzt gültig wäre, ohne etwas zu ändern
+    pub fn check_command(&self, player: PlayerId, command: &Command) -> Result<(), CommandError> {
+        self.check_references(command)?;
+        match command {
+            Command::PlaceBuilding {
+                building_type,
+                x,
+                y,
+            } => self
+                .check_placement(player, *building_type, *x, *y)
+                .map(|_| ())
+                .map_err(CommandError::Placement),
+            Command::PlaceFlag { x, y } => {
+                RoadNetwork::check_flag(&self.map, player, *x, *y).map_err(CommandError::Road)
+            }
+            Command::BuildRoad { path } => self
+                .roads
+                .check_road(&self.map, player, path)
+                .map_err(CommandError::Road),
+            Command::Demolish { building } | Command::SetPriority { building, .. } => {
+                match self.buildings.get(building) {
+                    Some(b) if b.owner == player => Ok(()),
+                    Some(_) => Err(CommandError::NotOwner),
+                    None => Err(CommandError::NotFound),
+                }
+            }
+            Command::RemoveFlag { flag } => match self.roads.flags.get(flag) {
+                Some(f) if f.owner == player => Ok(()),
+                Some(_) => Err(CommandError::NotOwner),
+                None => Err(CommandError::NotFound),
+            },
+            Command::RemoveRoad { road } => match self.roads.roads.get(road) {
+                Some(r) if r.owner == player => Ok(()),
+                Some(_) => Err(CommandError::NotOwner),
+                None => Err(CommandError::NotFound),
+            },
+            Command::Attack { target, .. } => self
+                .check_attack(player, *target)
+                .map_err(CommandError::Attack),
+        }
+    }
+
+    // Prüft und führt sofort aus. Nur zwischen zwei Ticks aufrufen, sonst über queue_command.
+    // Ins Protokoll kommen nur ausgeführte Befehle, abgelehnte ändern nichts.
+    pub fn execute(
+        &mut self,
+        player: PlayerId,
+        command: Command,
+    ) -> Result<CommandOutcome, CommandError> {
+        let tick = self.tick;
+        let outcome = self.apply_command(player, command.clone())?;
+        self.command_log.push(TimedCommand {
+            tick,
+            player,
+            command,
+        });
+        Ok(outcome)
+    }
+
+    fn apply_command(
+        &mut self,
+        player: PlayerId,
+        command: Command,
+    ) -> Result<CommandOutcome, CommandError> {
+        self.check_command(player, &command)?;
+        let outcome = match command {
+            Command::PlaceBuilding {
+                building_type,
+                x,
+                y,
+            } => CommandOutcome::Building(
+                self.place_building(player, building_type, x, y)
+                    .map_err(CommandError::Placement)?,
+            ),
+            Command::PlaceFlag { x, y } => {
+                CommandOutcome::Flag(self.place_flag(player, x, y).map_err(CommandError::Road)?)
+            }
+            Command::BuildRoad { path } => {
+                CommandOutcome::Road(self.build_road(player, path).map_err(CommandError::Road)?)
+            }
+            Command::Demolish { building } => {
+                self.demolish_building(building);
+                CommandOutcome::Done
+            }
+            Command::RemoveFlag { flag } => {
+                self.remove_flag(flag);
+                CommandOutcome::Done
+            }
+            Command::RemoveRoad { road } => {
+                self.remove_road(road);
+                CommandOutcome::Done
+            }
+            Command::SetPriority { building, priority } => {
+                self.set_priority(building, priority);
+                CommandOutcome::Done
+            }
+            Command::Attack { target, count } => CommandOutcome::Attack(
+                self.order_attack(player, target, count)
+                    .map_err(CommandError::Attack)?,
+            ),
+        };
+        Ok(outcome)
+    }
+
+    // Alle wartenden Befehle in der Reihenfolge, in der sie kamen. Ungültige landen in
+    // rejected, damit die Oberfläche sie melden kann.
+    pub(crate) fn apply_commands(&mut self) {
+        for (player, command) in std::mem::take(&mut self.commands) {
+            if let Err(e) = self.execute(player, command.clone()) {
+                self.rejected.push((player, command, e));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::testing;
+
+    #[test]
+    fn commands_off_the_map_are_rejected() {
+        let (mut world, castle) = testing::world();
+        let (w, h) = (world.map.width, world.map.height);
+        let flag = &world.roads.flags[&world.buildings[&castle].flag];
+        let start = (flag.x, flag.y);
+        let commands = vec![
+            Command::PlaceBuilding {
+                building_type: BuildingType::Woodcutter,
+                x: w,
+                y: 5,
+            },
+            Command::PlaceBuilding {
+                building_type: BuildingType::Castle,
+                x: u16::MAX,
+                y: u16::MAX,
+            },
+            Command::PlaceFlag { x: 5, y: h },
+            Command::BuildRoad {
+                path: vec![start, (start.0, h + 1)],
+            },
+            Command::BuildRoad {
+                path: vec![(w, h), start],
+            },
+        ];
+        for command in commands {
+            assert_eq!(
+                world.execute(0, command.clone()),
+                Err(CommandError::OutOfMap),
+                "{:?}",
+                command
+            );
+        }
+        let commands = vec![
+            Command::Demolish { building: u32::MAX },
+            Command::SetPriority {
+                building: u32::MAX,
+                priority: 9,
+            },
+            Command::RemoveFlag { flag: u32::MAX },
+            Command::RemoveRoad { road: u32::MAX },
+            Command::Attack {
+                target: u32::MAX,
+                count: u16::MAX,
+            },
+        ];
+        for command in commands {
+            assert_eq!(
+                world.execute(0, command.clone()),
+                Err(CommandError::NotFound),
+                "{:?}",
+                command
+            );
+        }
+        assert!(world.command_log.is_empty());
+    }
+
+    #[test]
+    fn only_executed_commands_are_logged() {
+        let (mut world, _) = testing::world();
+        world.queue_command(0, Command::PlaceFlag { x: 0, y: 0 });
+        world.queue_command(
+            0,
+            Command::PlaceBuilding {
+                building_type: BuildingType::Woodcutter,
+                x: 16,
+                y: 10,
+            },
+        );
+        world.tick();
+        assert_eq!(world.command_log.len(), 1);
+        assert!(matches!(
+            world.command_log[0].command,
+            Command::PlaceBuilding { .. }
+        ));
+        assert_eq!(world.rejected.len(), 1);
+        assert_eq!(world.rejected[0].1, Command::PlaceFlag { x: 0, y: 0 });
+    }
+}
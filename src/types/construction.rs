@@ -0,0 +1,173 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use super::{goods::Good, settler::SettlerId};
+
+// Ticks, die ein Bauarbeiter pro verbautem Material braucht
+pub const BUILD_TICKS_PER_GOOD: u32 = 40;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SitePhase {
+    WaitingForBuilder,
+    WaitingForMaterials,
+    Constructing,
+    Done,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConstructionSite {
+    // Noch nicht angelieferte Waren
+    pub missing: BTreeMap<Good, u8>,
+    // Angeliefert, aber noch nicht verbaut
+    pub stock: BTreeMap<Good, u8>,
+    pub builder: Option<SettlerId>,
+    pub builder_arrived: bool,
+    pub used: u16,
+    pub total: u16,
+    pub work_ticks: u32,
+}
+
+impl ConstructionSite {
+    pub fn new(cost: &[(Good, u8)]) -> Self {
+        let mut missing = BTreeMap::new();
+        let mut total = 0u16;
+        for (good, amount) in cost {
+            *missing.entry(*good).or_insert(0) += amount;
+            total += *amount as u16;
+        }
+        ConstructionSite {
+            missing,
+            stock: BTreeMap::new(),
+            builder: None,
+            builder_arrived: false,
+            used: 0,
+            total,
+            work_ticks: 0,
+        }
+    }
+
+    pub fn phase(&self) -> SitePhase {
+        if self.used >= self.total {
+            SitePhase::Done
+        } else if !self.builder_arrived {
+            SitePhase::WaitingForBuilder
+        } else if self.stock.values().all(|n| *n == 0) {
+            SitePhase::WaitingForMaterials
+        } else {
+            SitePhase::Constructing
+        }
+    }
+
+    // Anteil des fertigen Gebäudes, 0.0 bis 1.0
+    pub fn progress(&self) -> f32 {
+        if self.total == 0 {
+            return 1f32;
+        }
+        (self.used as f32 * BUILD_TICKS_PER_GOOD as f32 + self.work_ticks as f32)
+            / (self.total as f32 * BUILD_TICKS_PER_GOOD as f32)
+    }
+
+    // Was die Baustelle noch braucht, in fester Reihenfolge
+    pub fn missing_goods(&self) -> Vec<Good> {
+        let mut result = Vec::new();
+        for (good, amount) in &self.missing {
+            for _ in 0..*amount {
+                result.push(*good);
+            }
+        }
+        result
+    }
+
+    // Ware abladen. false, wenn die Baustelle sie nicht braucht.
+    pub fn deliver(&mut self, good: Good) -> bool {
+        match self.missing.get_mut(&good) {
+            Some(n) if *n > 0 => {
+                *n -= 1;
+                *self.stock.entry(good).or_insert(0) += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // Ein Tick Arbeit des Bauarbeiters. true, wenn das Gebäude fertig ist.
+    pub fn work(&mut self) -> bool {
+        if self.phase() != SitePhase::Constructing {
+            return self.phase() == SitePhase::Done;
+        }
+        self.work_ticks += 1;
+        if self.work_ticks >= BUILD_TICKS_PER_GOOD {
+            self.work_ticks = 0;
+            // Material in fester Reihenfolge verbauen
+            if let Some((_, n)) = self.stock.iter_mut().find(|(_, n)| **n > 0) {
+                *n -= 1;
+            }
+            self.used += 1;
+        }
+        self.phase() == SitePhase::Done
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{building::BuildingType, testing};
+
+    fn work_until_done(site: &mut ConstructionSite, limit: u32) -> Option<u32> {
+        (1..=limit).find(|_| site.work())
+    }
+
+    #[test]
+    fn builds_in_ticks_per_good() {
+        let cost = BuildingType::Sawmill.cost();
+        let mut site = ConstructionSite::new(cost);
+        for good in site.missing_goods() {
+            assert!(site.deliver(good));
+        }
+        site.builder_arrived = true;
+        let expected = BUILD_TICKS_PER_GOOD * site.total as u32;
+        assert_eq!(work_until_done(&mut site, expected * 2), Some(expected));
+        assert!(site.stock.values().all(|n| *n == 0));
+        assert_eq!(site.progress(), 1.0);
+    }
+
+    #[test]
+    fn waits_for_builder_and_materials() {
+        let mut site = ConstructionSite::new(&[(Good::Board, 2)]);
+        assert_eq!(site.phase(), SitePhase::WaitingForBuilder);
+        site.builder_arrived = true;
+        assert_eq!(site.phase(), SitePhase::WaitingForMaterials);
+        assert_eq!(work_until_done(&mut site, 100), None);
+        assert_eq!(site.work_ticks, 0);
+
+        // Mit nur einem Brett wird die Hälfte fertig
+        assert!(site.deliver(Good::Board));
+        assert_eq!(work_until_done(&mut site, 1000), None);
+        assert_eq!(site.used, 1);
+        assert_eq!(site.phase(), SitePhase::WaitingForMaterials);
+        assert!(site.deliver(Good::Board));
+        assert_eq!(work_until_done(&mut site, 1000), Some(BUILD_TICKS_PER_GOOD));
+    }
+
+    #[test]
+    fn site_gets_built_from_the_castle() {
+        let (mut world, castle) = testing::world();
+        let site = testing::connected_site(&mut world, castle, BuildingType::Woodcutter, 16, 10);
+        let ticks = testing::run_until(&mut world, 3000, |w| w.buildings[&site].is_finished());
+        let cost: u32 = BuildingType::Woodcutter
+            .cost()
+            .iter()
+            .map(|(_, n)| *n as u32)
+            .sum();
+        assert!(ticks.unwrap() as u32 >= BUILD_TICKS_PER_GOOD * cost);
+    }
+
+    #[test]
+    fn rejects_goods_it_does_not_need() {
+        let mut site = ConstructionSite::new(&[(Good::Board, 1)]);
+        assert!(!site.deliver(Good::Stone));
+        assert!(site.deliver(Good::Board));
+        assert!(!site.deliver(Good::Board));
+        assert!(site.missing_goods().is_empty());
+    }
+}
@@ -0,0 +1,278 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::{self, Write as _},
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use super::world::World;
+
+// Alle so viele Ticks wird der Zustand gehasht
+pub const HASH_TICKS: u64 = 100;
+
+// Hierhin kommen die Zustände, wenn zwei Rechner auseinanderlaufen
+pub const DESYNC_DIR: &str = "desync";
+
+// Teile des Zustands, die einzeln gehasht werden. So lässt sich sagen, wo es zuerst
+// auseinanderlief.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Subsystem {
+    Map,
+    Buildings,
+    Settlers,
+    Roads,
+    Transport,
+    Visibility,
+    Rng,
+    World,
+}
+
+// In dieser Reihenfolge stehen die Hashes in StateHash::parts. Die genauesten Teile
+// zuerst: Zufall, Tick und next_id ändern sich bei fast jeder Abweichung mit und
+// sollen nicht als Ursache gemeldet werden.
+pub const SUBSYSTEMS: [Subsystem; 8] = [
+    Subsystem::Map,
+    Subsystem::Buildings,
+    Subsystem::Settlers,
+    Subsystem::Roads,
+    Subsystem::Transport,
+    Subsystem::Visibility,
+    Subsystem::Rng,
+    Subsystem::World,
+];
+
+impl Subsystem {
+    pub fn name(self) -> &'static str {
+        match self {
+            Subsystem::World => "world",
+            Subsystem::Rng => "rng",
+            Subsystem::Map => "map",
+            Subsystem::Buildings => "buildings",
+            Subsystem::Settlers => "settlers",
+            Subsystem::Roads => "roads",
+            Subsystem::Transport => "transport",
+            Subsystem::Visibility => "visibility",
+        }
+    }
+}
+
+// Hash pro Teilsystem nach einem Tick
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StateHash {
+    pub tick: u64,
+    pub parts: Vec<u64>,
+}
+
+impl StateHash {
+    pub fn combined(&self) -> u64 {
+        let mut hasher = Fnv::new();
+        for part in &self.parts {
+            let _ = write!(hasher, "{:016x}", part);
+        }
+        hasher.0
+    }
+
+    // Erstes Teilsystem, das abweicht
+    pub fn first_difference(&self, other: &StateHash) -> Option<Subsystem> {
+        SUBSYSTEMS
+            .iter()
+            .zip(self.parts.iter().zip(other.parts.iter()))
+            .find(|(_, (a, b))| a != b)
+            .map(|(subsystem, _)| *subsystem)
+    }
+}
+
+// FNV-1a über die Debug-Ausgabe. Anders als der Hasher der Standardbibliothek auf jedem
+// Rechner und mit jeder Rust-Version gleich.
+struct Fnv(u64);
+
+impl Fnv {
+    fn new() -> Self {
+        Fnv(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl fmt::Write for Fnv {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            self.0 ^= byte as u64;
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+        Ok(())
+    }
+}
+
+// Zwei Rechner oder Wiederholung und Simulation rechnen nicht mehr dasselbe
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Desync {
+    pub tick: u64,
+    pub subsystem: Subsystem,
+    // Eigener Zustand zum Vergleichen
+    pub dump: Option<PathBuf>,
+    // Zustand der Gegenseite, im Netzwerkspiel schickt sie ihn. Wiederholungen haben nur
+    // die Hashes.
+    pub other: Option<PathBuf>,
+}
+
+impl fmt::Display for Desync {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "desync at tick {} in {}",
+            self.tick,
+            self.subsystem.name()
+        )?;
+        match (&self.dump, &self.other) {
+            (Some(dump), Some(other)) => write!(
+                f,
+                ", states written to {} and {}",
+                dump.display(),
+                other.display()
+            )?,
+            (Some(dump), None) => write!(f, ", state written to {}", dump.display())?,
+            _ => (),
+        }
+        Ok(())
+    }
+}
+
+impl World {
+    fn write_subsystem<W: fmt::Write>(
+        &self,
+        subsystem: Subsystem,
+        out: &mut W,
+        pretty: bool,
+    ) -> fmt::Result {
+        let value: &dyn fmt::Debug = match subsystem {
+            Subsystem::World => {
+                return write!(out, "tick: {}, next_id: {}", self.tick, self.next_id);
+            }
+            Subsystem::Rng => &self.rng,
+            Subsystem::Map => &self.map,
+            Subsystem::Buildings => &self.buildings,
+            Subsystem::Settlers => &self.settlers,
+            Subsystem::Roads => &self.roads,
+            Subsystem::Transport => &self.transport,
+            Subsystem::Visibility => &self.visibility,
+        };
+        if pretty {
+            write!(out, "{:#?}", value)
+        } else {
+            write!(out, "{:?}", value)
+        }
+    }
+
+    // Ohne Befehls- und Hashprotokoll, die gehören nicht zum Spielstand
+    pub fn state_hash(&self) -> StateHash {
+        StateHash {
+            tick: self.tick,
+            parts: SUBSYSTEMS
+                .iter()
+                .map(|subsystem| {
+                    let mut hasher = Fnv::new();
+                    let _ = self.write_subsystem(*subsystem, &mut hasher, false);
+                    hasher.0
+                })
+                .collect(),
+        }
+    }
+
+    // Der ganze Zustand als Text, ein Abschnitt pro Teilsystem. Zwei solche Texte
+    // lassen sich mit diff vergleichen.
+    pub fn state_text(&self) -> String {
+        let mut text = String::new();
+        for subsystem in SUBSYSTEMS.iter() {
+            let _ = writeln!(text, "== {} ==", subsystem.name());
+            let _ = self.write_subsystem(*subsystem, &mut text, true);
+            text.push('\n');
+        }
+        text
+    }
+
+    pub fn dump_state<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        write_dump(path.as_ref(), &self.state_text())
+    }
+
+    // Vergleicht mit einem Hash von anderswo. Bei Abweichung wird der eigene Zustand
+    // als name in dir gespeichert.
+    pub fn check_hash(&self, expected: &StateHash, dir: &Path, name: &str) -> Option<Desync> {
+        let own = self
+            .hash_log
+            .iter()
+            .rev()
+            .find(|h| h.tick == expected.tick)?;
+        let subsystem = own.first_difference(expected)?;
+        let path = dump_path(dir, name, expected.tick);
+        let dump = self.dump_state(&path).ok().map(|_| path);
+        Some(Desync {
+            tick: expected.tick,
+            subsystem,
+            dump,
+            other: None,
+        })
+    }
+}
+
+// Datei für einen Zustand beim Tick der Abweichung
+pub fn dump_path(dir: &Path, name: &str, tick: u64) -> PathBuf {
+    dir.join(format!("{}_tick_{}.txt", name, tick))
+}
+
+pub fn write_dump(path: &Path, text: &str) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::testing;
+
+    #[test]
+    fn names_the_subsystem_that_changed() {
+        let (mut world, castle) = testing::world();
+        for _ in 0..50 {
+            world.tick();
+        }
+        let hash = world.state_hash();
+        assert_eq!(hash.first_difference(&world.state_hash()), None);
+        let differs = |change: &dyn Fn(&mut World)| {
+            let mut changed = world.clone();
+            change(&mut changed);
+            hash.first_difference(&changed.state_hash())
+        };
+        let map = differs(&|w| w.map.tile_mut(30, 30).elevation += 1);
+        assert_eq!(map, Some(Subsystem::Map));
+        let buildings = differs(&|w| w.buildings.get_mut(&castle).unwrap().priority += 1);
+        assert_eq!(buildings, Some(Subsystem::Buildings));
+        let settlers = differs(&|w| w.settlers.values_mut().next().unwrap().x += 1);
+        assert_eq!(settlers, Some(Subsystem::Settlers));
+        assert_eq!(differs(&|w| w.roads.next_id += 1), Some(Subsystem::Roads));
+        let rng = differs(&|w| {
+            w.rng.next_u64();
+        });
+        assert_eq!(rng, Some(Subsystem::Rng));
+        assert_eq!(differs(&|w| w.next_id += 1), Some(Subsystem::World));
+    }
+
+    #[test]
+    fn dumps_the_state_on_a_desync() {
+        let (mut world, _) = testing::world();
+        for _ in 0..HASH_TICKS {
+            world.tick();
+        }
+        let mut other = world.clone();
+        other.map.tile_mut(30, 30).elevation += 1;
+        let dir = std::env::temp_dir().join("settlers_rs_desync_test");
+        assert_eq!(world.check_hash(&world.state_hash(), &dir, "same"), None);
+        let desync = world
+            .check_hash(&other.state_hash(), &dir, "replay")
+            .unwrap();
+        assert_eq!(desync.subsystem, Subsystem::Map);
+        let dump = fs::read_to_string(desync.dump.unwrap()).unwrap();
+        assert_eq!(dump, world.state_text());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,6 +1,6 @@
 use coffee::graphics::Frame;
 
-use super::camera_controller::CameraController;
+use super::{camera_controller::CameraController, player::PlayerId};
 
 pub trait Drawable {
     fn draw(&mut self, param: &mut DrawParameter);
@@ -9,4 +9,6 @@ pub trait Drawable {
 pub struct DrawParameter<'a, 'b, 'c> {
     pub camera: &'b mut CameraController,
     pub frame: &'a mut Frame<'c>,
+    // Aus wessen Sicht gemalt wird. None zeigt alles.
+    pub viewer: Option<PlayerId>,
 }
@@ -0,0 +1,295 @@
+use coffee::graphics::{Color, Shape};
+use std::{fs, io, path::Path};
+
+use super::{
+    hextile::{MapObject, Tile, TileType},
+    map::Map,
+    player::player_color,
+    resources::{Deposit, ResourceKind},
+    s4map::{S4Map, MAX_ELEVATION},
+    undo::EditHistory,
+    validate::ValidationReport,
+};
+
+// Größter Pinsel, in Ringen um das Tile unter der Maus
+pub const MAX_BRUSH_RADIUS: u16 = 8;
+
+// Mehr Spieler hat keine Karte des Originals
+pub const MAX_STARTS: usize = 8;
+
+// Hierhin speichert der Editor, wenn keine Datei angegeben ist
+pub const EDITOR_FILE: &str = "maps/edited.map";
+
+// Kartengröße für eine neue Karte
+pub const EDITOR_MAP_SIZE: u16 = 64;
+
+// So viele Brocken hat ein gesetzter Stein
+const EDITOR_STONES: u8 = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Brush {
+    Terrain(TileType),
+    Raise,
+    Lower,
+    // Gleicht alle Tiles unter dem Pinsel an ihre mittlere Höhe an
+    Flatten,
+    // Volles Vorkommen, None räumt den Boden leer
+    Resource(Option<ResourceKind>),
+    // Bäume und Steine, None räumt ab
+    Object(Option<MapObject>),
+    // Setzt einen Startpunkt oder nimmt ihn weg, immer nur ein Tile
+    Start,
+}
+
+// Alles außer den Böden, die kommen aus TILE_TYPES
+pub const TOOL_BRUSHES: [Brush; 13] = [
+    Brush::Raise,
+    Brush::Lower,
+    Brush::Flatten,
+    Brush::Resource(Some(ResourceKind::Fish)),
+    Brush::Resource(Some(ResourceKind::Coal)),
+    Brush::Resource(Some(ResourceKind::Iron)),
+    Brush::Resource(Some(ResourceKind::Gold)),
+    Brush::Resource(Some(ResourceKind::Sulfur)),
+    Brush::Resource(None),
+    Brush::Object(Some(MapObject::Tree)),
+    Brush::Object(Some(MapObject::Stone(EDITOR_STONES))),
+    Brush::Object(None),
+    Brush::Start,
+];
+
+impl Brush {
+    pub fn name(self) -> &'static str {
+        match self {
+            Brush::Terrain(tt) => tt.name(),
+            Brush::Raise => "Raise",
+            Brush::Lower => "Lower",
+            Brush::Flatten => "Flatten",
+            Brush::Resource(Some(kind)) => kind.name(),
+            Brush::Resource(None) => "No resources",
+            Brush::Object(Some(MapObject::Tree)) => "Trees",
+            Brush::Object(Some(_)) => "Stones",
+            Brush::Object(None) => "Clear",
+            Brush::Start => "Start",
+        }
+    }
+}
+
+// Fische nur im Wasser, Erze nur im Berg
+fn deposit_fits(tt: TileType, kind: ResourceKind) -> bool {
+    match kind {
+        ResourceKind::Fish => !tt.is_walkable(),
+        _ => tt == TileType::TtMountain,
+    }
+}
+
+// Leere, flache Wiese
+pub fn blank_map(width: u16, height: u16) -> Map {
+    Map {
+        width,
+        height,
+        tiles: (0..width)
+            .map(|x| {
+                (0..height)
+                    .map(|y| Tile::new(TileType::TtGras, x, y))
+                    .collect()
+            })
+            .collect(),
+    }
+}
+
+// Zustand des Karteneditors. Die Karte selbst liegt in der Welt, damit sie wie im Spiel
+// gemalt wird.
+pub struct MapEditor {
+    pub brush: Brush,
+    pub radius: u16,
+    pub starts: Vec<(u16, u16)>,
+    pub path: String,
+    // Beim Ziehen wird jedes Tile nur einmal bemalt, sonst wächst ein Berg mit jedem Bild
+    pub last_painted: Option<(u16, u16)>,
+    pub history: EditHistory,
+    // Ergebnis der letzten Prüfung, die Tiles bleiben bis zur nächsten markiert
+    pub validation: ValidationReport,
+}
+
+impl MapEditor {
+    pub fn new(path: &str, starts: Vec<(u16, u16)>) -> Self {
+        MapEditor {
+            brush: Brush::Terrain(TileType::TtGras),
+            radius: 1,
+            starts,
+            path: String::from(path),
+            last_painted: None,
+            history: EditHistory::default(),
+            validation: ValidationReport::default(),
+        }
+    }
+
+    // Vorhandene Karte zum Bearbeiten, sonst eine neue
+    pub fn open(path: &str, size: u16) -> io::Result<(Self, Map)> {
+        if !Path::new(path).exists() {
+            return Ok((Self::new(path, Vec::new()), blank_map(size, size)));
+        }
+        let s4 = S4Map::load_with_resources(path)?;
+        Ok((Self::new(path, s4.starts), s4.map))
+    }
+
+    pub fn save(&self, map: &Map) -> io::Result<()> {
+        if let Some(dir) = Path::new(&self.path).parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&self.path, S4Map::encode(map, &self.starts).encode())
+    }
+
+    pub fn resize_brush(&mut self, delta: i32) {
+        self.radius = (self.radius as i32 + delta).clamp(0, MAX_BRUSH_RADIUS as i32) as u16;
+    }
+
+    // Die Tiles, die ein Klick auf (x, y) verändern kann
+    pub fn area(&self, map: &Map, x: u16, y: u16) -> Vec<(u16, u16)> {
+        match self.brush {
+            Brush::Start => vec![(x, y)],
+            _ => map.spiral(x, y, self.radius),
+        }
+    }
+
+    // Malt mit dem Pinsel. Hält die Maustaste, passiert auf demselben Tile nichts mehr.
+    pub fn paint(&mut self, map: &mut Map, x: u16, y: u16) {
+        if self.last_painted == Some((x, y)) {
+            return;
+        }
+        self.last_painted = Some((x, y));
+        let area = self.area(map, x, y);
+        self.history.record(map, &area, &self.starts);
+        match self.brush {
+            Brush::Terrain(tt) => {
+                for (tx, ty) in area {
+                    let tile = map.tile_mut(tx, ty);
+                    tile.tile_type = tt;
+                    if !tt.is_walkable() {
+                        tile.object = None;
+                    }
+                    if tile.deposit.is_some_and(|d| !deposit_fits(tt, d.kind)) {
+                        tile.deposit = None;
+                    }
+                }
+            }
+            Brush::Raise | Brush::Lower => {
+                for (tx, ty) in area {
+                    let tile = map.tile_mut(tx, ty);
+                    tile.elevation = if self.brush == Brush::Raise {
+                        (tile.elevation + 1).min(MAX_ELEVATION)
+                    } else {
+                        tile.elevation.saturating_sub(1)
+                    };
+                }
+            }
+            Brush::Flatten => {
+                let sum: u32 = area
+                    .iter()
+                    .map(|(tx, ty)| map.tile(*tx, *ty).elevation as u32)
+                    .sum();
+                let mean = (sum as f32 / area.len() as f32).round() as u8;
+                for (tx, ty) in area {
+                    map.tile_mut(tx, ty).elevation = mean;
+                }
+            }
+            Brush::Resource(kind) => {
+                for (tx, ty) in area {
+                    let tile = map.tile_mut(tx, ty);
+                    tile.deposit = match kind {
+                        Some(kind) if deposit_fits(tile.tile_type, kind) => Some(Deposit {
+                            kind,
+                            amount: kind.max_amount(),
+                        }),
+                        Some(_) => continue,
+                        None => None,
+                    };
+                }
+            }
+            Brush::Object(object) => {
+                for (tx, ty) in area {
+                    let tile = map.tile_mut(tx, ty);
+                    if object.is_none() | tile.tile_type.is_walkable() {
+                        tile.object = object;
+                    }
+                }
+            }
+            Brush::Start => {
+                if let Some(i) = self.starts.iter().position(|s| *s == (x, y)) {
+                    self.starts.remove(i);
+                } else if self.starts.len() < MAX_STARTS {
+                    self.starts.push((x, y));
+                }
+            }
+        }
+    }
+
+    // Maustaste losgelassen, alles seit dem Drücken ist ein Schritt zum Rückgängigmachen
+    pub fn end_stroke(&mut self, map: &Map) {
+        self.last_painted = None;
+        self.history.end_stroke(map, &self.starts);
+    }
+
+    pub fn undo(&mut self, map: &mut Map) -> bool {
+        self.last_painted = None;
+        self.history.undo(map, &mut self.starts)
+    }
+
+    pub fn redo(&mut self, map: &mut Map) -> bool {
+        self.last_painted = None;
+        self.history.redo(map, &mut self.starts)
+    }
+
+    pub fn check(&mut self, map: &Map) -> &ValidationReport {
+        self.validation = map.validate(&self.starts);
+        &self.validation
+    }
+
+    // Höhen als Aufhellung, Startpunkte in Spielerfarbe, Tiles mit Warnungen rot und der
+    // Pinsel unter der Maus
+    pub fn draw(&self, param: &mut crate::DrawParameter, map: &Map, hover: Option<(u16, u16)>) {
+        let (ox, oy) = (param.camera.cameraoffset_x, param.camera.cameraoffset_y);
+        for tile in map.tiles.iter().flatten().filter(|t| t.elevation > 0) {
+            let alpha = tile.elevation as f32 / MAX_ELEVATION as f32 * 0.6;
+            param.camera.mesh.fill(
+                Shape::Polyline {
+                    points: tile.get_hex_point_vec(ox, oy),
+                },
+                Color::new(1.0, 1.0, 1.0, alpha),
+            );
+        }
+        for (i, (x, y)) in self.starts.iter().enumerate() {
+            let tile = map.tile(*x, *y);
+            let shape = Shape::Circle {
+                center: tile.get_center(ox, oy),
+                radius: tile.height as f32 * 0.8,
+            };
+            param.camera.mesh.fill(shape.clone(), player_color(i as u8));
+            param
+                .camera
+                .mesh
+                .stroke(shape, Color::from_rgb(255, 255, 255), 1.0);
+        }
+        for (x, y) in self.validation.tiles() {
+            param.camera.mesh.stroke(
+                Shape::Polyline {
+                    points: map.tile(x, y).get_hex_point_vec(ox, oy),
+                },
+                Color::from_rgb(255, 0, 0),
+                2.0,
+            );
+        }
+        if let Some((x, y)) = hover {
+            for (tx, ty) in self.area(map, x, y) {
+                param.camera.mesh.stroke(
+                    Shape::Polyline {
+                        points: map.tile(tx, ty).get_hex_point_vec(ox, oy),
+                    },
+                    Color::new(1.0, 1.0, 1.0, 0.8),
+                    1.0,
+                );
+            }
+        }
+    }
+}
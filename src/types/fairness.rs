@@ -0,0 +1,272 @@
+use std::{collections::VecDeque, fmt};
+
+use super::{hextile::MapObject, map::Map, resources::RESOURCE_KINDS};
+
+// So weit um jeden Start wird gezählt, etwa das Land der Burg und des ersten Ausbaus
+pub const FAIRNESS_RADIUS: u16 = 12;
+
+// Liegen bester und schlechtester Start mehr als so viele Prozent auseinander, gilt der
+// Wert als unfair
+pub const FAIRNESS_TOLERANCE: u32 = 15;
+
+// Was ein Spieler von seinem Start aus hat
+pub struct StartReport {
+    pub x: u16,
+    pub y: u16,
+    // Freie, bebaubare Tiles im Umkreis, die zu Fuß erreichbar sind
+    pub buildable: u32,
+    pub trees: u32,
+    // Brocken aller Steine im Umkreis
+    pub stone: u32,
+    // Menge jedes Vorkommens im Umkreis, in der Reihenfolge von RESOURCE_KINDS
+    pub deposits: [u32; 5],
+    // Fußwege in Tiles. None, wenn es keinen Weg gibt.
+    pub nearest_opponent: Option<u32>,
+    pub nearest_trees: Option<u32>,
+    pub nearest_stone: Option<u32>,
+    pub nearest_deposits: [Option<u32>; 5],
+}
+
+impl StartReport {
+    // Alle Werte zum Vergleichen mit ihrem Namen
+    pub fn values(&self) -> Vec<(String, Option<u32>)> {
+        let mut values = vec![
+            (String::from("buildable"), Some(self.buildable)),
+            (String::from("trees"), Some(self.trees)),
+            (String::from("stone"), Some(self.stone)),
+        ];
+        for (kind, amount) in RESOURCE_KINDS.iter().zip(self.deposits.iter()) {
+            values.push((kind.name().to_lowercase(), Some(*amount)));
+        }
+        values.push((String::from("way to opponent"), self.nearest_opponent));
+        values.push((String::from("way to trees"), self.nearest_trees));
+        values.push((String::from("way to stone"), self.nearest_stone));
+        for (kind, way) in RESOURCE_KINDS.iter().zip(self.nearest_deposits.iter()) {
+            values.push((format!("way to {}", kind.name().to_lowercase()), *way));
+        }
+        values
+    }
+}
+
+pub struct FairnessReport {
+    pub starts: Vec<StartReport>,
+}
+
+impl FairnessReport {
+    // Werte, bei denen die Starts zu weit auseinanderliegen, mit dem Unterschied in
+    // Prozent. Kommt ein Start gar nicht hin und ein anderer schon, sind es 100.
+    pub fn unfair(&self) -> Vec<(String, u32)> {
+        let rows: Vec<Vec<(String, Option<u32>)>> =
+            self.starts.iter().map(|s| s.values()).collect();
+        let first = match rows.first() {
+            Some(first) => first,
+            None => return Vec::new(),
+        };
+        let mut unfair = Vec::new();
+        for (i, (name, _)) in first.iter().enumerate() {
+            let values: Vec<Option<u32>> = rows.iter().map(|row| row[i].1).collect();
+            let reached: Vec<u32> = values.iter().flatten().copied().collect();
+            let spread = if reached.is_empty() {
+                0
+            } else if reached.len() < values.len() {
+                100
+            } else {
+                let (min, max) = (
+                    *reached.iter().min().unwrap(),
+                    *reached.iter().max().unwrap(),
+                );
+                (max - min) * 100 / max.max(1)
+            };
+            if spread > FAIRNESS_TOLERANCE {
+                unfair.push((name.clone(), spread));
+            }
+        }
+        unfair
+    }
+}
+
+impl fmt::Display for FairnessReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let way = |way: Option<u32>| way.map_or(String::from("-"), |w| w.to_string());
+        for (i, s) in self.starts.iter().enumerate() {
+            writeln!(
+                f,
+                "Start {} at {}/{}: buildable {}, trees {}, stone {}",
+                i, s.x, s.y, s.buildable, s.trees, s.stone
+            )?;
+            let deposits: Vec<String> = RESOURCE_KINDS
+                .iter()
+                .zip(s.deposits.iter())
+                .map(|(kind, amount)| format!("{} {}", kind.name(), amount))
+                .collect();
+            writeln!(f, "  deposits: {}", deposits.join(", "))?;
+            let ways: Vec<String> = RESOURCE_KINDS
+                .iter()
+                .zip(s.nearest_deposits.iter())
+                .map(|(kind, w)| format!("{} {}", kind.name(), way(*w)))
+                .collect();
+            writeln!(
+                f,
+                "  ways: opponent {}, trees {}, stone {}, {}",
+                way(s.nearest_opponent),
+                way(s.nearest_trees),
+                way(s.nearest_stone),
+                ways.join(", ")
+            )?;
+        }
+        let unfair = self.unfair();
+        if unfair.is_empty() {
+            writeln!(f, "Fair within {}%", FAIRNESS_TOLERANCE)
+        } else {
+            let list: Vec<String> = unfair
+                .iter()
+                .map(|(name, spread)| format!("{} {}%", name, spread))
+                .collect();
+            writeln!(f, "Unfair: {}", list.join(", "))
+        }
+    }
+}
+
+impl Map {
+    // Fußweg von (x, y) zu jedem Tile, u32::MAX wenn es keinen gibt. Index x * height + y.
+    pub fn walk_distances(&self, x: u16, y: u16) -> Vec<u32> {
+        let index = |x: u16, y: u16| x as usize * self.height as usize + y as usize;
+        let mut distances = vec![u32::MAX; self.width as usize * self.height as usize];
+        let mut queue = VecDeque::new();
+        distances[index(x, y)] = 0;
+        queue.push_back((x, y));
+        while let Some((cx, cy)) = queue.pop_front() {
+            let next = distances[index(cx, cy)] + 1;
+            for (nx, ny) in self.neighbours(cx, cy) {
+                if (distances[index(nx, ny)] == u32::MAX) & self.is_passable(nx, ny) {
+                    distances[index(nx, ny)] = next;
+                    queue.push_back((nx, ny));
+                }
+            }
+        }
+        distances
+    }
+
+    // Vergleicht, was jeder Start in der Nähe hat und wie weit es bis zu den Rohstoffen
+    // und zum nächsten Gegner ist. Geht mit jeder Karte, auch mit selbst gebauten.
+    pub fn analyze_fairness(&self, starts: &[(u16, u16)]) -> FairnessReport {
+        let fields: Vec<Vec<u32>> = starts
+            .iter()
+            .map(|(x, y)| self.walk_distances(*x, *y))
+            .collect();
+        let index = |x: u16, y: u16| x as usize * self.height as usize + y as usize;
+        let reports = starts
+            .iter()
+            .zip(fields.iter())
+            .enumerate()
+            .map(|(i, ((sx, sy), distances))| {
+                // Wasser erreicht man vom Ufer aus
+                let way = |x: u16, y: u16| {
+                    if self.tile(x, y).tile_type.is_walkable() {
+                        Some(distances[index(x, y)]).filter(|d| *d != u32::MAX)
+                    } else {
+                        self.neighbours(x, y)
+                            .into_iter()
+                            .map(|(nx, ny)| distances[index(nx, ny)])
+                            .filter(|d| *d != u32::MAX)
+                            .min()
+                            .map(|d| d + 1)
+                    }
+                };
+                let mut report = StartReport {
+                    x: *sx,
+                    y: *sy,
+                    buildable: 0,
+                    trees: 0,
+                    stone: 0,
+                    deposits: [0; 5],
+                    nearest_opponent: starts
+                        .iter()
+                        .enumerate()
+                        .filter(|(j, _)| *j != i)
+                        .filter_map(|(_, (ox, oy))| way(*ox, *oy))
+                        .min(),
+                    nearest_trees: None,
+                    nearest_stone: None,
+                    nearest_deposits: [None; 5],
+                };
+                let nearest = |best: &mut Option<u32>, way: u32| {
+                    *best = Some(best.map_or(way, |b| b.min(way)));
+                };
+                for tile in self.tiles.iter().flatten() {
+                    let way = match way(tile.x, tile.y) {
+                        Some(way) => way,
+                        None => continue,
+                    };
+                    let near = Map::hex_distance(*sx, *sy, tile.x, tile.y) <= FAIRNESS_RADIUS;
+                    match tile.object {
+                        Some(MapObject::Tree) => {
+                            report.trees += near as u32;
+                            nearest(&mut report.nearest_trees, way);
+                        }
+                        Some(MapObject::Stone(n)) => {
+                            report.stone += if near { n as u32 } else { 0 };
+                            nearest(&mut report.nearest_stone, way);
+                        }
+                        None => {
+                            report.buildable +=
+                                (near & tile.tile_type.is_buildable() & tile.building.is_none())
+                                    as u32;
+                        }
+                        _ => {}
+                    }
+                    if let Some(d) = tile.deposit.filter(|d| d.amount > 0) {
+                        let k = d.kind.index();
+                        report.deposits[k] += if near { d.amount as u32 } else { 0 };
+                        nearest(&mut report.nearest_deposits[k], way);
+                    }
+                }
+                report
+            })
+            .collect();
+        FairnessReport { starts: reports }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{editor::blank_map, hextile::TileType};
+
+    // Zwei Starts, die sich bei einer halben Drehung der 40x40-Karte gegenüberliegen
+    const STARTS: [(u16, u16); 2] = [(12, 20), (27, 19)];
+
+    #[test]
+    fn equal_starts_are_fair() {
+        let map = blank_map(40, 40);
+        let report = map.analyze_fairness(&STARTS);
+        assert_eq!(report.starts[0].buildable, report.starts[1].buildable);
+        assert_eq!(report.starts[0].nearest_opponent, Some(15));
+        assert_eq!(report.unfair(), Vec::new());
+    }
+
+    #[test]
+    fn missing_trees_are_unfair() {
+        let mut map = blank_map(40, 40);
+        for y in 15..20 {
+            map.tile_mut(8, y).object = Some(MapObject::Tree);
+        }
+        let report = map.analyze_fairness(&STARTS);
+        assert_eq!(report.starts[0].trees, 5);
+        assert_eq!(report.starts[1].trees, 0);
+        let unfair = report.unfair();
+        assert!(unfair.contains(&(String::from("trees"), 100)));
+        assert!(report.to_string().contains("Unfair: "));
+    }
+
+    #[test]
+    fn water_cuts_the_way() {
+        let mut map = blank_map(40, 40);
+        for y in 0..40 {
+            map.tile_mut(20, y).tile_type = TileType::TtOcean;
+        }
+        let report = map.analyze_fairness(&STARTS);
+        assert_eq!(report.starts[0].nearest_opponent, None);
+        assert_eq!(map.walk_distances(12, 20)[27 * 40 + 19], u32::MAX);
+    }
+}
@@ -0,0 +1,885 @@
+use coffee::graphics::{Batch, Gpu, Image, Point, Rectangle, Sprite};
+use serde::Deserialize;
+use std::{
+    collections::BTreeMap,
+    convert::TryInto,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use super::{
+    assets::{self, Assets},
+    building::BuildingType,
+    hextile::TileType,
+    settler::Job,
+    world::World,
+};
+
+// Grafiken aus einer Siedler-4-Installation, Verzeichnis Gfx. Zu jeder Nummer gehören
+// mehrere Dateien:
+//   n.gfx  Bilder, lauflängenkodiert mit Farben aus einer Palette
+//   n.gil  Versatz jedes Bildes in n.gfx
+//   n.pil  Versatz der Palette jedes Bildes in n.pa6
+//   n.pa6  Paletten, Farben als RGB565
+//   n.jil  Versatz in n.dil für jeden Beruf, n.dil Versatz in n.gil für jede Richtung
+//   n.gh6  Landschaftstexturen mit 256×256 Pixeln in RGB565, n.gh5 dasselbe in RGB555
+// Jede Datei beginnt mit demselben Kopf: Kennung, drei Flags, Dateilänge, je u32.
+// Alle Zahlen little-endian.
+
+pub const GFX_DIR: &str = "Gfx";
+
+const GFX_MAGIC: u32 = 0x0004_1304;
+const HEADER_LEN: usize = 20;
+
+// Breite, Höhe, Versatz links und oben, Bildart, Füllbyte, unbekannt
+const IMAGE_HEADER_LEN: usize = 12;
+
+// Die ersten beiden Farbwerte im Bild sind Lauflängen statt Farben
+const RUN_TRANSPARENT: u8 = 0;
+const RUN_SHADOW: u8 = 1;
+const SHADOW: [u8; 4] = [0, 0, 0, 96];
+
+pub const TEXTURE_SIZE: u16 = 256;
+
+// Breite des Atlas, er wächst nach unten
+const ATLAS_WIDTH: u16 = 2048;
+
+// Mehr Bilder pro Laufanimation kommen nicht in den Atlas
+const MAX_FRAMES: usize = 12;
+
+// Siedler werden auf diese Höhe verkleinert, Gebäude auf die Breite ihrer Fläche
+const SETTLER_HEIGHT: f32 = 10.0;
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(data[offset..offset + 2].try_into().unwrap())
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn check_header(data: &[u8], name: &str) -> io::Result<()> {
+    if data.len() < HEADER_LEN {
+        return Err(invalid(format!("{} is too short", name)));
+    }
+    if read_u32(data, 0) != GFX_MAGIC {
+        return Err(invalid(format!("{} is not a graphics file", name)));
+    }
+    if read_u32(data, 16) as usize != data.len() {
+        return Err(invalid(format!(
+            "{} should have {} bytes, not {}",
+            name,
+            read_u32(data, 16),
+            data.len()
+        )));
+    }
+    Ok(())
+}
+
+fn entry_index(offset: u32, entry_len: usize, name: &str) -> io::Result<usize> {
+    (offset as usize)
+        .checked_sub(HEADER_LEN)
+        .filter(|o| o.is_multiple_of(entry_len))
+        .map(|o| o / entry_len)
+        .ok_or_else(|| invalid(format!("{} points between entries: {}", name, offset)))
+}
+
+fn read_table(data: &[u8], name: &str) -> io::Result<Vec<u32>> {
+    check_header(data, name)?;
+    Ok(data[HEADER_LEN..]
+        .chunks_exact(4)
+        .map(|c| u32::from_le_bytes(c.try_into().unwrap()))
+        .collect())
+}
+
+fn rgb565(color: u16) -> [u8; 4] {
+    let r = (color >> 11) & 0x1f;
+    let g = (color >> 5) & 0x3f;
+    let b = color & 0x1f;
+    [
+        (r * 255 / 31) as u8,
+        (g * 255 / 63) as u8,
+        (b * 255 / 31) as u8,
+        255,
+    ]
+}
+
+fn rgb555(color: u16) -> [u8; 4] {
+    let r = (color >> 10) & 0x1f;
+    let g = (color >> 5) & 0x1f;
+    let b = color & 0x1f;
+    [
+        (r * 255 / 31) as u8,
+        (g * 255 / 31) as u8,
+        (b * 255 / 31) as u8,
+        255,
+    ]
+}
+
+// Ein Bild mit Versatz zum Punkt, an dem es steht, Pixel als RGBA
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GfxImage {
+    pub width: u16,
+    pub height: u16,
+    pub left: i16,
+    pub top: i16,
+    pub pixels: Vec<[u8; 4]>,
+}
+
+impl GfxImage {
+    fn decode(data: &[u8], offset: usize, palette: &[[u8; 4]]) -> io::Result<Self> {
+        if offset + IMAGE_HEADER_LEN > data.len() {
+            return Err(invalid(format!("image at {} is cut off", offset)));
+        }
+        let width = read_u16(data, offset);
+        let height = read_u16(data, offset + 2);
+        let left = read_u16(data, offset + 4) as i16;
+        let top = read_u16(data, offset + 6) as i16;
+        let len = width as usize * height as usize;
+        // Nicht nach dem Kopf vorbelegen, der kann aus einer kaputten Datei stammen
+        let mut pixels = Vec::new();
+        let mut pos = offset + IMAGE_HEADER_LEN;
+        let byte = |pos: usize| {
+            data.get(pos)
+                .copied()
+                .ok_or_else(|| invalid(format!("image at {} is cut off", offset)))
+        };
+        while pixels.len() < len {
+            let value = byte(pos)?;
+            pos += 1;
+            match value {
+                RUN_TRANSPARENT | RUN_SHADOW => {
+                    let count = byte(pos)? as usize;
+                    pos += 1;
+                    let color = if value == RUN_SHADOW { SHADOW } else { [0; 4] };
+                    pixels.extend(std::iter::repeat_n(color, count));
+                }
+                _ => {
+                    pixels.push(*palette.get(value as usize).ok_or_else(|| {
+                        invalid(format!("image at {} leaves its palette", offset))
+                    })?)
+                }
+            }
+        }
+        if pixels.len() > len {
+            return Err(invalid(format!("image at {} runs past its end", offset)));
+        }
+        Ok(GfxImage {
+            width,
+            height,
+            left,
+            top,
+            pixels,
+        })
+    }
+}
+
+fn file_path(dir: &Path, number: u16, extension: &str) -> PathBuf {
+    dir.join(format!("{}.{}", number, extension))
+}
+
+// Eine Nummer aus dem Gfx-Verzeichnis. Die Bilder werden erst beim Zugriff entpackt,
+// weil manche Dateien tausende davon enthalten.
+pub struct GfxArchive {
+    pub number: u16,
+    gfx: Vec<u8>,
+    images: Vec<u32>,
+    palettes: Vec<u32>,
+    colors: Vec<[u8; 4]>,
+    jobs: Vec<u32>,
+    directions: Vec<u32>,
+}
+
+impl GfxArchive {
+    pub fn open(dir: &Path, number: u16) -> io::Result<Self> {
+        let read = |extension: &str| -> io::Result<Vec<u8>> {
+            let path = file_path(dir, number, extension);
+            fs::read(&path)
+                .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))
+        };
+        let name = |extension: &str| format!("{}.{}", number, extension);
+        let gfx = read("gfx")?;
+        check_header(&gfx, &name("gfx"))?;
+        let images = read_table(&read("gil")?, &name("gil"))?;
+        let palettes = read_table(&read("pil")?, &name("pil"))?;
+        if palettes.len() != images.len() {
+            return Err(invalid(format!(
+                "{} has {} palettes for {} images",
+                name("pil"),
+                palettes.len(),
+                images.len()
+            )));
+        }
+        let pa6 = read("pa6")?;
+        check_header(&pa6, &name("pa6"))?;
+        let colors = pa6[HEADER_LEN..]
+            .chunks_exact(2)
+            .map(|c| rgb565(u16::from_le_bytes([c[0], c[1]])))
+            .collect();
+        // Nur Dateien mit Siedlern haben Animationen
+        let (jobs, directions) = if file_path(dir, number, "jil").exists() {
+            (
+                read_table(&read("jil")?, &name("jil"))?,
+                read_table(&read("dil")?, &name("dil"))?,
+            )
+        } else {
+            (Vec::new(), Vec::new())
+        };
+        Ok(GfxArchive {
+            number,
+            gfx,
+            images,
+            palettes,
+            colors,
+            jobs,
+            directions,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.images.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.images.is_empty()
+    }
+
+    pub fn image(&self, index: usize) -> io::Result<GfxImage> {
+        let offset = *self
+            .images
+            .get(index)
+            .ok_or_else(|| invalid(format!("{}.gfx has no image {}", self.number, index)))?;
+        let palette = entry_index(self.palettes[index], 2, "pil")?;
+        let palette = self.colors.get(palette..).unwrap_or(&[]);
+        GfxImage::decode(&self.gfx, offset as usize, palette)
+    }
+
+    pub fn job_count(&self) -> usize {
+        self.jobs.len()
+    }
+
+    // Bildnummern einer Animation. Jeder Eintrag läuft bis zum Anfang des nächsten.
+    pub fn animation(&self, job: usize, direction: usize) -> io::Result<Vec<usize>> {
+        let first = entry_index(
+            *self
+                .jobs
+                .get(job)
+                .ok_or_else(|| invalid(format!("{}.jil has no job {}", self.number, job)))?,
+            4,
+            "jil",
+        )?;
+        let last = match self.jobs.get(job + 1) {
+            Some(next) => entry_index(*next, 4, "jil")?,
+            None => self.directions.len(),
+        };
+        if first + direction >= last.min(self.directions.len()) {
+            return Err(invalid(format!(
+                "job {} in {}.jil has no direction {}",
+                job, self.number, direction
+            )));
+        }
+        let start = entry_index(self.directions[first + direction], 4, "dil")?;
+        let end = match self.directions.get(first + direction + 1) {
+            Some(next) => entry_index(*next, 4, "dil")?,
+            None => self.images.len(),
+        };
+        Ok((start..end.min(self.images.len())).collect())
+    }
+}
+
+// Landschaftstexturen aus n.gh6, sonst n.gh5
+pub fn load_textures(dir: &Path, number: u16) -> io::Result<Vec<GfxImage>> {
+    let (data, extension) = match fs::read(file_path(dir, number, "gh6")) {
+        Ok(data) => (data, "gh6"),
+        Err(_) => (fs::read(file_path(dir, number, "gh5"))?, "gh5"),
+    };
+    let decode = if extension == "gh6" { rgb565 } else { rgb555 };
+    check_header(&data, &format!("{}.{}", number, extension))?;
+    let size = TEXTURE_SIZE as usize * TEXTURE_SIZE as usize * 2;
+    let body = &data[HEADER_LEN..];
+    if !body.len().is_multiple_of(size) {
+        return Err(invalid(format!(
+            "{}.{} is not a whole number of textures",
+            number, extension
+        )));
+    }
+    Ok(body
+        .chunks_exact(size)
+        .map(|texture| GfxImage {
+            width: TEXTURE_SIZE,
+            height: TEXTURE_SIZE,
+            left: 0,
+            top: 0,
+            pixels: texture
+                .chunks_exact(2)
+                .map(|c| decode(u16::from_le_bytes([c[0], c[1]])))
+                .collect(),
+        })
+        .collect())
+}
+
+// Welche Bilder aus der Installation wofür benutzt werden, res/data/gfx.ron
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct GfxManifest {
+    // Boden, Nummer der .gh6, Textur
+    pub ground: Vec<(TileType, u16, usize)>,
+    // Gebäude, Nummer der .gfx, Bild
+    pub buildings: Vec<(BuildingType, u16, usize)>,
+    // Beruf, Nummer der .gfx, Beruf in der .jil
+    pub settlers: Vec<(Job, u16, usize)>,
+}
+
+impl GfxManifest {
+    pub fn load(assets: &mut Assets) -> io::Result<Self> {
+        let data = assets.read_to_string(assets::GFX_MANIFEST)?;
+        ron::de::from_str(&data).map_err(|e| invalid(format!("{}: {}", assets::GFX_MANIFEST, e)))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SpriteKey {
+    Ground(TileType),
+    Building(BuildingType),
+    // Beruf und Bild der Laufanimation
+    Settler(Job, u8),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AtlasSprite {
+    pub x: u16,
+    pub y: u16,
+    pub width: u16,
+    pub height: u16,
+    pub left: i16,
+    pub top: i16,
+}
+
+// Alle Bilder in einem großen, damit der Renderer sie in einem Rutsch malen kann
+pub struct Atlas {
+    pub width: u16,
+    pub height: u16,
+    // RGBA, zeilenweise
+    pub pixels: Vec<u8>,
+    pub sprites: BTreeMap<SpriteKey, AtlasSprite>,
+}
+
+impl Atlas {
+    // Regale: die Bilder nach Höhe sortiert nebeneinander, ist eine Reihe voll, beginnt
+    // darunter die nächste. Bilder breiter als der Atlas passen nicht hinein.
+    pub fn pack(mut images: Vec<(SpriteKey, GfxImage)>) -> io::Result<Self> {
+        images.sort_by(|(ka, a), (kb, b)| b.height.cmp(&a.height).then(ka.cmp(kb)));
+        let mut sprites = BTreeMap::new();
+        let (mut x, mut y, mut row) = (0u32, 0u32, 0u32);
+        for (key, image) in &images {
+            if image.width > ATLAS_WIDTH {
+                return Err(invalid(format!(
+                    "{:?} is {} pixels wide, the atlas only {}",
+                    key, image.width, ATLAS_WIDTH
+                )));
+            }
+            if x + image.width as u32 > ATLAS_WIDTH as u32 {
+                x = 0;
+                y += row;
+                row = 0;
+            }
+            if y + image.height as u32 > u16::MAX as u32 {
+                return Err(invalid(String::from("images don't fit into one atlas")));
+            }
+            sprites.insert(
+                *key,
+                AtlasSprite {
+                    x: x as u16,
+                    y: y as u16,
+                    width: image.width,
+                    height: image.height,
+                    left: image.left,
+                    top: image.top,
+                },
+            );
+            x += image.width as u32;
+            row = row.max(image.height as u32);
+        }
+        let height = (y + row) as u16;
+        let mut pixels = vec![0; ATLAS_WIDTH as usize * height as usize * 4];
+        for (key, image) in &images {
+            let sprite = sprites[key];
+            for (row, line) in image.pixels.chunks(image.width.max(1) as usize).enumerate() {
+                let start =
+                    ((sprite.y as usize + row) * ATLAS_WIDTH as usize + sprite.x as usize) * 4;
+                for (i, pixel) in line.iter().enumerate() {
+                    pixels[start + i * 4..start + i * 4 + 4].copy_from_slice(pixel);
+                }
+            }
+        }
+        Ok(Atlas {
+            width: ATLAS_WIDTH,
+            height,
+            pixels,
+            sprites,
+        })
+    }
+
+    // Sucht die Bilder aus dem Manifest in der Installation zusammen
+    pub fn build(install_dir: &Path, manifest: &GfxManifest) -> io::Result<Self> {
+        let dir = install_dir.join(GFX_DIR);
+        let mut archives: BTreeMap<u16, GfxArchive> = BTreeMap::new();
+        for number in manifest
+            .buildings
+            .iter()
+            .map(|(_, number, _)| number)
+            .chain(manifest.settlers.iter().map(|(_, number, _)| number))
+        {
+            if !archives.contains_key(number) {
+                archives.insert(*number, GfxArchive::open(&dir, *number)?);
+            }
+        }
+        let mut images = Vec::new();
+        let mut textures: BTreeMap<u16, Vec<GfxImage>> = BTreeMap::new();
+        for (tt, number, index) in &manifest.ground {
+            if !textures.contains_key(number) {
+                textures.insert(*number, load_textures(&dir, *number)?);
+            }
+            let texture = textures[number]
+                .get(*index)
+                .ok_or_else(|| invalid(format!("{}.gh6 has no texture {}", number, index)))?;
+            images.push((SpriteKey::Ground(*tt), texture.clone()));
+        }
+        for (bt, number, index) in &manifest.buildings {
+            images.push((SpriteKey::Building(*bt), archives[number].image(*index)?));
+        }
+        for (job, number, index) in &manifest.settlers {
+            let archive = &archives[number];
+            for (frame, image) in archive
+                .animation(*index, 0)?
+                .into_iter()
+                .take(MAX_FRAMES)
+                .enumerate()
+            {
+                images.push((SpriteKey::Settler(*job, frame as u8), archive.image(image)?));
+            }
+        }
+        Self::pack(images)
+    }
+
+    pub fn frames(&self, job: Job) -> u8 {
+        self.sprites
+            .range(SpriteKey::Settler(job, 0)..=SpriteKey::Settler(job, u8::MAX))
+            .count() as u8
+    }
+}
+
+// Der Atlas auf der Grafikkarte
+pub struct SpriteSheet {
+    pub sprites: BTreeMap<SpriteKey, AtlasSprite>,
+    frames: BTreeMap<Job, u8>,
+    pub batch: Batch,
+}
+
+impl SpriteSheet {
+    pub fn upload(gpu: &mut Gpu, atlas: Atlas) -> coffee::Result<Self> {
+        let frames = atlas
+            .sprites
+            .keys()
+            .filter_map(|key| match key {
+                SpriteKey::Settler(job, _) => Some((*job, atlas.frames(*job))),
+                _ => None,
+            })
+            .collect();
+        let image =
+            image::RgbaImage::from_raw(atlas.width as u32, atlas.height as u32, atlas.pixels)
+                .expect("atlas pixels match its size");
+        Ok(SpriteSheet {
+            sprites: atlas.sprites,
+            frames,
+            batch: Batch::new(Image::from_image(
+                gpu,
+                &image::DynamicImage::ImageRgba8(image),
+            )?),
+        })
+    }
+
+    // Auf die gewünschte Breite oder Höhe skaliert, der Versatz des Bildes zeigt auf point
+    fn add(&mut self, key: SpriteKey, point: Point, size: f32, by_height: bool) {
+        let sprite = match self.sprites.get(&key) {
+            Some(sprite) => *sprite,
+            None => return,
+        };
+        let scale = if by_height {
+            size / sprite.height.max(1) as f32
+        } else {
+            size / sprite.width.max(1) as f32
+        };
+        self.batch.add(Sprite {
+            source: Rectangle {
+                x: sprite.x,
+                y: sprite.y,
+                width: sprite.width,
+                height: sprite.height,
+            },
+            position: Point::new(
+                point.coords.x + sprite.left as f32 * scale,
+                point.coords.y + sprite.top as f32 * scale,
+            ),
+            scale: (scale, scale),
+        });
+    }
+}
+
+// Bilder aus dem Original über den einfachen Formen, soweit es welche gibt
+impl World {
+    pub fn draw_sprites(&mut self, param: &mut crate::DrawParameter, sheet: &mut SpriteSheet) {
+        sheet.batch.clear();
+        let (ox, oy) = (param.camera.cameraoffset_x, param.camera.cameraoffset_y);
+        let buildings = match param.viewer {
+            Some(player) => self.known_buildings(player),
+            None => self.buildings.keys().copied().collect(),
+        };
+        for id in buildings {
+            let b = &self.buildings[&id];
+            if b.site().is_some() {
+                continue;
+            }
+            let (bt, x, y) = (b.building_type, b.x, b.y);
+            let center = self.footprint_center(bt.size(), x, y, ox, oy);
+            sheet.add(
+                SpriteKey::Building(bt),
+                center,
+                super::world::building_radius(bt.size()) * 2.0,
+                false,
+            );
+        }
+        let settlers: Vec<(Job, u16, u16)> = match param.viewer {
+            Some(player) => self.visible_settlers(player),
+            None => self.settlers.values().collect(),
+        }
+        .into_iter()
+        .map(|s| (s.job, s.x, s.y))
+        .collect();
+        for (job, x, y) in settlers {
+            let frames = match sheet.frames.get(&job) {
+                Some(frames) => *frames as u64,
+                None => continue,
+            };
+            let frame = (self.tick / 2 % frames) as u8;
+            let center = self.map.tile(x, y).get_center(ox, oy);
+            sheet.add(SpriteKey::Settler(job, frame), center, SETTLER_HEIGHT, true);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+
+    // Verzeichnis einer Installation des Originals für install_files_can_be_read
+    const INSTALL_ENV: &str = "SETTLERS_RS_S4_DIR";
+
+    // Versatz in die Datei zu einem Eintrag in einer Liste hinter dem Kopf
+    fn entry_offset(index: usize, entry_len: usize) -> u32 {
+        (HEADER_LEN + index * entry_len) as u32
+    }
+
+    fn write_header(data: &mut Vec<u8>) {
+        for value in &[GFX_MAGIC, 0, 0, 0, 0] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+
+    // Trägt die Länge ein, wenn alles geschrieben ist
+    fn finish_header(mut data: Vec<u8>) -> Vec<u8> {
+        let len = (data.len() as u32).to_le_bytes();
+        data[16..20].copy_from_slice(&len);
+        data
+    }
+
+    fn encode_table(entries: &[u32]) -> Vec<u8> {
+        let mut data = Vec::new();
+        write_header(&mut data);
+        for entry in entries {
+            data.extend_from_slice(&entry.to_le_bytes());
+        }
+        finish_header(data)
+    }
+
+    fn to_rgb565(color: [u8; 4]) -> u16 {
+        ((color[0] as u16 >> 3) << 11) | ((color[1] as u16 >> 2) << 5) | (color[2] as u16 >> 3)
+    }
+
+    fn encode_palette(colors: &[[u8; 4]]) -> Vec<u8> {
+        let mut data = Vec::new();
+        write_header(&mut data);
+        for color in colors {
+            data.extend_from_slice(&to_rgb565(*color).to_le_bytes());
+        }
+        finish_header(data)
+    }
+
+    fn encode_textures(textures: &[GfxImage]) -> Vec<u8> {
+        let mut data = Vec::new();
+        write_header(&mut data);
+        for texture in textures {
+            for pixel in &texture.pixels {
+                data.extend_from_slice(&to_rgb565(*pixel).to_le_bytes());
+            }
+        }
+        finish_header(data)
+    }
+
+    impl GfxImage {
+        // Gegenstück zu decode. Farben, die nicht in der Palette stehen, werden durchsichtig.
+        // Mehr als 254 Farben passen nicht neben die beiden Lauflängen in ein Byte.
+        fn encode(&self, palette: &[[u8; 4]]) -> io::Result<Vec<u8>> {
+            let mut data = Vec::new();
+            for value in &[self.width, self.height, self.left as u16, self.top as u16] {
+                data.extend_from_slice(&value.to_le_bytes());
+            }
+            data.extend_from_slice(&[0; IMAGE_HEADER_LEN - 8]);
+            let mut pixels = self.pixels.iter().peekable();
+            while let Some(pixel) = pixels.next() {
+                let run = match *pixel {
+                    SHADOW => Some(RUN_SHADOW),
+                    p => match palette.iter().skip(2).position(|c| *c == p) {
+                        Some(i) => {
+                            let value = u8::try_from(i + 2).map_err(|_| {
+                                invalid(format!("palette index {} does not fit in a byte", i + 2))
+                            })?;
+                            data.push(value);
+                            None
+                        }
+                        None => Some(RUN_TRANSPARENT),
+                    },
+                };
+                if let Some(run) = run {
+                    let color = *pixel;
+                    let mut count = 1;
+                    while (count < u8::MAX) && pixels.next_if(|p| **p == color).is_some() {
+                        count += 1;
+                    }
+                    data.extend_from_slice(&[run, count]);
+                }
+            }
+            Ok(data)
+        }
+    }
+
+    // Schreibt kleine Dateien in allen Formaten in ein Verzeichnis, liest sie wie eine
+    // Installation und vergleicht. Kaputte Dateien müssen als Fehler ankommen.
+    fn check_round_trip(dir: &Path) -> io::Result<()> {
+        let gfx_dir = dir.join(GFX_DIR);
+        fs::create_dir_all(&gfx_dir)?;
+        let mut palette: Vec<[u8; 4]> = vec![[0; 4], [0; 4]];
+        palette.extend((0..20u8).map(|i| rgb565(to_rgb565([i * 12, 255 - i * 12, i * 6, 255]))));
+        let image = |width: u16, height: u16, seed: usize| GfxImage {
+            width,
+            height,
+            left: -(width as i16) / 2,
+            top: -(height as i16),
+            pixels: (0..width as usize * height as usize)
+                .map(|i| match (i * 7 + seed) % 11 {
+                    0 | 1 => [0; 4],
+                    2 => SHADOW,
+                    n => palette[n + seed % 8],
+                })
+                .collect(),
+        };
+        // Zwei Gebäude und ein Beruf mit zwei Richtungen zu drei und zwei Bildern
+        let images: Vec<GfxImage> = (0..7)
+            .map(|i| image(10 + i as u16 * 3, 8 + i as u16, i))
+            .collect();
+        let mut gfx = Vec::new();
+        write_header(&mut gfx);
+        let mut offsets = Vec::new();
+        for image in &images {
+            offsets.push(gfx.len() as u32);
+            gfx.extend(image.encode(&palette)?);
+        }
+        let number = 20;
+        fs::write(file_path(&gfx_dir, number, "gfx"), finish_header(gfx))?;
+        fs::write(file_path(&gfx_dir, number, "gil"), encode_table(&offsets))?;
+        fs::write(
+            file_path(&gfx_dir, number, "pil"),
+            encode_table(&vec![entry_offset(0, 2); images.len()]),
+        )?;
+        fs::write(file_path(&gfx_dir, number, "pa6"), encode_palette(&palette))?;
+        fs::write(
+            file_path(&gfx_dir, number, "jil"),
+            encode_table(&[entry_offset(0, 4)]),
+        )?;
+        fs::write(
+            file_path(&gfx_dir, number, "dil"),
+            encode_table(&[entry_offset(2, 4), entry_offset(5, 4)]),
+        )?;
+        let texture = GfxImage {
+            width: TEXTURE_SIZE,
+            height: TEXTURE_SIZE,
+            left: 0,
+            top: 0,
+            pixels: (0..TEXTURE_SIZE as usize * TEXTURE_SIZE as usize)
+                .map(|i| palette[2 + i % 20])
+                .collect(),
+        };
+        fs::write(
+            file_path(&gfx_dir, 2, "gh6"),
+            encode_textures(std::slice::from_ref(&texture)),
+        )?;
+
+        let archive = GfxArchive::open(&gfx_dir, number)?;
+        for (i, image) in images.iter().enumerate() {
+            if archive.image(i)? != *image {
+                return Err(invalid(format!("image {} differs", i)));
+            }
+        }
+        if (archive.animation(0, 0)? != vec![2, 3, 4]) | (archive.animation(0, 1)? != vec![5, 6]) {
+            return Err(invalid(String::from("animations differ")));
+        }
+        if archive.animation(0, 2).is_ok() | archive.image(7).is_ok() {
+            return Err(invalid(String::from("read past the last entry")));
+        }
+        if load_textures(&gfx_dir, 2)? != vec![texture] {
+            return Err(invalid(String::from("texture differs")));
+        }
+
+        let manifest = GfxManifest {
+            ground: vec![(TileType::TtGras, 2, 0)],
+            buildings: vec![
+                (BuildingType::Castle, number, 0),
+                (BuildingType::Woodcutter, number, 1),
+            ],
+            settlers: vec![(Job::Carrier, number, 0)],
+        };
+        let atlas = Atlas::build(dir, &manifest)?;
+        if (atlas.sprites.len() != 6) | (atlas.frames(Job::Carrier) != 3) {
+            return Err(invalid(format!(
+                "atlas has {} sprites",
+                atlas.sprites.len()
+            )));
+        }
+        let castle = atlas.sprites[&SpriteKey::Building(BuildingType::Castle)];
+        let at = |x: u16, y: u16| {
+            let i = ((castle.y + y) as usize * atlas.width as usize + (castle.x + x) as usize) * 4;
+            [
+                atlas.pixels[i],
+                atlas.pixels[i + 1],
+                atlas.pixels[i + 2],
+                atlas.pixels[i + 3],
+            ]
+        };
+        if (0..castle.height).any(|y| {
+            (0..castle.width).any(|x| at(x, y) != images[0].pixels[(y * castle.width + x) as usize])
+        }) {
+            return Err(invalid(String::from("castle is not copied into the atlas")));
+        }
+
+        // Abgeschnittenes Bild und falsche Länge im Kopf
+        let gfx = fs::read(file_path(&gfx_dir, number, "gfx"))?;
+        fs::write(
+            file_path(&gfx_dir, number, "gfx"),
+            finish_header(gfx[..gfx.len() - 3].to_vec()),
+        )?;
+        if GfxArchive::open(&gfx_dir, number)?
+            .image(images.len() - 1)
+            .is_ok()
+        {
+            return Err(invalid(String::from("cut off image was read")));
+        }
+        fs::write(file_path(&gfx_dir, number, "gfx"), &gfx[..gfx.len() - 1])?;
+        if GfxArchive::open(&gfx_dir, number).is_ok() {
+            return Err(invalid(String::from("file with wrong length was read")));
+        }
+        fs::remove_dir_all(dir)
+    }
+
+    fn blank(width: u16) -> (SpriteKey, GfxImage) {
+        let image = GfxImage {
+            width,
+            height: 2,
+            left: 0,
+            top: 0,
+            pixels: vec![[0; 4]; width as usize * 2],
+        };
+        (SpriteKey::Building(BuildingType::Castle), image)
+    }
+
+    #[test]
+    fn synthetic_files_round_trip() {
+        check_round_trip(&std::env::temp_dir().join("settlers_rs_gfx_test")).unwrap();
+    }
+
+    #[test]
+    fn palettes_must_fit_in_a_byte() {
+        let mut palette = vec![[0; 4]; 2];
+        palette.extend((0..300u16).map(|i| [(i % 256) as u8, (i / 256) as u8, 0, 255]));
+        let image = |color: [u8; 4]| GfxImage {
+            width: 1,
+            height: 1,
+            left: 0,
+            top: 0,
+            pixels: vec![color],
+        };
+        let data = image(palette[255]).encode(&palette).unwrap();
+        assert_eq!(
+            GfxImage::decode(&data, 0, &palette).unwrap(),
+            image(palette[255])
+        );
+        assert!(image(palette[256]).encode(&palette).is_err());
+    }
+
+    #[test]
+    fn image_header_is_not_trusted() {
+        let mut data = Vec::new();
+        for value in [u16::MAX, u16::MAX, 0, 0] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        data.resize(IMAGE_HEADER_LEN, 0);
+        assert!(GfxImage::decode(&data, 0, &[[0; 4]; 2]).is_err());
+    }
+
+    #[test]
+    fn atlas_rejects_wide_images() {
+        assert!(Atlas::pack(vec![blank(ATLAS_WIDTH)]).is_ok());
+        assert!(Atlas::pack(vec![blank(ATLAS_WIDTH + 1)]).is_err());
+    }
+
+    // Läuft nur, wenn INSTALL_ENV gesetzt ist
+    #[test]
+    fn install_files_can_be_read() {
+        let dir = match std::env::var_os(INSTALL_ENV) {
+            Some(install) => Path::new(&install).join(GFX_DIR),
+            None => return,
+        };
+        let mut files: Vec<(u16, String)> = fs::read_dir(&dir)
+            .unwrap()
+            .filter_map(|e| e.ok())
+            .filter_map(|e| {
+                let path = e.path();
+                let number = path.file_stem()?.to_str()?.parse().ok()?;
+                let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+                Some((number, extension))
+            })
+            .collect();
+        files.sort();
+        for (number, extension) in files {
+            match extension.as_str() {
+                "gfx" => {
+                    let archive = GfxArchive::open(&dir, number).unwrap();
+                    for i in 0..archive.len() {
+                        if let Err(e) = archive.image(i) {
+                            panic!("{}.gfx image {}: {}", number, i, e);
+                        }
+                    }
+                }
+                "gh5" | "gh6" => {
+                    if let Err(e) = load_textures(&dir, number) {
+                        panic!("{}.{}: {}", number, extension, e);
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+}
@@ -0,0 +1,70 @@
+use coffee::graphics::Color;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
+pub enum Good {
+    Log,
+    Board,
+    Stone,
+    Grain,
+    Flour,
+    Bread,
+    Fish,
+    Coal,
+    IronOre,
+    GoldOre,
+    Sulfur,
+    Hammer,
+    Axe,
+    Pickaxe,
+    Saw,
+    Scythe,
+    FishingRod,
+    Sword,
+}
+
+impl Good {
+    pub fn name(self) -> &'static str {
+        match self {
+            Good::Log => "Log",
+            Good::Board => "Board",
+            Good::Stone => "Stone",
+            Good::Grain => "Grain",
+            Good::Flour => "Flour",
+            Good::Bread => "Bread",
+            Good::Fish => "Fish",
+            Good::Coal => "Coal",
+            Good::IronOre => "Iron ore",
+            Good::GoldOre => "Gold ore",
+            Good::Sulfur => "Sulfur",
+            Good::Hammer => "Hammer",
+            Good::Axe => "Axe",
+            Good::Pickaxe => "Pickaxe",
+            Good::Saw => "Saw",
+            Good::Scythe => "Scythe",
+            Good::FishingRod => "Fishing rod",
+            Good::Sword => "Sword",
+        }
+    }
+
+    pub fn color(self) -> Color {
+        match self {
+            Good::Log => Color::from_rgb(110, 70, 30),
+            Good::Board => Color::from_rgb(220, 170, 100),
+            Good::Stone => Color::from_rgb(170, 170, 170),
+            Good::Grain => Color::from_rgb(240, 220, 80),
+            Good::Flour => Color::from_rgb(250, 250, 240),
+            Good::Bread => Color::from_rgb(180, 110, 40),
+            Good::Fish => Color::from_rgb(120, 160, 200),
+            Good::Coal => Color::from_rgb(20, 20, 20),
+            Good::IronOre => Color::from_rgb(160, 60, 40),
+            Good::GoldOre => Color::from_rgb(255, 215, 0),
+            Good::Sulfur => Color::from_rgb(200, 230, 40),
+            Good::Hammer | Good::Axe | Good::Pickaxe | Good::Saw | Good::Scythe => {
+                Color::from_rgb(90, 90, 110)
+            }
+            Good::FishingRod => Color::from_rgb(150, 110, 60),
+            Good::Sword => Color::from_rgb(200, 200, 220),
+        }
+    }
+}
@@ -1,9 +1,21 @@
-use coffee::graphics::{Color, Point, Rectangle, Shape::Polyline};
+use coffee::graphics::{
+    Color, Point,
+    Shape::{Circle, Polyline},
+};
 use rand::{
     distributions::{Distribution, Standard},
     Rng,
 };
+use serde::{Deserialize, Serialize};
+
+use super::{
+    building::BuildingId,
+    player::PlayerId,
+    resources::Deposit,
+    road::{FlagId, RoadId},
+};
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum TileType {
     TtDirt,
     TtSand,
@@ -28,6 +40,65 @@ fn color_by_tiletype(tt: &mut TileType) -> Color {
     }
 }
 
+pub const TILE_TYPES: [TileType; 8] = [
+    TileType::TtGras,
+    TileType::TtDirt,
+    TileType::TtSand,
+    TileType::TtMountain,
+    TileType::TtSnow,
+    TileType::TtOcean,
+    TileType::TtRiver,
+    TileType::TtRoad,
+];
+
+impl TileType {
+    pub fn name(self) -> &'static str {
+        match self {
+            TileType::TtDirt => "Dirt",
+            TileType::TtSand => "Sand",
+            TileType::TtMountain => "Mountain",
+            TileType::TtRoad => "Road",
+            TileType::TtOcean => "Ocean",
+            TileType::TtRiver => "River",
+            TileType::TtGras => "Grass",
+            TileType::TtSnow => "Snow",
+        }
+    }
+
+    // Auf diesen Böden dürfen normale Gebäude stehen
+    pub fn is_buildable(self) -> bool {
+        matches!(self, TileType::TtDirt | TileType::TtSand | TileType::TtGras)
+    }
+
+    // Hier können Siedler laufen und Straßen gebaut werden
+    pub fn is_walkable(self) -> bool {
+        !matches!(self, TileType::TtOcean | TileType::TtRiver)
+    }
+}
+
+// Was auf einem Tile steht, abgesehen von Gebäuden, Flaggen und Straßen
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MapObject {
+    Tree,
+    // Junger Baum, Tick an dem er ausgesät wurde
+    Sapling { planted: u64 },
+    // Restliche Steine
+    Stone(u8),
+    // Tick, an dem das Feld gesät wurde
+    Field { planted: u64 },
+}
+
+impl MapObject {
+    pub fn color(self) -> Color {
+        match self {
+            MapObject::Tree => Color::from_rgb(0, 90, 20),
+            MapObject::Sapling { .. } => Color::from_rgb(60, 150, 60),
+            MapObject::Stone(_) => Color::from_rgb(190, 190, 190),
+            MapObject::Field { .. } => Color::from_rgb(210, 190, 70),
+        }
+    }
+}
+
 impl Distribution<TileType> for Standard {
     fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> TileType {
         match rng.gen_range(0, 8) {
@@ -44,12 +115,22 @@ impl Distribution<TileType> for Standard {
     }
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Tile {
     pub tile_type: TileType,
     pub x: u16,
     pub y: u16,
     pub width: u16,
     pub height: u16,
+    pub elevation: u8,
+    pub owner: Option<PlayerId>,
+    pub building: Option<BuildingId>,
+    pub flag: Option<FlagId>,
+    // Nur für Tiles zwischen den Flaggen
+    pub road: Option<RoadId>,
+    pub object: Option<MapObject>,
+    // Fische im Wasser, Erz im Berg
+    pub deposit: Option<Deposit>,
 }
 
 impl Tile {
@@ -60,6 +141,13 @@ impl Tile {
             y: _y,
             width: crate::FIELDWIDTH,
             height: crate::FIELDHEIGHT,
+            elevation: 0,
+            owner: None,
+            building: None,
+            flag: None,
+            road: None,
+            object: None,
+            deposit: None,
         }
     }
 }
@@ -75,6 +163,20 @@ impl crate::Drawable for Tile {
             },
             color_by_tiletype(&mut self.tile_type),
         );
+        if let Some(object) = self.object {
+            let center = self.get_center(param.camera.cameraoffset_x, param.camera.cameraoffset_y);
+            let size = match object {
+                MapObject::Sapling { .. } => 0.15,
+                _ => 0.3,
+            };
+            param.camera.mesh.fill(
+                Circle {
+                    center,
+                    radius: self.height as f32 * size,
+                },
+                object.color(),
+            );
+        }
         //}
     }
 }
@@ -85,18 +187,18 @@ impl Tile {
         self.tile_type = rand::random();
     }
 
-    fn get_hex_point_vec(&mut self, x_offset: i16, y_offset: i16) -> Vec<Point> {
+    pub fn get_hex_point_vec(&self, x_offset: i16, y_offset: i16) -> Vec<Point> {
         let mut polypoints: Vec<Point> = Vec::new();
         for n in 0..6 {
             polypoints.push(self.pointy_hex_corner(n, x_offset, y_offset));
         }
-        return polypoints;
+        polypoints
     }
 
-    fn pointy_hex_corner(&mut self, i: i16, x_offset: i16, y_offset: i16) -> Point {
+    fn pointy_hex_corner(&self, i: i16, x_offset: i16, y_offset: i16) -> Point {
         let angle_deg = 60 * i - 30;
         let angle_rad = std::f32::consts::PI / 180f32 * (angle_deg as f32);
-        return Point::new(
+        Point::new(
             (self.x * self.width) as f32 //center
                 + self.width as f32 * angle_rad.cos() / 3f32.sqrt() //size
                 + x_offset as f32 // offset
@@ -106,11 +208,11 @@ impl Tile {
                 + self.height as f32 * angle_rad.sin() / 2f32
                 + y_offset as f32
                 + self.height as f32 / 2f32,
-        );
+        )
     }
 
-    pub fn get_center(&mut self, x_offset: i16, y_offset: i16) -> Point {
-        return Point::new(
+    pub fn get_center(&self, x_offset: i16, y_offset: i16) -> Point {
+        Point::new(
             self.x as f32 * self.width as f32 //center
                 + x_offset as f32 // offset
                 + self.y as f32 / 2f32 * self.width as f32 // row-offset
@@ -118,6 +220,6 @@ impl Tile {
             self.y as f32 * self.height as f32 * 0.75f32 // center
                 + y_offset as f32 // offset
                 + self.height as f32 / 2f32, // erstes tile
-        );
+        )
     }
 }
@@ -1,7 +1,9 @@
 use coffee::graphics::Point;
+use serde::{Deserialize, Serialize};
 
 use super::hextile::*;
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Map {
     pub width: u16,
     pub height: u16,
@@ -9,31 +11,22 @@ pub struct Map {
 }
 
 impl Map {
-    pub fn new(_x: u16, _y: u16) -> Self {
-        let mut a = Vec::<Vec<Tile>>::with_capacity(_x.into());
-        for x in 0.._x {
-            let mut b = Vec::<Tile>::with_capacity(_y.into());
-            for y in 0.._y {
-                let tt: TileType = rand::random();
-                let t = Tile::new(tt, x, y);
-                b.push(t)
-            }
-            a.push(b);
-        }
-
-        Map {
-            width: _x,
-            height: _y,
-            tiles: a,
-        }
-    }
-
     pub fn get_tile_from_coords(
         &mut self,
         pointa: Point,
         x_offset: i16,
         y_offset: i16,
     ) -> &mut Tile {
+        let (x, y) = self.get_tile_pos_from_coords(pointa, x_offset, y_offset);
+        &mut self.tiles[x as usize][y as usize]
+    }
+
+    pub fn get_tile_pos_from_coords(
+        &mut self,
+        pointa: Point,
+        x_offset: i16,
+        y_offset: i16,
+    ) -> (u16, u16) {
         let x = pointa.coords.x;
         let y = pointa.coords.y;
         let width = self.tiles[0][0].width as f32;
@@ -59,9 +52,6 @@ impl Map {
             schaetz_y = self.height as f32 - 0.51f32;
         }
 
-        println!("Click: {}, {}", pointa.coords.x, pointa.coords.y);
-        println!("Schätzwerte: {} {}", schaetz_x, schaetz_y);
-
         // 4 hexagons:
         let i1 = schaetz_x.floor();
         let j1 = schaetz_y.floor();
@@ -116,38 +106,26 @@ impl Map {
             }
         }
 
-        let resulthex;
-
         if (dist1 <= dist2) & (dist1 <= dist3) & (dist1 <= dist4) {
-            resulthex = &mut self.tiles[i1 as i16 as usize][j1 as i16 as usize];
+            (i1 as i16 as u16, j1 as i16 as u16)
         } else if (dist2 <= dist1) & (dist2 <= dist3) & (dist2 <= dist4) {
-            resulthex = &mut self.tiles[i2 as i16 as usize][j2 as i16 as usize];
+            (i2 as i16 as u16, j2 as i16 as u16)
         } else if (dist3 <= dist1) & (dist3 <= dist2) & (dist3 <= dist4) {
-            resulthex = &mut self.tiles[i3 as i16 as usize][j3 as i16 as usize];
+            (i3 as i16 as u16, j3 as i16 as u16)
         } else {
-            resulthex = &mut self.tiles[i4 as i16 as usize][j4 as i16 as usize];
+            (i4 as i16 as u16, j4 as i16 as u16)
         }
-
-        println!("Distanzen: {}, {}, {}, {}", dist1, dist2, dist3, dist4);
-        println!(
-            "Center Winner: {}, {}",
-            resulthex.get_center(x_offset, y_offset).coords.x,
-            resulthex.get_center(x_offset, y_offset).coords.y
-        );
-        println!();
-
-        return resulthex;
     }
 
     fn distance_between_two_points(&mut self, point_a: &Point, point_b: &Point) -> f32 {
         let y_factor = crate::FIELDWIDTH as f32 / crate::FIELDHEIGHT as f32;
 
-        let x1 = point_a.coords.x as f32;
-        let x2 = point_b.coords.x as f32;
+        let x1 = point_a.coords.x;
+        let x2 = point_b.coords.x;
         let part1 = (x1 - x2).powi(2i32);
 
-        let y1 = point_a.coords.y as f32;
-        let y2 = point_b.coords.y as f32;
+        let y1 = point_a.coords.y;
+        let y2 = point_b.coords.y;
         let part2 = (y1 * y_factor - y2 * y_factor).powi(2i32);
 
         (part1 + (part2)).sqrt()
@@ -156,8 +134,80 @@ impl Map {
     fn valid_hex(&mut self, x: i16, y: i16) -> bool {
         (x < self.width as i16) & (y < self.height as i16) & (x >= 0i16) & (y >= 0i16)
     }
+
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        (x < self.width as i32) & (y < self.height as i32) & (x >= 0) & (y >= 0)
+    }
+
+    pub fn tile(&self, x: u16, y: u16) -> &Tile {
+        &self.tiles[x as usize][y as usize]
+    }
+
+    pub fn tile_mut(&mut self, x: u16, y: u16) -> &mut Tile {
+        &mut self.tiles[x as usize][y as usize]
+    }
+
+    // Axiale Koordinaten: x = q, y = r (pointy-top, siehe redblobgames)
+    pub fn hex_distance(ax: u16, ay: u16, bx: u16, by: u16) -> u16 {
+        let dq = ax as i32 - bx as i32;
+        let dr = ay as i32 - by as i32;
+        ((dq.abs() + dr.abs() + (dq + dr).abs()) / 2) as u16
+    }
+
+    // Versetzt die Koordinate, None wenn sie außerhalb der Karte landet
+    pub fn offset(&self, x: u16, y: u16, dx: i32, dy: i32) -> Option<(u16, u16)> {
+        let nx = x as i32 + dx;
+        let ny = y as i32 + dy;
+        if self.contains(nx, ny) {
+            Some((nx as u16, ny as u16))
+        } else {
+            None
+        }
+    }
+
+    pub fn neighbours(&self, x: u16, y: u16) -> Vec<(u16, u16)> {
+        HEX_DIRECTIONS
+            .iter()
+            .filter_map(|(dx, dy)| self.offset(x, y, *dx, *dy))
+            .collect()
+    }
+
+    // Alle Tiles mit genau `radius` Abstand
+    pub fn ring(&self, x: u16, y: u16, radius: u16) -> Vec<(u16, u16)> {
+        if radius == 0 {
+            return vec![(x, y)];
+        }
+        let r = radius as i32;
+        let mut result = Vec::new();
+        // Start oben links und dann einmal im Kreis laufen
+        let mut pos = (
+            x as i32 + HEX_DIRECTIONS[4].0 * r,
+            y as i32 + HEX_DIRECTIONS[4].1 * r,
+        );
+        for (dx, dy) in HEX_DIRECTIONS.iter() {
+            for _ in 0..r {
+                if self.contains(pos.0, pos.1) {
+                    result.push((pos.0 as u16, pos.1 as u16));
+                }
+                pos = (pos.0 + dx, pos.1 + dy);
+            }
+        }
+        result
+    }
+
+    // Alle Tiles mit Abstand <= radius, von innen nach außen
+    pub fn spiral(&self, x: u16, y: u16, radius: u16) -> Vec<(u16, u16)> {
+        let mut result = Vec::new();
+        for r in 0..=radius {
+            result.append(&mut self.ring(x, y, r));
+        }
+        result
+    }
 }
 
+// Nachbarn im Uhrzeigersinn, beginnend rechts
+pub const HEX_DIRECTIONS: [(i32, i32); 6] = [(1, 0), (0, 1), (-1, 1), (-1, 0), (0, -1), (1, -1)];
+
 impl crate::Drawable for Map {
     fn draw(&mut self, param: &mut crate::DrawParameter) {
         for a in &mut self.tiles {
@@ -0,0 +1,121 @@
+use super::{
+    hextile::{Tile, TileType},
+    map::Map,
+    random::GameRng,
+    symmetry::Symmetry,
+};
+
+// Anteile der Böden beim Würfeln, bevor geglättet wird
+const TERRAIN_WEIGHTS: [(TileType, u32); 6] = [
+    (TileType::TtGras, 50),
+    (TileType::TtDirt, 10),
+    (TileType::TtSand, 5),
+    (TileType::TtMountain, 15),
+    (TileType::TtOcean, 15),
+    (TileType::TtSnow, 5),
+];
+
+// So oft nimmt jedes Tile den häufigsten Boden seiner Nachbarn an
+const SMOOTH_PASSES: usize = 3;
+
+fn random_terrain(rng: &mut GameRng) -> TileType {
+    let total: u32 = TERRAIN_WEIGHTS.iter().map(|(_, w)| w).sum();
+    let mut roll = rng.range(0, total);
+    for (tt, weight) in TERRAIN_WEIGHTS.iter() {
+        if roll < *weight {
+            return *tt;
+        }
+        roll -= weight;
+    }
+    TileType::TtGras
+}
+
+impl Map {
+    // Karte aus einem Startwert: gleiche Zahl, gleiche Karte. Statt Rauschen entstehen
+    // zusammenhängende Wiesen, Gebirge und Seen.
+    pub fn generate(width: u16, height: u16, seed: u64) -> Self {
+        Self::generate_symmetric(width, height, seed, Symmetry::None)
+    }
+
+    // Vor jedem Glätten symmetrisch gemacht, damit keine Nähte zwischen den Teilen
+    // bleiben. Ohne Symmetrie dieselbe Karte wie bisher.
+    pub fn generate_symmetric(width: u16, height: u16, seed: u64, symmetry: Symmetry) -> Self {
+        let mut rng = GameRng::new(seed);
+        let tiles = (0..width)
+            .map(|x| {
+                (0..height)
+                    .map(|y| Tile::new(random_terrain(&mut rng), x, y))
+                    .collect()
+            })
+            .collect();
+        let mut map = Map {
+            width,
+            height,
+            tiles,
+        };
+        for _ in 0..SMOOTH_PASSES {
+            map.symmetrize(symmetry);
+            map.smooth();
+        }
+        map.symmetrize(symmetry);
+        // Berge sind höher als das Flachland
+        for tile in map.tiles.iter_mut().flatten() {
+            if tile.tile_type == TileType::TtMountain {
+                tile.elevation = rng.range(3, 7) as u8;
+            }
+        }
+        map.generate_resources(&mut rng);
+        map.symmetrize(symmetry);
+        map
+    }
+
+    fn smooth(&mut self) {
+        let mut next = Vec::new();
+        for x in 0..self.width {
+            for y in 0..self.height {
+                let own = self.tile(x, y).tile_type;
+                let mut counts: Vec<(TileType, usize)> = Vec::new();
+                for (nx, ny) in self.neighbours(x, y) {
+                    let tt = self.tile(nx, ny).tile_type;
+                    match counts.iter_mut().find(|(t, _)| *t == tt) {
+                        Some((_, n)) => *n += 1,
+                        None => counts.push((tt, 1)),
+                    }
+                }
+                // Nur eine klare Mehrheit ändert den Boden
+                if let Some((tt, n)) = counts.iter().max_by_key(|(_, n)| *n) {
+                    if (*n >= 4) & (*tt != own) {
+                        next.push((x, y, *tt));
+                    }
+                }
+            }
+        }
+        for (x, y, tt) in next {
+            self.tile_mut(x, y).tile_type = tt;
+        }
+    }
+
+    // Startpunkte für n Spieler im Kreis um die Kartenmitte
+    pub fn start_positions(&self, players: usize) -> Vec<(u16, u16)> {
+        let (cx, cy) = (self.width as f32 / 2.0, self.height as f32 / 2.0);
+        let radius = cx.min(cy) * 0.6;
+        (0..players)
+            .map(|i| {
+                let angle = std::f32::consts::PI * 2.0 * i as f32 / players as f32;
+                let x = (cx + radius * angle.cos()).round() as u16;
+                let y = (cy + radius * angle.sin()).round() as u16;
+                (keep_inside(x, self.width), keep_inside(y, self.height))
+            })
+            .collect()
+    }
+}
+
+// Drei Felder Abstand zum Rand für die Burg. Auf Karten, die dafür zu klein sind, die
+// Mitte; dort passt dann keine Burg hin und das Erzeugen der Welt meldet einen Fehler.
+fn keep_inside(v: u16, size: u16) -> u16 {
+    if size < 7 {
+        size / 2
+    } else {
+        v.clamp(3, size - 4)
+    }
+}
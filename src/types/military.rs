@@ -0,0 +1,388 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use super::{
+    building::BuildingId,
+    map::Map,
+    player::PlayerId,
+    random::GameRng,
+    settler::{Job, SettlerId, SettlerState},
+    world::World,
+};
+
+// Höchster Rang eines Soldaten
+pub const MAX_RANK: u8 = 4;
+
+// Alle so viele Ticks schlagen die Kämpfer an einer Tür zu
+pub const COMBAT_TICKS: u64 = 10;
+
+// Alle so viele Ticks heilen Soldaten in ihrem Gebäude
+pub const HEAL_TICKS: u64 = 40;
+pub const HEAL_AMOUNT: u16 = 5;
+
+// So weit dürfen angreifende Gebäude vom Ziel entfernt sein
+pub const ATTACK_RANGE: u16 = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Soldier {
+    pub rank: u8,
+    pub health: u16,
+    // Gebäude, das dieser Soldat gerade angreift
+    pub attacking: Option<BuildingId>,
+}
+
+impl Default for Soldier {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Soldier {
+    pub fn new() -> Self {
+        Soldier {
+            rank: 0,
+            health: Self::max_health_of(0),
+            attacking: None,
+        }
+    }
+
+    fn max_health_of(rank: u8) -> u16 {
+        100 + 25 * rank as u16
+    }
+
+    pub fn max_health(&self) -> u16 {
+        Self::max_health_of(self.rank)
+    }
+
+    pub fn damage(&self) -> u16 {
+        20 + 5 * self.rank as u16
+    }
+
+    // Nach einem Sieg steigt der Rang, die Wunden bleiben
+    pub fn promote(&mut self) {
+        if self.rank < MAX_RANK {
+            self.rank += 1;
+        }
+    }
+
+    pub fn heal(&mut self, amount: u16) {
+        self.health = (self.health + amount).min(self.max_health());
+    }
+}
+
+// Trefferchance in Prozent, jeder Rang Unterschied zählt 10 Punkte
+pub fn hit_chance(striker: &Soldier, target: &Soldier) -> u32 {
+    (50 + 10 * striker.rank as i32 - 10 * target.rank as i32).clamp(10, 90) as u32
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Attacker,
+    Defender,
+}
+
+// Eine Runde Nahkampf: erst schlägt der Angreifer, dann der Verteidiger, wenn er noch steht.
+// Liefert den Sieger, sobald einer fällt. Mit demselben Zufall immer dasselbe Ergebnis.
+pub fn fight_round(
+    attacker: &mut Soldier,
+    defender: &mut Soldier,
+    rng: &mut GameRng,
+) -> Option<Side> {
+    if rng.chance(hit_chance(attacker, defender)) {
+        defender.health = defender.health.saturating_sub(attacker.damage());
+        if defender.health == 0 {
+            return Some(Side::Attacker);
+        }
+    }
+    if rng.chance(hit_chance(defender, attacker)) {
+        attacker.health = attacker.health.saturating_sub(defender.damage());
+        if attacker.health == 0 {
+            return Some(Side::Defender);
+        }
+    }
+    None
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttackError {
+    NoTarget,
+    OwnBuilding,
+    NotMilitary,
+    NotVisible,
+    NoSoldiers,
+}
+
+// Soldaten, Angriffe und Eroberung
+impl World {
+    // Soldaten, die gerade in ihrem Gebäude sind
+    pub fn present_soldiers(&self, building: BuildingId) -> Vec<SettlerId> {
+        self.buildings.get(&building).map_or(Vec::new(), |b| {
+            b.garrison
+                .iter()
+                .copied()
+                .filter(|id| {
+                    self.settlers.get(id).is_some_and(|s| {
+                        (s.state == SettlerState::Working)
+                            & s.soldier.is_some_and(|soldier| soldier.attacking.is_none())
+                    })
+                })
+                .collect()
+        })
+    }
+
+    // Ob der Spieler dieses Gebäude angreifen darf
+    pub fn check_attack(&self, player: PlayerId, target: BuildingId) -> Result<(), AttackError> {
+        match self.buildings.get(&target) {
+            Some(b) if b.owner == player => Err(AttackError::OwnBuilding),
+            Some(b) if !self.is_visible(player, b.x, b.y) => Err(AttackError::NotVisible),
+            Some(b) if b.is_finished() & (b.building_type.garrison_capacity() > 0) => Ok(()),
+            Some(_) => Err(AttackError::NotMilitary),
+            None => Err(AttackError::NoTarget),
+        }
+    }
+
+    // Schickt bis zu count Soldaten aus den nahen Gebäuden los. Jedes Gebäude behält
+    // einen Soldaten. Liefert, wie viele losgelaufen sind.
+    pub fn order_attack(
+        &mut self,
+        player: PlayerId,
+        target: BuildingId,
+        count: u16,
+    ) -> Result<u16, AttackError> {
+        self.check_attack(player, target)?;
+        let (tx, ty, flag) = {
+            let b = &self.buildings[&target];
+            let flag = &self.roads.flags[&b.flag];
+            (b.x, b.y, (flag.x, flag.y))
+        };
+        let mut homes: Vec<(u16, BuildingId)> = self
+            .buildings
+            .values()
+            .filter(|b| (b.owner == player) & (b.building_type.garrison_capacity() > 0))
+            .map(|b| (Map::hex_distance(b.x, b.y, tx, ty), b.id))
+            .filter(|(distance, _)| *distance <= ATTACK_RANGE)
+            .collect();
+        homes.sort();
+
+        let mut sent = 0;
+        for (_, home) in homes {
+            let present = self.present_soldiers(home);
+            for id in present.into_iter().skip(1) {
+                if sent >= count {
+                    return Ok(sent);
+                }
+                let s = self.settlers.get_mut(&id).unwrap();
+                if s.walk_to(&self.map, flag.0, flag.1) {
+                    s.soldier.as_mut().unwrap().attacking = Some(target);
+                    sent += 1;
+                }
+            }
+        }
+        if sent == 0 {
+            return Err(AttackError::NoSoldiers);
+        }
+        Ok(sent)
+    }
+
+    pub(crate) fn update_military(&mut self) {
+        let ids: Vec<SettlerId> = self
+            .settlers
+            .values()
+            .filter(|s| (s.job == Job::Soldier) & s.workplace.is_some())
+            .map(|s| s.id)
+            .collect();
+        for id in ids {
+            let mut s = self.settlers.remove(&id).unwrap();
+            let home = s.workplace.unwrap();
+            if self.buildings.get(&home).is_none_or(|b| b.owner != s.owner) {
+                // Gebäude verloren, der Soldat geht ins Lager
+                self.go_home(&mut s);
+                self.settlers.insert(id, s);
+                continue;
+            }
+            if let SettlerState::Walking { .. } = s.state {
+                if s.step() {
+                    s.state = SettlerState::Working;
+                }
+            } else if (s.state == SettlerState::Working) & self.tick.is_multiple_of(HEAL_TICKS) {
+                let soldier = s.soldier.as_mut().unwrap();
+                if soldier.attacking.is_none() {
+                    soldier.heal(HEAL_AMOUNT);
+                }
+            }
+            self.settlers.insert(id, s);
+        }
+        if self.tick.is_multiple_of(COMBAT_TICKS) {
+            self.update_battles();
+        }
+    }
+
+    // Angreifer, die an der Tür ihres Ziels warten, nach Ziel sortiert
+    fn waiting_attackers(&self) -> BTreeMap<BuildingId, Vec<SettlerId>> {
+        let mut battles: BTreeMap<BuildingId, Vec<SettlerId>> = BTreeMap::new();
+        for s in self.settlers.values() {
+            if s.state != SettlerState::Working {
+                continue;
+            }
+            if let Some(target) = s.soldier.and_then(|soldier| soldier.attacking) {
+                battles.entry(target).or_default().push(s.id);
+            }
+        }
+        battles
+    }
+
+    // Pro Ziel kämpft der erste wartende Angreifer gegen den ersten Verteidiger.
+    // Ist keiner mehr drin, wird das Gebäude erobert.
+    fn update_battles(&mut self) {
+        for (target, attackers) in self.waiting_attackers() {
+            let attacker_id = attackers[0];
+            let owner = self.settlers[&attacker_id].owner;
+            if self.buildings.get(&target).is_none_or(|b| b.owner == owner) {
+                // Ziel ist weg oder schon unseres
+                for id in attackers {
+                    self.return_home(id);
+                }
+                continue;
+            }
+            let defender_id = match self.present_soldiers(target).first() {
+                Some(id) => *id,
+                None => {
+                    let conquerors: Vec<SettlerId> = attackers
+                        .into_iter()
+                        .filter(|id| self.settlers[id].owner == owner)
+                        .collect();
+                    self.conquer(target, &conquerors);
+                    continue;
+                }
+            };
+            let mut attacker = self.settlers[&attacker_id].soldier.unwrap();
+            let mut defender = self.settlers[&defender_id].soldier.unwrap();
+            let winner = fight_round(&mut attacker, &mut defender, &mut self.rng);
+            self.settlers.get_mut(&attacker_id).unwrap().soldier = Some(attacker);
+            self.settlers.get_mut(&defender_id).unwrap().soldier = Some(defender);
+            match winner {
+                Some(Side::Attacker) => {
+                    self.kill_soldier(defender_id);
+                    self.settlers
+                        .get_mut(&attacker_id)
+                        .and_then(|s| s.soldier.as_mut())
+                        .unwrap()
+                        .promote();
+                }
+                Some(Side::Defender) => {
+                    self.kill_soldier(attacker_id);
+                    self.settlers
+                        .get_mut(&defender_id)
+                        .and_then(|s| s.soldier.as_mut())
+                        .unwrap()
+                        .promote();
+                }
+                None => {}
+            }
+        }
+    }
+
+    fn kill_soldier(&mut self, id: SettlerId) {
+        if let Some(s) = self.settlers.remove(&id) {
+            if let Some(b) = s.workplace.and_then(|home| self.buildings.get_mut(&home)) {
+                b.garrison.retain(|g| *g != id);
+            }
+        }
+    }
+
+    // Zurück ins eigene Gebäude
+    fn return_home(&mut self, id: SettlerId) {
+        let mut s = self.settlers.remove(&id).unwrap();
+        s.soldier.as_mut().unwrap().attacking = None;
+        let home = s.workplace.and_then(|home| self.buildings.get(&home));
+        match home {
+            Some(b) if s.walk_to(&self.map, b.x, b.y) => {}
+            _ => self.go_home(&mut s),
+        }
+        self.settlers.insert(id, s);
+    }
+
+    // Das Gebäude wechselt den Besitzer. Die Angreifer ziehen ein, soweit Platz ist,
+    // der Rest geht zurück.
+    pub(crate) fn conquer(&mut self, target: BuildingId, attackers: &[SettlerId]) {
+        let owner = self.settlers[&attackers[0]].owner;
+        let (capacity, x, y, flag) = {
+            let b = self.buildings.get_mut(&target).unwrap();
+            b.owner = owner;
+            b.reserved.clear();
+            b.garrison.clear();
+            (
+                b.building_type.garrison_capacity() as usize,
+                b.x,
+                b.y,
+                b.flag,
+            )
+        };
+        if let Some(flag) = self.roads.flags.get_mut(&flag) {
+            flag.owner = owner;
+        }
+        for (i, id) in attackers.iter().enumerate() {
+            if i >= capacity {
+                self.return_home(*id);
+                continue;
+            }
+            let buildings = &mut self.buildings;
+            let s = self.settlers.get_mut(id).unwrap();
+            if let Some(old) = s.workplace.and_then(|home| buildings.get_mut(&home)) {
+                old.garrison.retain(|g| g != id);
+            }
+            s.workplace = Some(target);
+            s.soldier.as_mut().unwrap().attacking = None;
+            s.walk_to(&self.map, x, y);
+            buildings.get_mut(&target).unwrap().garrison.push(*id);
+        }
+        self.update_territory();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn soldier(rank: u8) -> Soldier {
+        let mut soldier = Soldier::new();
+        for _ in 0..rank {
+            soldier.promote();
+        }
+        soldier.health = soldier.max_health();
+        soldier
+    }
+
+    // Kämpft bis einer fällt, liefert Sieger, Runden und die Gesundheit beider
+    fn fight(seed: u64, attacker: u8, defender: u8) -> (Side, u32, u16, u16) {
+        let (mut a, mut d) = (soldier(attacker), soldier(defender));
+        let mut rng = GameRng::new(seed);
+        let mut rounds = 0;
+        loop {
+            rounds += 1;
+            if let Some(winner) = fight_round(&mut a, &mut d, &mut rng) {
+                return (winner, rounds, a.health, d.health);
+            }
+        }
+    }
+
+    #[test]
+    fn same_seed_same_fight() {
+        for seed in 0..50 {
+            assert_eq!(fight(seed, 0, 0), fight(seed, 0, 0));
+            assert_eq!(fight(seed, 3, 1), fight(seed, 3, 1));
+        }
+        // Verschiedener Zufall, verschiedene Kämpfe
+        let fights: Vec<_> = (0..50).map(|seed| fight(seed, 0, 0)).collect();
+        assert!(fights.iter().any(|f| *f != fights[0]));
+    }
+
+    #[test]
+    fn hit_chance_follows_rank() {
+        assert_eq!(hit_chance(&soldier(0), &soldier(0)), 50);
+        assert_eq!(hit_chance(&soldier(2), &soldier(0)), 70);
+        assert_eq!(hit_chance(&soldier(0), &soldier(2)), 30);
+        assert_eq!(hit_chance(&soldier(MAX_RANK), &soldier(0)), 90);
+        assert_eq!(hit_chance(&soldier(0), &soldier(MAX_RANK)), 10);
+    }
+}
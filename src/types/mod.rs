@@ -1,4 +1,38 @@
+pub mod ai;
+pub mod assets;
+pub mod building;
 pub mod camera_controller;
+pub mod command;
+pub mod construction;
+pub mod desync;
 pub mod drawable;
+pub mod editor;
+pub mod fairness;
+pub mod gfx;
+pub mod goods;
 pub mod hextile;
 pub mod map;
+pub mod mapgen;
+pub mod military;
+pub mod net;
+pub mod pathfinding;
+pub mod player;
+pub mod population;
+pub mod production;
+pub mod random;
+pub mod replay;
+pub mod resources;
+pub mod road;
+pub mod s4map;
+pub mod save;
+pub mod settler;
+pub mod stock;
+pub mod symmetry;
+pub mod territory;
+#[cfg(test)]
+pub mod testing;
+pub mod transport;
+pub mod undo;
+pub mod validate;
+pub mod visibility;
+pub mod world;
@@ -0,0 +1,665 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    io::{self, Read, Write},
+    net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    path::PathBuf,
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+    time::{Duration, Instant},
+};
+
+use super::{
+    command::Command,
+    desync::{self, Desync, StateHash, DESYNC_DIR},
+    player::PlayerId,
+    production::ProductionDefs,
+    replay::{GameSetup, Replay},
+    world::World,
+};
+
+// Steht vor jeder Nachricht. Verschiedene Versionen spielen nicht miteinander.
+pub const PROTOCOL_VERSION: u16 = 3;
+
+// Befehle wirken so viele Ticks später, damit sie rechtzeitig bei allen sind
+pub const MIN_INPUT_DELAY: u64 = 2;
+pub const MAX_INPUT_DELAY: u64 = 20;
+
+// Ein Tick bei 20 Ticks pro Sekunde
+pub const TICK_MILLIS: u64 = 50;
+
+// So oft misst ein Client die Laufzeit zum Host
+pub const PING_TICKS: u64 = 20;
+
+// Größer ist keine gültige Nachricht
+const MAX_MESSAGE_BYTES: u32 = 16 * 1024 * 1024;
+
+// So lange wartet ein Client nach einer Abweichung auf den Zustand der Gegenseite
+const STATE_WAIT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Message {
+    // Client meldet sich beim Host
+    Hello,
+    // Alle sind da, das Spiel beginnt
+    Welcome {
+        player: PlayerId,
+        setup: GameSetup,
+    },
+    Reject {
+        reason: String,
+    },
+    // Befehle eines Spielers für genau einen Tick, auch wenn es keine sind
+    Input {
+        tick: u64,
+        player: PlayerId,
+        commands: Vec<Command>,
+    },
+    // Zustandshash eines Spielers, alle vergleichen ihn mit ihrem eigenen
+    Hash {
+        player: PlayerId,
+        hash: StateHash,
+    },
+    // Eigener Zustand als Text beim Tick einer Abweichung, damit jeder beide speichert
+    State {
+        player: PlayerId,
+        tick: u64,
+        state: String,
+    },
+    // Ab diesem Tick wird auf den Spieler nicht mehr gewartet
+    Left {
+        player: PlayerId,
+        from_tick: u64,
+    },
+    Ping {
+        id: u64,
+    },
+    Pong {
+        id: u64,
+    },
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+// Länge (u32) und Protokollversion (u16), dann die Nachricht als RON
+pub fn write_message<W: Write>(stream: &mut W, message: &Message) -> io::Result<()> {
+    let data = ron::ser::to_string(message).map_err(|e| invalid(e.to_string()))?;
+    stream.write_all(&(data.len() as u32).to_be_bytes())?;
+    stream.write_all(&PROTOCOL_VERSION.to_be_bytes())?;
+    stream.write_all(data.as_bytes())?;
+    stream.flush()
+}
+
+pub fn read_message<R: Read>(stream: &mut R) -> io::Result<Message> {
+    let mut length = [0; 4];
+    stream.read_exact(&mut length)?;
+    let length = u32::from_be_bytes(length);
+    let mut version = [0; 2];
+    stream.read_exact(&mut version)?;
+    let version = u16::from_be_bytes(version);
+    if version != PROTOCOL_VERSION {
+        return Err(invalid(format!(
+            "protocol version {}, expected {}",
+            version, PROTOCOL_VERSION
+        )));
+    }
+    if length > MAX_MESSAGE_BYTES {
+        return Err(invalid(format!("message of {} bytes", length)));
+    }
+    let mut data = vec![0; length as usize];
+    stream.read_exact(&mut data)?;
+    let data = String::from_utf8(data).map_err(|e| invalid(e.to_string()))?;
+    ron::de::from_str(&data).map_err(|e| invalid(e.to_string()))
+}
+
+// Liest in einem eigenen Thread. None, sobald die Verbindung weg ist.
+fn spawn_reader<T: Send + Copy + 'static>(
+    mut stream: TcpStream,
+    tag: T,
+    sender: Sender<(T, Option<Message>)>,
+) {
+    thread::spawn(move || loop {
+        let message = read_message(&mut stream).ok();
+        let closed = message.is_none();
+        if sender.send((tag, message)).is_err() | closed {
+            return;
+        }
+    });
+}
+
+// Der Host nimmt die Spieler an und verteilt danach nur noch ihre Eingaben an alle.
+// Er rechnet selbst nicht mit, wer auf dem Rechner spielen will, verbindet sich wie alle.
+pub struct Host {
+    listener: TcpListener,
+    setup: GameSetup,
+}
+
+impl Host {
+    pub fn bind<A: ToSocketAddrs>(addr: A, setup: GameSetup) -> io::Result<Self> {
+        Ok(Host {
+            listener: TcpListener::bind(addr)?,
+            setup,
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    // Wartet auf einen Client pro Startplatz und verteilt dann, bis alle weg sind
+    pub fn run(self) -> io::Result<()> {
+        let mut clients: BTreeMap<PlayerId, TcpStream> = BTreeMap::new();
+        for (player, _, _) in &self.setup.starts {
+            loop {
+                let (mut stream, _) = self.listener.accept()?;
+                stream.set_nodelay(true)?;
+                match read_message(&mut stream) {
+                    Ok(Message::Hello) => {
+                        clients.insert(*player, stream);
+                        break;
+                    }
+                    Ok(_) => {
+                        let reason = String::from("expected Hello");
+                        let _ = write_message(&mut stream, &Message::Reject { reason });
+                    }
+                    Err(e) => {
+                        let reason = e.to_string();
+                        let _ = write_message(&mut stream, &Message::Reject { reason });
+                    }
+                }
+            }
+        }
+
+        let (sender, incoming) = mpsc::channel();
+        for (player, stream) in clients.iter_mut() {
+            let setup = self.setup.clone();
+            write_message(
+                stream,
+                &Message::Welcome {
+                    player: *player,
+                    setup,
+                },
+            )?;
+            spawn_reader(stream.try_clone()?, *player, sender.clone());
+        }
+        drop(sender);
+
+        // Nächster Tick, für den von jedem Spieler noch keine Eingabe kam
+        let mut next_tick: BTreeMap<PlayerId, u64> = clients.keys().map(|p| (*p, 0)).collect();
+        while !clients.is_empty() {
+            let (player, message) = match incoming.recv() {
+                Ok(received) => received,
+                Err(_) => break,
+            };
+            match message {
+                Some(Message::Input { tick, commands, .. }) => {
+                    // Der Absender bestimmt den Spieler, nicht die Nachricht
+                    next_tick.insert(player, tick + 1);
+                    let input = Message::Input {
+                        tick,
+                        player,
+                        commands,
+                    };
+                    for stream in clients.values_mut() {
+                        let _ = write_message(stream, &input);
+                    }
+                }
+                Some(Message::Hash { hash, .. }) => {
+                    let hash = Message::Hash { player, hash };
+                    for stream in clients.values_mut() {
+                        let _ = write_message(stream, &hash);
+                    }
+                }
+                Some(Message::State { tick, state, .. }) => {
+                    let state = Message::State {
+                        player,
+                        tick,
+                        state,
+                    };
+                    for stream in clients.values_mut() {
+                        let _ = write_message(stream, &state);
+                    }
+                }
+                Some(Message::Ping { id }) => {
+                    if let Some(stream) = clients.get_mut(&player) {
+                        let _ = write_message(stream, &Message::Pong { id });
+                    }
+                }
+                Some(_) => {}
+                None => {
+                    clients.remove(&player);
+                    let left = Message::Left {
+                        player,
+                        from_tick: next_tick[&player],
+                    };
+                    for stream in clients.values_mut() {
+                        let _ = write_message(stream, &left);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// Ein Spieler im Lockstep: Tick N wird erst gerechnet, wenn von allen Spielern die
+// Eingabe für N da ist. Eigene Befehle wirken erst delay Ticks später.
+pub struct LockstepClient {
+    pub player: PlayerId,
+    pub setup: GameSetup,
+    pub delay: u64,
+    stream: TcpStream,
+    incoming: Receiver<((), Option<Message>)>,
+    players: Vec<PlayerId>,
+    // Eingaben pro Tick und Spieler, die noch nicht gerechnet wurden
+    inputs: BTreeMap<u64, BTreeMap<PlayerId, Vec<Command>>>,
+    // Spieler, die weg sind, ab welchem Tick
+    left: BTreeMap<PlayerId, u64>,
+    // Eigene Befehle, die mit der nächsten Eingabe rausgehen
+    pending: Vec<Command>,
+    next_input: u64,
+    pings: BTreeMap<u64, Instant>,
+    next_ping: u64,
+    // Geglättete Laufzeit zum Host und zurück in Millisekunden
+    round_trip: Option<u64>,
+    connected: bool,
+    // Eigener Stand bei jedem Hash, bis alle anderen denselben gemeldet haben
+    unconfirmed: BTreeMap<u64, World>,
+    // Hashes der anderen, die noch nicht verglichen wurden
+    peer_hashes: Vec<(PlayerId, StateHash)>,
+    // Letzter verglichener Hash pro Spieler
+    confirmed: BTreeMap<PlayerId, u64>,
+    pub desync: Option<Desync>,
+    // Mit wem es auseinanderlief und seit wann auf dessen Zustand gewartet wird
+    desync_peer: Option<(PlayerId, Instant)>,
+    // Zustände der anderen, die vor der eigenen Abweichung kamen
+    peer_states: Vec<(PlayerId, u64, String)>,
+    // Hierhin kommen die Zustände bei einer Abweichung
+    pub dump_dir: PathBuf,
+}
+
+impl LockstepClient {
+    // Meldet sich an und wartet, bis der Host alle Spieler zusammen hat
+    pub fn join<A: ToSocketAddrs>(addr: A) -> io::Result<Self> {
+        let mut stream = TcpStream::connect(addr)?;
+        stream.set_nodelay(true)?;
+        write_message(&mut stream, &Message::Hello)?;
+        let (player, setup) = match read_message(&mut stream)? {
+            Message::Welcome { player, setup } => (player, setup),
+            Message::Reject { reason } => {
+                return Err(io::Error::new(io::ErrorKind::ConnectionRefused, reason))
+            }
+            other => return Err(invalid(format!("expected Welcome, got {:?}", other))),
+        };
+        let (sender, incoming) = mpsc::channel();
+        spawn_reader(stream.try_clone()?, (), sender);
+        Ok(LockstepClient {
+            player,
+            players: setup.starts.iter().map(|(p, _, _)| *p).collect(),
+            setup,
+            delay: MIN_INPUT_DELAY,
+            stream,
+            incoming,
+            inputs: BTreeMap::new(),
+            left: BTreeMap::new(),
+            pending: Vec::new(),
+            next_input: 0,
+            pings: BTreeMap::new(),
+            next_ping: 0,
+            round_trip: None,
+            connected: true,
+            unconfirmed: BTreeMap::new(),
+            peer_hashes: Vec::new(),
+            confirmed: BTreeMap::new(),
+            desync: None,
+            desync_peer: None,
+            peer_states: Vec::new(),
+            dump_dir: PathBuf::from(DESYNC_DIR),
+        })
+    }
+
+    pub fn queue(&mut self, command: Command) {
+        self.pending.push(command);
+    }
+
+    pub fn round_trip(&self) -> Option<u64> {
+        self.round_trip
+    }
+
+    fn poll(&mut self) {
+        while let Ok((_, message)) = self.incoming.try_recv() {
+            match message {
+                Some(Message::Input {
+                    tick,
+                    player,
+                    commands,
+                }) => {
+                    self.inputs
+                        .entry(tick)
+                        .or_default()
+                        .insert(player, commands);
+                }
+                Some(Message::Hash { player, hash }) => {
+                    if player != self.player {
+                        self.peer_hashes.push((player, hash));
+                    }
+                }
+                Some(Message::State {
+                    player,
+                    tick,
+                    state,
+                }) => {
+                    if player != self.player {
+                        self.peer_states.push((player, tick, state));
+                        self.save_peer_state();
+                    }
+                }
+                Some(Message::Left { player, from_tick }) => {
+                    self.left.insert(player, from_tick);
+                }
+                Some(Message::Pong { id }) => {
+                    if let Some(sent) = self.pings.remove(&id) {
+                        self.adapt_delay(sent.elapsed());
+                    }
+                }
+                Some(_) => {}
+                None => self.connected = false,
+            }
+        }
+    }
+
+    // Die Verzögerung folgt der Laufzeit. Jeder Client wählt seine eigene, weil jeder
+    // trotzdem für jeden Tick genau eine Eingabe schickt.
+    fn adapt_delay(&mut self, sample: Duration) {
+        let sample = sample.as_millis() as u64;
+        let round_trip = match self.round_trip {
+            Some(old) => (old * 3 + sample) / 4,
+            None => sample,
+        };
+        self.round_trip = Some(round_trip);
+        self.delay = (round_trip / TICK_MILLIS + 1).clamp(MIN_INPUT_DELAY, MAX_INPUT_DELAY);
+    }
+
+    // Eingaben bis tick + delay. Wird die Verzögerung größer, gehen leere Eingaben für
+    // die Lücke mit, wird sie kleiner, wird einfach gewartet.
+    fn send_inputs(&mut self, tick: u64) -> io::Result<()> {
+        while self.next_input <= tick + self.delay {
+            let input = Message::Input {
+                tick: self.next_input,
+                player: self.player,
+                commands: std::mem::take(&mut self.pending),
+            };
+            write_message(&mut self.stream, &input)?;
+            self.next_input += 1;
+        }
+        if tick >= self.next_ping * PING_TICKS {
+            self.pings.insert(self.next_ping, Instant::now());
+            write_message(&mut self.stream, &Message::Ping { id: self.next_ping })?;
+            self.next_ping += 1;
+        }
+        Ok(())
+    }
+
+    // Vergleicht die Hashes der anderen mit dem eigenen Stand von damals. Bei der ersten
+    // Abweichung wird dieser Stand gespeichert und an alle geschickt, die anderen machen
+    // es genauso. So hat jeder am Ende beide Stände.
+    fn compare_hashes(&mut self, tick: u64) -> io::Result<()> {
+        let (ready, waiting): (Vec<_>, Vec<_>) = std::mem::take(&mut self.peer_hashes)
+            .into_iter()
+            .partition(|(_, hash)| hash.tick <= tick);
+        self.peer_hashes = waiting;
+        for (player, hash) in ready {
+            self.confirmed.insert(player, hash.tick);
+            if self.desync.is_some() {
+                continue;
+            }
+            let world = match self.unconfirmed.get(&hash.tick) {
+                Some(world) => world,
+                None => continue,
+            };
+            let name = format!("player_{}_vs_{}", self.player, player);
+            self.desync = world.check_hash(&hash, &self.dump_dir, &name);
+            if self.desync.is_none() {
+                continue;
+            }
+            self.desync_peer = Some((player, Instant::now()));
+            let state = world.state_text();
+            // Zu groß für eine Nachricht, dann bleibt es beim eigenen Stand
+            if state.len() < MAX_MESSAGE_BYTES as usize / 2 {
+                let state = Message::State {
+                    player: self.player,
+                    tick: hash.tick,
+                    state,
+                };
+                write_message(&mut self.stream, &state)?;
+            }
+            self.save_peer_state();
+        }
+        // Was alle anderen schon gemeldet haben, wird nicht mehr gebraucht
+        let confirmed = self
+            .players
+            .iter()
+            .filter(|p| (**p != self.player) & !self.left.contains_key(p))
+            .map(|p| self.confirmed.get(p).copied().unwrap_or(0))
+            .min()
+            .unwrap_or(tick);
+        self.unconfirmed = self.unconfirmed.split_off(&(confirmed + 1));
+        Ok(())
+    }
+
+    // Speichert den Zustand der Gegenseite neben den eigenen, sobald beide da sind
+    fn save_peer_state(&mut self) {
+        let (desync, (peer, _)) = match (self.desync.as_mut(), self.desync_peer) {
+            (Some(desync), Some(peer)) if desync.other.is_none() => (desync, peer),
+            _ => return,
+        };
+        let tick = desync.tick;
+        if let Some((_, _, state)) = self
+            .peer_states
+            .iter()
+            .find(|(player, t, _)| (*player == peer) & (*t == tick))
+        {
+            let name = format!("player_{}_vs_{}_theirs", self.player, peer);
+            let path = desync::dump_path(&self.dump_dir, &name, tick);
+            desync.other = desync::write_dump(&path, state).ok().map(|_| path);
+            self.peer_states.clear();
+        }
+    }
+
+    // Nach einer Abweichung noch auf den Zustand der Gegenseite warten
+    fn waiting_for_state(&self) -> bool {
+        match (&self.desync, self.desync_peer) {
+            (Some(desync), Some((peer, since))) => {
+                desync.other.is_none()
+                    & self.connected
+                    & !self.left.contains_key(&peer)
+                    & (since.elapsed() < STATE_WAIT)
+            }
+            _ => false,
+        }
+    }
+
+    pub fn is_ready(&self, tick: u64) -> bool {
+        let inputs = self.inputs.get(&tick);
+        self.players.iter().all(|player| {
+            self.left.get(player).is_some_and(|from| tick >= *from)
+                | inputs.is_some_and(|inputs| inputs.contains_key(player))
+        })
+    }
+
+    // Rechnet einen Tick, wenn alle Eingaben dafür da sind. false heißt warten.
+    pub fn advance(&mut self, world: &mut World) -> io::Result<bool> {
+        self.poll();
+        if let Some(desync) = &self.desync {
+            if self.waiting_for_state() {
+                return Ok(false);
+            }
+            return Err(invalid(desync.to_string()));
+        }
+        let tick = world.tick;
+        if self.connected {
+            self.send_inputs(tick)?;
+        }
+        if !self.is_ready(tick) {
+            if !self.connected {
+                return Err(io::Error::new(
+                    io::ErrorKind::ConnectionAborted,
+                    "lost connection to host",
+                ));
+            }
+            // Auch beim Warten vergleichen, sonst wartet die Gegenseite nach einer
+            // Abweichung vergeblich auf unseren Stand
+            self.compare_hashes(tick)?;
+            return Ok(false);
+        }
+        // Alle in derselben Reihenfolge, nach Spieler
+        for (player, commands) in self.inputs.remove(&tick).unwrap_or_default() {
+            for command in commands {
+                let _ = world.execute(player, command);
+            }
+        }
+        world.tick();
+        if let Some(hash) = world.hash_log.last().filter(|h| h.tick == world.tick) {
+            let hash = hash.clone();
+            self.unconfirmed.insert(world.tick, world.clone());
+            write_message(
+                &mut self.stream,
+                &Message::Hash {
+                    player: self.player,
+                    hash,
+                },
+            )?;
+        }
+        self.compare_hashes(world.tick)?;
+        Ok(true)
+    }
+}
+
+// Der Lese-Thread hat eine Kopie der Verbindung, deshalb ausdrücklich schließen
+impl Drop for LockstepClient {
+    fn drop(&mut self) {
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+}
+
+// Testlauf auf diesem Rechner: ein Host und ein Client pro Spieler, jeder in seinem
+// Thread mit eigener Welt. Jeder Client schickt die Befehle seines Spielers aus der
+// Wiederholung. Liefert die Welten aller Clients nach ticks Ticks, die müssen gleich sein.
+pub fn run_local_match(
+    replay: &Replay,
+    production: &ProductionDefs,
+    ticks: u64,
+) -> io::Result<Vec<World>> {
+    let host = Host::bind("127.0.0.1:0", replay.setup.clone())?;
+    let addr = host.local_addr()?;
+    let host = thread::spawn(move || host.run());
+    let clients: Vec<_> = replay
+        .setup
+        .starts
+        .iter()
+        .map(|_| {
+            let commands = replay.commands.clone();
+            let production = production.clone();
+            thread::spawn(move || -> io::Result<World> {
+                let mut client = LockstepClient::join(addr)?;
+                let mut world = client.setup.create_world(production)?;
+                let player = client.player;
+                let mut own = commands
+                    .into_iter()
+                    .filter(|c| c.player == player)
+                    .peekable();
+                // Nach einer Abweichung weiter, bis advance sie meldet
+                while (world.tick < ticks) | client.desync.is_some() {
+                    while let Some(timed) = own.next_if(|c| c.tick <= world.tick) {
+                        client.queue(timed.command);
+                    }
+                    if !client.advance(&mut world)? {
+                        thread::sleep(Duration::from_millis(1));
+                    }
+                }
+                Ok(world)
+            })
+        })
+        .collect();
+    let mut worlds = Vec::new();
+    for client in clients {
+        worlds.push(
+            client
+                .join()
+                .map_err(|_| invalid(String::from("client thread panicked")))??,
+        );
+    }
+    // Erst wenn alle Clients weg sind, hört der Host auf
+    host.join()
+        .map_err(|_| invalid(String::from("host thread panicked")))??;
+    Ok(worlds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{ai, desync::Subsystem, symmetry::Symmetry, testing};
+    use std::fs;
+
+    #[test]
+    fn two_clients_stay_in_sync() {
+        let ticks = 300;
+        let (_, replay) =
+            ai::run_match(testing::production(), 1, 2, 48, Symmetry::None, ticks).unwrap();
+        assert!(!replay.commands.is_empty());
+        let worlds = run_local_match(&replay, &testing::production(), ticks).unwrap();
+        assert_eq!(worlds.len(), 2);
+        for world in &worlds {
+            assert_eq!(world.tick, ticks);
+            // Jeder Client hat die Befehle beider Spieler ausgeführt
+            for player in 0..2 {
+                assert!(world.command_log.iter().any(|c| c.player == player));
+            }
+        }
+        assert_eq!(worlds[0].state_hash(), worlds[1].state_hash());
+    }
+
+    #[test]
+    fn both_states_are_dumped_on_a_desync() {
+        let dir = std::env::temp_dir().join("settlers_rs_net_desync_test");
+        let setup = GameSetup::generated(32, 32, 1, 2, Symmetry::None);
+        let host = Host::bind("127.0.0.1:0", setup).unwrap();
+        let addr = host.local_addr().unwrap();
+        let host = thread::spawn(move || host.run());
+        let clients: Vec<_> = (0..2)
+            .map(|_| {
+                let dir = dir.clone();
+                thread::spawn(move || {
+                    let mut client = LockstepClient::join(addr).unwrap();
+                    client.dump_dir = dir;
+                    let mut world = client.setup.create_world(testing::production()).unwrap();
+                    let mut changed = false;
+                    while world.tick < 1000 {
+                        // Spieler 1 rechnet ab hier etwas anderes
+                        if (client.player == 1) & (world.tick == 50) & !changed {
+                            world.map.tile_mut(0, 0).elevation += 1;
+                            changed = true;
+                        }
+                        match client.advance(&mut world) {
+                            Ok(true) => (),
+                            Ok(false) => thread::sleep(Duration::from_millis(1)),
+                            Err(_) => break,
+                        }
+                    }
+                    client.desync.clone()
+                })
+            })
+            .collect();
+        for client in clients {
+            let desync = client.join().unwrap().unwrap();
+            assert_eq!(desync.subsystem, Subsystem::Map);
+            let own = fs::read_to_string(desync.dump.unwrap()).unwrap();
+            let other = fs::read_to_string(desync.other.unwrap()).unwrap();
+            assert_ne!(own, other);
+        }
+        host.join().unwrap().unwrap();
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
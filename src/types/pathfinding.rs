@@ -0,0 +1,71 @@
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, BinaryHeap},
+};
+
+use super::map::Map;
+
+impl Map {
+    // Hier kann ein Siedler durchlaufen. Gebäude stehen im Weg.
+    pub fn is_passable(&self, x: u16, y: u16) -> bool {
+        let tile = self.tile(x, y);
+        tile.tile_type.is_walkable() & tile.building.is_none()
+    }
+
+    // A* über die begehbaren Tiles. Start und Ziel dürfen in einem Gebäude liegen.
+    // Liefert die Tiles ohne den Start, das Ziel ist das letzte.
+    pub fn find_path(&self, from: (u16, u16), to: (u16, u16)) -> Option<Vec<(u16, u16)>> {
+        if from == to {
+            return Some(Vec::new());
+        }
+        if !self.contains(to.0 as i32, to.1 as i32)
+            || !self.tile(to.0, to.1).tile_type.is_walkable()
+        {
+            return None;
+        }
+        // Aus dem eigenen Gebäude heraus und in das Zielgebäude hinein darf man laufen
+        let from_building = self.tile(from.0, from.1).building;
+        let to_building = self.tile(to.0, to.1).building;
+        let heuristic = |(x, y): (u16, u16)| Map::hex_distance(x, y, to.0, to.1) as u32;
+        let mut cost: BTreeMap<(u16, u16), u32> = BTreeMap::new();
+        let mut came_from: BTreeMap<(u16, u16), (u16, u16)> = BTreeMap::new();
+        // Gleich gute Tiles werden über die Koordinate entschieden, damit jeder Rechner
+        // denselben Weg findet
+        let mut queue = BinaryHeap::new();
+        cost.insert(from, 0);
+        queue.push(Reverse((heuristic(from), 0u32, from)));
+        while let Some(Reverse((_, g, pos))) = queue.pop() {
+            if pos == to {
+                let mut path = vec![to];
+                let mut current = to;
+                while let Some(prev) = came_from.get(&current) {
+                    if *prev == from {
+                        break;
+                    }
+                    path.push(*prev);
+                    current = *prev;
+                }
+                path.reverse();
+                return Some(path);
+            }
+            if cost.get(&pos).is_some_and(|best| g > *best) {
+                continue;
+            }
+            for next in self.neighbours(pos.0, pos.1) {
+                let building = self.tile(next.0, next.1).building;
+                let inside =
+                    building.is_some() & ((building == from_building) | (building == to_building));
+                if (next != to) & !inside & !self.is_passable(next.0, next.1) {
+                    continue;
+                }
+                let ng = g + 1;
+                if cost.get(&next).is_none_or(|best| ng < *best) {
+                    cost.insert(next, ng);
+                    came_from.insert(next, pos);
+                    queue.push(Reverse((ng + heuristic(next), ng, next)));
+                }
+            }
+        }
+        None
+    }
+}
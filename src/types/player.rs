@@ -0,0 +1,12 @@
+pub type PlayerId = u8;
+
+pub fn player_color(player: PlayerId) -> coffee::graphics::Color {
+    match player {
+        0 => coffee::graphics::Color::from_rgb(220, 30, 30),
+        1 => coffee::graphics::Color::from_rgb(30, 30, 220),
+        2 => coffee::graphics::Color::from_rgb(30, 180, 30),
+        3 => coffee::graphics::Color::from_rgb(230, 200, 0),
+        4 => coffee::graphics::Color::from_rgb(160, 0, 200),
+        _ => coffee::graphics::Color::from_rgb(0, 200, 200),
+    }
+}
@@ -0,0 +1,366 @@
+use super::{
+    building::{BuildingId, BuildingType},
+    map::Map,
+    military::Soldier,
+    player::PlayerId,
+    production::{WorkPhase, Workshop},
+    road::RoadId,
+    settler::{CarrierTask, Job, Settler, SettlerId, SettlerState},
+    world::World,
+};
+
+// Alle so viele Ticks zieht in jedes Wohnhaus mit freiem Platz ein neuer Siedler
+pub const RESIDENCE_SPAWN_TICKS: u64 = 600;
+
+// So viele Siedler bringt ein Wohnhaus insgesamt hervor
+pub const RESIDENCE_CAPACITY: u16 = 8;
+
+// Wo ein Beruf gebraucht wird
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Workplace {
+    Site(BuildingId),
+    Road(RoadId),
+    Workshop(BuildingId),
+    Garrison(BuildingId),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JobDemand {
+    pub owner: PlayerId,
+    pub job: Job,
+    pub workplace: Workplace,
+    // Hierhin läuft der neue Arbeiter
+    pub x: u16,
+    pub y: u16,
+}
+
+// Wer die Stelle bekommt und welchen Weg er dorthin nimmt
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JobOffer {
+    pub settler: SettlerId,
+    // Lager, aus dem das Werkzeug geholt wird
+    pub tool_from: Option<BuildingId>,
+    pub route: Vec<(u16, u16)>,
+}
+
+// Arbeitsmarkt und Bevölkerung
+impl World {
+    // Alle offenen Stellen in fester Reihenfolge: Baustellen, Straßen, Betriebe, Soldaten
+    pub fn job_demands(&self) -> Vec<JobDemand> {
+        let mut demands = Vec::new();
+        for b in self.buildings.values() {
+            if b.site().is_some_and(|s| s.builder.is_none()) {
+                demands.push(JobDemand {
+                    owner: b.owner,
+                    job: Job::Builder,
+                    workplace: Workplace::Site(b.id),
+                    x: b.x,
+                    y: b.y,
+                });
+            }
+        }
+        for road in self.roads.roads.values() {
+            if road.carrier.is_none() {
+                let (x, y) = road.path[road.path.len() / 2];
+                demands.push(JobDemand {
+                    owner: road.owner,
+                    job: Job::Carrier,
+                    workplace: Workplace::Road(road.id),
+                    x,
+                    y,
+                });
+            }
+        }
+        for b in self.buildings.values() {
+            if !b.is_finished() | b.workshop.is_some() {
+                continue;
+            }
+            if let Some(def) = self.production.get(b.building_type) {
+                demands.push(JobDemand {
+                    owner: b.owner,
+                    job: def.worker,
+                    workplace: Workplace::Workshop(b.id),
+                    x: b.x,
+                    y: b.y,
+                });
+            }
+        }
+        for b in self.buildings.values() {
+            if !b.is_finished() {
+                continue;
+            }
+            let missing =
+                (b.building_type.garrison_capacity() as usize).saturating_sub(b.garrison.len());
+            for _ in 0..missing {
+                demands.push(JobDemand {
+                    owner: b.owner,
+                    job: Job::Soldier,
+                    workplace: Workplace::Garrison(b.id),
+                    x: b.x,
+                    y: b.y,
+                });
+            }
+        }
+        demands
+    }
+
+    // Freie Siedler: haben weder Arbeitsplatz noch Straße und stehen still
+    fn is_idle(s: &Settler) -> bool {
+        s.workplace.is_none() & s.road.is_none() & (s.state == SettlerState::Idle)
+    }
+
+    // Sucht jemanden für die Stelle. Wer den Beruf schon hat, geht vor, sonst wird ein
+    // Siedler ohne Beruf umgeschult und holt sich unterwegs das Werkzeug.
+    pub fn find_worker(&self, demand: &JobDemand) -> Option<JobOffer> {
+        let target = (demand.x, demand.y);
+        let mut trained: Vec<&Settler> = self
+            .settlers
+            .values()
+            .filter(|s| (s.owner == demand.owner) & (s.job == demand.job) & Self::is_idle(s))
+            .collect();
+        trained.sort_by_key(|s| (Map::hex_distance(s.x, s.y, target.0, target.1), s.id));
+        for s in trained {
+            if let Some(route) = self.map.find_path((s.x, s.y), target) {
+                return Some(JobOffer {
+                    settler: s.id,
+                    tool_from: None,
+                    route,
+                });
+            }
+        }
+
+        let mut generic: Vec<&Settler> = self
+            .settlers
+            .values()
+            .filter(|s| (s.owner == demand.owner) & (s.job == Job::Settler) & Self::is_idle(s))
+            .collect();
+        let tool = match demand.job.tool() {
+            Some(tool) => tool,
+            None => {
+                generic.sort_by_key(|s| (Map::hex_distance(s.x, s.y, target.0, target.1), s.id));
+                return generic.into_iter().find_map(|s| {
+                    self.map
+                        .find_path((s.x, s.y), target)
+                        .map(|route| JobOffer {
+                            settler: s.id,
+                            tool_from: None,
+                            route,
+                        })
+                });
+            }
+        };
+
+        // Werkzeug aus dem nächsten Lager, das eins hat, dann der nächste Siedler dazu
+        let mut storages: Vec<(u16, BuildingId, u16, u16)> = self
+            .buildings
+            .values()
+            .filter(|b| {
+                (b.owner == demand.owner)
+                    & b.is_finished()
+                    & b.is_storage()
+                    & (b.available(tool) > 0)
+            })
+            .map(|b| {
+                (
+                    Map::hex_distance(b.x, b.y, target.0, target.1),
+                    b.id,
+                    b.x,
+                    b.y,
+                )
+            })
+            .collect();
+        storages.sort();
+        for (_, storage, sx, sy) in storages {
+            let to_work = match self.map.find_path((sx, sy), target) {
+                Some(path) => path,
+                None => continue,
+            };
+            generic.sort_by_key(|s| (Map::hex_distance(s.x, s.y, sx, sy), s.id));
+            for s in &generic {
+                if let Some(mut route) = self.map.find_path((s.x, s.y), (sx, sy)) {
+                    route.extend(to_work.iter().copied());
+                    return Some(JobOffer {
+                        settler: s.id,
+                        tool_from: Some(storage),
+                        route,
+                    });
+                }
+            }
+        }
+        None
+    }
+
+    // Besetzt die offenen Stellen mit freien Siedlern
+    pub(crate) fn assign_jobs(&mut self) {
+        for demand in self.job_demands() {
+            if let Some(offer) = self.find_worker(&demand) {
+                self.employ(&demand, offer);
+            }
+        }
+    }
+
+    fn employ(&mut self, demand: &JobDemand, offer: JobOffer) {
+        if let Some(storage) = offer.tool_from {
+            let b = self.buildings.get_mut(&storage).unwrap();
+            let tool = demand.job.tool().unwrap();
+            b.reserve(tool);
+            b.take_reserved(tool);
+        }
+        let s = self.settlers.get_mut(&offer.settler).unwrap();
+        s.job = demand.job;
+        s.walk_path(offer.route);
+        match demand.workplace {
+            Workplace::Site(building) => {
+                s.workplace = Some(building);
+                if let Some(site) = self.buildings.get_mut(&building).and_then(|b| b.site_mut()) {
+                    site.builder = Some(offer.settler);
+                }
+            }
+            Workplace::Road(road) => {
+                s.road = Some(road);
+                self.roads.roads.get_mut(&road).unwrap().carrier = Some(offer.settler);
+            }
+            Workplace::Workshop(building) => {
+                s.workplace = Some(building);
+                self.buildings.get_mut(&building).unwrap().workshop = Some(Workshop {
+                    worker: offer.settler,
+                    phase: WorkPhase::Arriving,
+                });
+            }
+            Workplace::Garrison(building) => {
+                s.workplace = Some(building);
+                if s.soldier.is_none() {
+                    s.soldier = Some(Soldier::new());
+                }
+                self.buildings
+                    .get_mut(&building)
+                    .unwrap()
+                    .garrison
+                    .push(offer.settler);
+            }
+        }
+    }
+
+    // Siedler ohne Arbeit gehen ins nächste Lager und warten dort
+    pub(crate) fn go_home(&self, s: &mut Settler) {
+        s.workplace = None;
+        s.road = None;
+        s.task = CarrierTask::None;
+        s.state = SettlerState::Idle;
+        if let Some(soldier) = s.soldier.as_mut() {
+            soldier.attacking = None;
+        }
+        let mut storages: Vec<(u16, BuildingId, u16, u16)> = self
+            .buildings
+            .values()
+            .filter(|b| (b.owner == s.owner) & b.is_finished() & b.is_storage())
+            .map(|b| (Map::hex_distance(b.x, b.y, s.x, s.y), b.id, b.x, b.y))
+            .collect();
+        storages.sort();
+        for (_, _, x, y) in storages {
+            if s.walk_to(&self.map, x, y) {
+                return;
+            }
+        }
+    }
+
+    pub(crate) fn send_home(&mut self, id: SettlerId) {
+        if let Some(mut s) = self.settlers.remove(&id) {
+            self.go_home(&mut s);
+            self.settlers.insert(id, s);
+        }
+    }
+
+    // Fertige Wohnhäuser bringen nach und nach neue Siedler hervor
+    pub(crate) fn update_residences(&mut self) {
+        if !self.tick.is_multiple_of(RESIDENCE_SPAWN_TICKS) {
+            return;
+        }
+        let homes: Vec<(BuildingId, PlayerId, u16, u16)> = self
+            .buildings
+            .values()
+            .filter(|b| {
+                (b.building_type == BuildingType::Residence)
+                    & b.is_finished()
+                    & (b.residents < RESIDENCE_CAPACITY)
+            })
+            .map(|b| (b.id, b.owner, b.x, b.y))
+            .collect();
+        for (home, owner, x, y) in homes {
+            self.buildings.get_mut(&home).unwrap().residents += 1;
+            let id = self.next_id();
+            let mut settler = Settler::new(id, owner, Job::Settler, x, y);
+            self.go_home(&mut settler);
+            self.settlers.insert(id, settler);
+        }
+    }
+
+    // Siedler ohne Arbeitsplatz laufen ins Lager und bleiben dort stehen
+    pub(crate) fn update_idle_settlers(&mut self) {
+        for s in self.settlers.values_mut() {
+            if s.workplace.is_none() & s.road.is_none() {
+                if let SettlerState::Walking { .. } = s.state {
+                    if s.step() {
+                        s.state = SettlerState::Idle;
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn population(&self, player: PlayerId) -> usize {
+        self.settlers.values().filter(|s| s.owner == player).count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{goods::Good, testing};
+
+    fn demand(job: Job) -> JobDemand {
+        JobDemand {
+            owner: 0,
+            job,
+            workplace: Workplace::Workshop(0),
+            x: 16,
+            y: 10,
+        }
+    }
+
+    #[test]
+    fn prefers_trained_settlers() {
+        let (mut world, _) = testing::world();
+        // Weiter weg als die Siedler in der Burg, aber schon Holzfäller
+        let id = world.next_id();
+        let woodcutter = Settler::new(id, 0, Job::Woodcutter, 4, 10);
+        world.settlers.insert(id, woodcutter);
+        let offer = world.find_worker(&demand(Job::Woodcutter)).unwrap();
+        assert_eq!(offer.settler, id);
+        assert_eq!(offer.tool_from, None);
+    }
+
+    #[test]
+    fn trains_a_settler_with_a_tool() {
+        let (world, castle) = testing::world();
+        let offer = world.find_worker(&demand(Job::Woodcutter)).unwrap();
+        assert_eq!(world.settlers[&offer.settler].job, Job::Settler);
+        assert_eq!(offer.tool_from, Some(castle));
+        assert_eq!(offer.route.last(), Some(&(16, 10)));
+    }
+
+    #[test]
+    fn needs_a_tool_in_stock() {
+        let (mut world, castle) = testing::world();
+        world
+            .buildings
+            .get_mut(&castle)
+            .unwrap()
+            .stock
+            .remove(&Good::Axe);
+        assert_eq!(world.find_worker(&demand(Job::Woodcutter)), None);
+        // Träger brauchen kein Werkzeug
+        let offer = world.find_worker(&demand(Job::Carrier)).unwrap();
+        assert_eq!(offer.tool_from, None);
+    }
+}
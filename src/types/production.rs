@@ -0,0 +1,407 @@
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, io};
+
+use super::{
+    assets::{self, Assets},
+    building::{BuildingId, BuildingType},
+    goods::Good,
+    hextile::MapObject,
+    map::Map,
+    player::PlayerId,
+    resources::ResourceKind,
+    settler::{Job, Settler, SettlerId, SettlerState},
+    world::World,
+};
+
+// So viele Eingangswaren hält ein Betrieb auf Vorrat
+pub const INPUT_BUFFER: u16 = 4;
+
+// Liegen so viele fertige Waren im Gebäude, wird nicht weiter produziert
+pub const OUTPUT_CAPACITY: u16 = 8;
+
+// So lange wächst ein Feld, bis es geerntet werden kann
+pub const FIELD_GROW_TICKS: u64 = 600;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum WorkKind {
+    // Arbeitet im Gebäude
+    InPlace,
+    FellTree,
+    CutStone,
+    // Erntet reife Felder, sonst wird ein freies Tile eingesät
+    Farm,
+    // Fischt vom Ufer aus
+    Fish,
+    // Baut im Gebäude ein Vorkommen im Umkreis ab
+    Mine(ResourceKind),
+}
+
+// Eine Zeile aus res/data/production.ron
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProductionDef {
+    pub building: BuildingType,
+    pub worker: Job,
+    // Verbrauch pro Arbeitsgang
+    pub inputs: Vec<(Good, u8)>,
+    // Ertrag pro Arbeitsgang
+    pub outputs: Vec<(Good, u8)>,
+    // Dauer eines Arbeitsgangs in Ticks, bei Sammlern die Zeit am Ziel
+    pub cycle_ticks: u32,
+    pub work_radius: u16,
+    pub work: WorkKind,
+}
+
+#[derive(Clone, Default)]
+pub struct ProductionDefs {
+    pub defs: BTreeMap<BuildingType, ProductionDef>,
+}
+
+impl ProductionDefs {
+    pub fn parse(data: &str) -> Result<Self, ron::Error> {
+        let list: Vec<ProductionDef> = ron::de::from_str(data)?;
+        Ok(ProductionDefs {
+            defs: list.into_iter().map(|def| (def.building, def)).collect(),
+        })
+    }
+
+    pub fn load(assets: &mut Assets) -> io::Result<Self> {
+        let data = assets.read_to_string(assets::PRODUCTION)?;
+        let invalid = |e: String| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {}", assets::PRODUCTION, e),
+            )
+        };
+        let defs = Self::parse(&data).map_err(|e| invalid(e.to_string()))?;
+        defs.check().map_err(invalid)?;
+        Ok(defs)
+    }
+
+    // Jede Ware eines Rezepts muss mindestens einmal vorkommen
+    pub fn check(&self) -> Result<(), String> {
+        for def in self.defs.values() {
+            for (good, n) in def.inputs.iter().chain(def.outputs.iter()) {
+                if *n == 0 {
+                    return Err(format!("{:?} lists {:?} with count 0", def.building, good));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, bt: BuildingType) -> Option<&ProductionDef> {
+        self.defs.get(&bt)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WorkPhase {
+    // Arbeiter ist noch auf dem Weg zum Gebäude
+    Arriving,
+    // Wartet auf Eingangswaren, Platz für die Ausgabe oder ein Ziel
+    Idle,
+    Working { ticks: u32 },
+    GoingOut { x: u16, y: u16 },
+    Gathering { x: u16, y: u16, ticks: u32 },
+    Returning,
+}
+
+impl WorkPhase {
+    // Tile, das gerade bearbeitet wird. Andere Arbeiter lassen es in Ruhe.
+    pub fn target(self) -> Option<(u16, u16)> {
+        match self {
+            WorkPhase::GoingOut { x, y } | WorkPhase::Gathering { x, y, .. } => Some((x, y)),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Workshop {
+    pub worker: SettlerId,
+    pub phase: WorkPhase,
+}
+
+// Sucht das nächste Tile, an dem der Arbeiter etwas zu tun hat
+fn find_target(
+    map: &Map,
+    def: &ProductionDef,
+    owner: PlayerId,
+    home: (u16, u16),
+    reserved: &[(u16, u16)],
+    tick: u64,
+) -> Option<(u16, u16)> {
+    let area = map.spiral(home.0, home.1, def.work_radius);
+    let free = |pos: &(u16, u16)| (*pos != home) & !reserved.contains(pos);
+    match def.work {
+        WorkKind::InPlace | WorkKind::Mine(_) => None,
+        WorkKind::FellTree => area
+            .into_iter()
+            .filter(free)
+            .find(|(x, y)| map.tile(*x, *y).object == Some(MapObject::Tree)),
+        WorkKind::CutStone => area
+            .into_iter()
+            .filter(free)
+            .find(|(x, y)| matches!(map.tile(*x, *y).object, Some(MapObject::Stone(_)))),
+        WorkKind::Farm => {
+            let ripe = area.iter().copied().filter(free).find(|(x, y)| {
+                matches!(map.tile(*x, *y).object,
+                    Some(MapObject::Field { planted }) if tick >= planted + FIELD_GROW_TICKS)
+            });
+            ripe.or_else(|| {
+                area.into_iter().filter(free).find(|(x, y)| {
+                    let tile = map.tile(*x, *y);
+                    tile.tile_type.is_buildable()
+                        & (tile.owner == Some(owner))
+                        & tile.object.is_none()
+                        & tile.building.is_none()
+                        & tile.flag.is_none()
+                        & tile.road.is_none()
+                })
+            })
+        }
+        WorkKind::Fish => area.into_iter().filter(free).find(|(x, y)| {
+            map.tile(*x, *y)
+                .deposit
+                .is_some_and(|d| (d.kind == ResourceKind::Fish) & (d.amount > 0))
+                & shore(map, *x, *y).is_some()
+        }),
+    }
+}
+
+// Von hier aus wird am Wasser-Tile (x, y) gefischt
+fn shore(map: &Map, x: u16, y: u16) -> Option<(u16, u16)> {
+    map.neighbours(x, y)
+        .into_iter()
+        .find(|(nx, ny)| map.tile(*nx, *ny).tile_type.is_walkable())
+}
+
+// Produktion in den fertigen Betrieben
+impl World {
+    pub(crate) fn update_workers(&mut self) {
+        let ids: Vec<BuildingId> = self
+            .buildings
+            .values()
+            .filter(|b| b.workshop.is_some())
+            .map(|b| b.id)
+            .collect();
+        for id in ids {
+            let (bt, worker_id, phase) = {
+                let b = &self.buildings[&id];
+                let workshop = b.workshop.as_ref().unwrap();
+                (b.building_type, workshop.worker, workshop.phase)
+            };
+            let def = match self.production.get(bt) {
+                Some(def) => def.clone(),
+                None => continue,
+            };
+            let mut worker = match self.settlers.remove(&worker_id) {
+                Some(worker) => worker,
+                None => {
+                    // Arbeiter verschwunden, es kommt ein neuer
+                    self.buildings.get_mut(&id).unwrap().workshop = None;
+                    continue;
+                }
+            };
+            let phase = self.update_worker(&mut worker, id, &def, phase);
+            self.settlers.insert(worker_id, worker);
+            if let Some(workshop) = self.buildings.get_mut(&id).unwrap().workshop.as_mut() {
+                workshop.phase = phase;
+            }
+        }
+    }
+
+    fn update_worker(
+        &mut self,
+        w: &mut Settler,
+        building: BuildingId,
+        def: &ProductionDef,
+        phase: WorkPhase,
+    ) -> WorkPhase {
+        if let SettlerState::Walking { .. } = w.state {
+            if !w.step() {
+                return phase;
+            }
+            w.state = SettlerState::Working;
+        }
+        let (home_x, home_y, owner) = {
+            let b = &self.buildings[&building];
+            (b.x, b.y, b.owner)
+        };
+        match phase {
+            WorkPhase::Arriving => {
+                w.state = SettlerState::Idle;
+                WorkPhase::Idle
+            }
+            WorkPhase::Idle => {
+                let b = &self.buildings[&building];
+                let has_inputs = def
+                    .inputs
+                    .iter()
+                    .all(|(good, n)| b.stock_of(*good) >= *n as u16);
+                let output_full = b.output.values().sum::<u16>() >= OUTPUT_CAPACITY;
+                if !has_inputs | output_full {
+                    return WorkPhase::Idle;
+                }
+                let next = if def.work == WorkKind::InPlace {
+                    w.state = SettlerState::Working;
+                    WorkPhase::Working { ticks: 0 }
+                } else if let WorkKind::Mine(kind) = def.work {
+                    match self.map.find_deposit(home_x, home_y, def.work_radius, kind) {
+                        Some((x, y)) => {
+                            self.map.take_deposit(x, y);
+                            w.state = SettlerState::Working;
+                            WorkPhase::Working { ticks: 0 }
+                        }
+                        None => return WorkPhase::Idle,
+                    }
+                } else {
+                    let reserved: Vec<(u16, u16)> = self
+                        .buildings
+                        .values()
+                        .filter_map(|b| b.workshop.as_ref().and_then(|ws| ws.phase.target()))
+                        .collect();
+                    match find_target(
+                        &self.map,
+                        def,
+                        owner,
+                        (home_x, home_y),
+                        &reserved,
+                        self.tick,
+                    ) {
+                        Some((x, y)) => {
+                            let (sx, sy) = if def.work == WorkKind::Fish {
+                                shore(&self.map, x, y).unwrap()
+                            } else {
+                                (x, y)
+                            };
+                            if !w.walk_to(&self.map, sx, sy) {
+                                // Kein Weg dorthin, also auch nichts verbrauchen
+                                return WorkPhase::Idle;
+                            }
+                            WorkPhase::GoingOut { x, y }
+                        }
+                        None => return WorkPhase::Idle,
+                    }
+                };
+                let b = self.buildings.get_mut(&building).unwrap();
+                for (good, n) in &def.inputs {
+                    let count = b.stock.entry(*good).or_insert(0);
+                    *count = count.saturating_sub(*n as u16);
+                }
+                b.stock.retain(|_, count| *count > 0);
+                next
+            }
+            WorkPhase::Working { ticks } => {
+                if ticks + 1 < def.cycle_ticks {
+                    return WorkPhase::Working { ticks: ticks + 1 };
+                }
+                self.store_output(building, def);
+                w.state = SettlerState::Idle;
+                WorkPhase::Idle
+            }
+            WorkPhase::GoingOut { x, y } => WorkPhase::Gathering { x, y, ticks: 0 },
+            WorkPhase::Gathering { x, y, ticks } => {
+                if ticks + 1 < def.cycle_ticks {
+                    return WorkPhase::Gathering {
+                        x,
+                        y,
+                        ticks: ticks + 1,
+                    };
+                }
+                if self.gather(def.work, x, y) {
+                    w.carrying_good = def.outputs.first().map(|(good, _)| *good);
+                }
+                w.walk_to(&self.map, home_x, home_y);
+                WorkPhase::Returning
+            }
+            WorkPhase::Returning => {
+                if w.carrying_good.take().is_some() {
+                    self.store_output(building, def);
+                }
+                w.state = SettlerState::Idle;
+                WorkPhase::Idle
+            }
+        }
+    }
+
+    // Bearbeitet das Ziel-Tile. true, wenn es etwas nach Hause zu tragen gibt.
+    fn gather(&mut self, work: WorkKind, x: u16, y: u16) -> bool {
+        if work == WorkKind::Fish {
+            return self.map.take_deposit(x, y);
+        }
+        let tick = self.tick;
+        let tile = self.map.tile_mut(x, y);
+        match (work, tile.object) {
+            (WorkKind::FellTree, Some(MapObject::Tree)) => {
+                tile.object = None;
+                true
+            }
+            (WorkKind::CutStone, Some(MapObject::Stone(n))) => {
+                tile.object = if n > 1 {
+                    Some(MapObject::Stone(n - 1))
+                } else {
+                    None
+                };
+                true
+            }
+            (WorkKind::Farm, Some(MapObject::Field { planted }))
+                if tick >= planted + FIELD_GROW_TICKS =>
+            {
+                tile.object = None;
+                true
+            }
+            (WorkKind::Farm, None) => {
+                // Nur säen, wenn in der Zwischenzeit nichts darauf gebaut wurde
+                if tile.building.is_none() & tile.flag.is_none() & tile.road.is_none() {
+                    tile.object = Some(MapObject::Field { planted: tick });
+                }
+                false
+            }
+            _ => false,
+        }
+    }
+
+    fn store_output(&mut self, building: BuildingId, def: &ProductionDef) {
+        let b = self.buildings.get_mut(&building).unwrap();
+        for (good, n) in &def.outputs {
+            *b.output.entry(*good).or_insert(0) += *n as u16;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{hextile::MapObject, testing};
+
+    #[test]
+    fn woodcutter_and_sawmill_make_boards() {
+        let (mut world, castle) = testing::world();
+        // Ohne die Stämme aus der Burg muss alles Holz vom Holzfäller kommen
+        world
+            .buildings
+            .get_mut(&castle)
+            .unwrap()
+            .stock
+            .remove(&Good::Log);
+        for x in 18..22 {
+            for y in 6..10 {
+                world.map.tile_mut(x, y).object = Some(MapObject::Tree);
+            }
+        }
+        testing::connected_site(&mut world, castle, BuildingType::Woodcutter, 16, 10);
+        let mill = testing::connected_site(&mut world, castle, BuildingType::Sawmill, 4, 10);
+        let board = |w: &World| w.buildings[&mill].output.get(&Good::Board).copied();
+        let ticks = testing::run_until(&mut world, 5000, |w| board(w).unwrap_or(0) > 0);
+        assert!(ticks.is_some());
+    }
+
+    #[test]
+    fn rejects_zero_counts() {
+        let mut defs = testing::production();
+        assert_eq!(defs.check(), Ok(()));
+        defs.defs.get_mut(&BuildingType::Sawmill).unwrap().inputs[0].1 = 0;
+        assert!(defs.check().is_err());
+    }
+}
@@ -0,0 +1,35 @@
+use serde::{Deserialize, Serialize};
+
+// Eigener Zufallsgenerator für die Simulation (SplitMix64). Mit gleichem Startwert
+// liefert er auf jedem Rechner dieselbe Folge, der Zustand ist ein einzelnes u64.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GameRng {
+    pub state: u64,
+}
+
+impl GameRng {
+    pub fn new(seed: u64) -> Self {
+        GameRng { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    // Zahl in [low, high)
+    pub fn range(&mut self, low: u32, high: u32) -> u32 {
+        if high <= low {
+            return low;
+        }
+        low + (self.next_u64() % (high - low) as u64) as u32
+    }
+
+    // true mit `percent` Prozent Wahrscheinlichkeit
+    pub fn chance(&mut self, percent: u32) -> bool {
+        self.range(0, 100) < percent
+    }
+}
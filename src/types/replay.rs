@@ -0,0 +1,310 @@
+use serde::{Deserialize, Serialize};
+use std::{fs, io, path::Path};
+
+use super::{
+    command::TimedCommand,
+    desync::{Desync, StateHash, DESYNC_DIR},
+    map::Map,
+    player::PlayerId,
+    production::ProductionDefs,
+    s4map::S4Map,
+    symmetry::Symmetry,
+    world::World,
+};
+
+// Beim Abspielen wird alle so viele Ticks der Stand gemerkt, zum Zurückspulen
+pub const SNAPSHOT_TICKS: u64 = 600;
+
+// Woraus die Karte am Anfang entsteht
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MapSource {
+    Generated {
+        width: u16,
+        height: u16,
+        seed: u64,
+        // Fehlt in älteren Wiederholungen und Spielständen
+        #[serde(default)]
+        symmetry: Symmetry,
+    },
+    // Karte aus Siedler 4. Sie muss bei jedem Mitspieler unter diesem Pfad liegen.
+    S4File {
+        path: String,
+    },
+}
+
+// Alles, was vor dem ersten Tick feststeht
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GameSetup {
+    pub map: MapSource,
+    // Burg jedes Spielers
+    pub starts: Vec<(PlayerId, u16, u16)>,
+}
+
+impl GameSetup {
+    // Erzeugte Karte, die Spieler verteilt wie die Teile der Karte oder im Kreis
+    pub fn generated(
+        width: u16,
+        height: u16,
+        seed: u64,
+        players: usize,
+        symmetry: Symmetry,
+    ) -> Self {
+        let map = MapSource::Generated {
+            width,
+            height,
+            seed,
+            symmetry,
+        };
+        let starts = Map::generate_symmetric(width, height, seed, symmetry)
+            .symmetric_starts(symmetry, players)
+            .into_iter()
+            .enumerate()
+            .map(|(i, (x, y))| (i as PlayerId, x, y))
+            .collect();
+        GameSetup { map, starts }
+    }
+
+    // Startpunkte aus der Karte, die ersten für die gewünschten Spieler
+    pub fn s4_file(path: &str, players: usize) -> io::Result<Self> {
+        let map = S4Map::load(path)?;
+        if map.starts.len() < players {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "{} has start positions for {} players",
+                    path,
+                    map.starts.len()
+                ),
+            ));
+        }
+        let starts = map
+            .starts
+            .into_iter()
+            .take(players)
+            .enumerate()
+            .map(|(i, (x, y))| (i as PlayerId, x, y))
+            .collect();
+        Ok(GameSetup {
+            map: MapSource::S4File {
+                path: String::from(path),
+            },
+            starts,
+        })
+    }
+
+    fn build_map(source: &MapSource) -> io::Result<Map> {
+        match source {
+            MapSource::Generated {
+                width,
+                height,
+                seed,
+                symmetry,
+            } => Ok(Map::generate_symmetric(*width, *height, *seed, *symmetry)),
+            MapSource::S4File { path } => Ok(S4Map::load_with_resources(path)?.map),
+        }
+    }
+
+    // Gleiches Setup, gleiche Welt
+    pub fn create_world(&self, production: ProductionDefs) -> io::Result<World> {
+        let mut world = World::new(Self::build_map(&self.map)?, production);
+        for (player, x, y) in &self.starts {
+            world.add_player_start(*player, *x, *y).map_err(|err| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("start of player {} at {}/{}: {:?}", player, x, y, err),
+                )
+            })?;
+        }
+        Ok(world)
+    }
+}
+
+// Anfangszustand und jeder Befehl mit seinem Tick. Mehr braucht es nicht, weil die
+// Simulation mit denselben Befehlen immer dasselbe ausrechnet.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Replay {
+    pub setup: GameSetup,
+    // So lange lief das Spiel
+    pub ticks: u64,
+    pub commands: Vec<TimedCommand>,
+    // Zum Prüfen, ob das Abspielen noch dasselbe ausrechnet
+    #[serde(default)]
+    pub hashes: Vec<StateHash>,
+}
+
+impl Replay {
+    pub fn record(setup: &GameSetup, world: &World) -> Self {
+        Replay {
+            setup: setup.clone(),
+            ticks: world.tick,
+            commands: world.command_log.clone(),
+            hashes: world.hash_log.clone(),
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let data = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::new())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+        fs::write(path, data)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let data = fs::read_to_string(path)?;
+        ron::de::from_str(&data)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+    }
+}
+
+// Spielt eine Wiederholung in einer Welt ab, die aus ihrem Setup entstanden ist.
+// Zurückspulen geht vom letzten gemerkten Stand aus neu.
+pub struct Playback {
+    pub replay: Replay,
+    // Nächster Befehl, der noch nicht ausgeführt wurde
+    next: usize,
+    // Stand vor den Befehlen des Ticks, mit dem Befehl, bei dem es weitergeht
+    snapshots: Vec<(World, usize)>,
+    // Erste Abweichung von den aufgezeichneten Hashes
+    pub desync: Option<Desync>,
+}
+
+impl Playback {
+    pub fn new(replay: Replay) -> Self {
+        Playback {
+            replay,
+            next: 0,
+            snapshots: Vec::new(),
+            desync: None,
+        }
+    }
+
+    pub fn create_world(&self, production: ProductionDefs) -> io::Result<World> {
+        self.replay.setup.create_world(production)
+    }
+
+    pub fn is_finished(&self, world: &World) -> bool {
+        world.tick >= self.replay.ticks
+    }
+
+    // Ein Tick: erst die Befehle, die nach dem letzten Tick kamen, dann die Simulation
+    pub fn step(&mut self, world: &mut World) {
+        if self.is_finished(world) {
+            return;
+        }
+        let tick = world.tick;
+        let snapshotted = self
+            .snapshots
+            .last()
+            .is_some_and(|(snapshot, _)| snapshot.tick >= tick);
+        if tick.is_multiple_of(SNAPSHOT_TICKS) & !snapshotted {
+            self.snapshots.push((world.clone(), self.next));
+        }
+        while let Some(timed) = self.replay.commands.get(self.next) {
+            if timed.tick != tick {
+                break;
+            }
+            let _ = world.execute(timed.player, timed.command.clone());
+            self.next += 1;
+        }
+        world.tick();
+        if self.desync.is_none() {
+            let recorded = self.replay.hashes.iter().find(|h| h.tick == world.tick);
+            self.desync =
+                recorded.and_then(|hash| world.check_hash(hash, Path::new(DESYNC_DIR), "replay"));
+        }
+    }
+
+    pub fn fast_forward(&mut self, world: &mut World, ticks: u64) {
+        for _ in 0..ticks {
+            self.step(world);
+        }
+    }
+
+    // Springt zu einem Tick. Rückwärts und weit nach vorn geht es vom nächsten
+    // gemerkten Stand davor aus.
+    pub fn seek(&mut self, world: &mut World, tick: u64) {
+        let target = tick.min(self.replay.ticks);
+        let snapshot = self
+            .snapshots
+            .iter()
+            .rev()
+            .find(|(snapshot, _)| snapshot.tick <= target);
+        if let Some((snapshot, next)) = snapshot {
+            if (target < world.tick) | (snapshot.tick > world.tick) {
+                *world = snapshot.clone();
+                self.next = *next;
+            }
+        }
+        while world.tick < target {
+            self.step(world);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{ai, testing};
+
+    fn setup(starts: Vec<(PlayerId, u16, u16)>) -> GameSetup {
+        GameSetup {
+            map: MapSource::Generated {
+                width: 40,
+                height: 40,
+                seed: 3,
+                symmetry: Symmetry::None,
+            },
+            starts,
+        }
+    }
+
+    #[test]
+    fn starts_at_the_edge_are_an_error() {
+        assert!(setup(vec![(0, 10, 10)])
+            .create_world(testing::production())
+            .is_ok());
+        for start in [(0, 0), (39, 20), (20, 39), (60, 60)] {
+            let result = setup(vec![(0, start.0, start.1)]).create_world(testing::production());
+            assert!(matches!(result, Err(err) if err.kind() == io::ErrorKind::InvalidData));
+        }
+    }
+
+    #[test]
+    fn failed_starts_leave_the_map_alone() {
+        let (mut world, _) = testing::world();
+        let before = format!("{:?}", world.map);
+        assert!(world.add_player_start(1, 0, 0).is_err());
+        assert_eq!(format!("{:?}", world.map), before);
+        assert_eq!(world.buildings.len(), 1);
+    }
+
+    // Stand nach ticks Ticks, von Anfang an ohne Zurückspulen abgespielt
+    fn played(replay: &Replay, ticks: u64) -> StateHash {
+        let mut playback = Playback::new(replay.clone());
+        let mut world = playback.create_world(testing::production()).unwrap();
+        playback.fast_forward(&mut world, ticks);
+        world.state_hash()
+    }
+
+    #[test]
+    fn seeking_matches_playing_from_the_start() {
+        let ticks = 2 * SNAPSHOT_TICKS + 150;
+        let (_, replay) =
+            ai::run_match(testing::production(), 2, 2, 40, Symmetry::None, ticks).unwrap();
+        let mut playback = Playback::new(replay.clone());
+        let mut world = playback.create_world(testing::production()).unwrap();
+        playback.fast_forward(&mut world, ticks);
+        assert_eq!(world.state_hash(), played(&replay, ticks));
+        // Zurück über einen gemerkten Stand, dann wieder nach vorn und ganz an den Anfang
+        for target in [SNAPSHOT_TICKS + 70, 2 * SNAPSHOT_TICKS + 20, 30, 0, ticks] {
+            playback.seek(&mut world, target);
+            assert_eq!(world.tick, target);
+            assert_eq!(
+                world.state_hash(),
+                played(&replay, target),
+                "tick {}",
+                target
+            );
+        }
+        assert!(playback.desync.is_none());
+    }
+}
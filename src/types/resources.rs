@@ -0,0 +1,388 @@
+use coffee::graphics::{Color, Shape};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    hextile::{MapObject, TileType},
+    map::Map,
+    random::GameRng,
+    visibility::Sight,
+    world::World,
+};
+
+// Alle so viele Ticks wachsen Bäume nach und Fische vermehren sich
+pub const RESOURCE_TICKS: u64 = 100;
+
+// So lange braucht ein junger Baum, bis er gefällt werden kann
+pub const TREE_GROW_TICKS: u64 = 1200;
+
+// Mehr Fische passen nicht in ein Wasser-Tile
+pub const FISH_MAX: u8 = 15;
+
+// Chance in Prozent, dass ein Wasser-Tile pro Runde einen Fisch dazubekommt
+pub const FISH_REGROW_CHANCE: u32 = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum ResourceKind {
+    Fish,
+    Coal,
+    Iron,
+    Gold,
+    Sulfur,
+}
+
+pub const RESOURCE_KINDS: [ResourceKind; 5] = [
+    ResourceKind::Fish,
+    ResourceKind::Coal,
+    ResourceKind::Iron,
+    ResourceKind::Gold,
+    ResourceKind::Sulfur,
+];
+
+impl ResourceKind {
+    // Stelle in RESOURCE_KINDS
+    pub fn index(self) -> usize {
+        RESOURCE_KINDS.iter().position(|k| *k == self).unwrap()
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ResourceKind::Fish => "Fish",
+            ResourceKind::Coal => "Coal",
+            ResourceKind::Iron => "Iron",
+            ResourceKind::Gold => "Gold",
+            ResourceKind::Sulfur => "Sulfur",
+        }
+    }
+
+    pub fn color(self) -> Color {
+        match self {
+            ResourceKind::Fish => Color::from_rgb(250, 120, 160),
+            ResourceKind::Coal => Color::from_rgb(20, 20, 20),
+            ResourceKind::Iron => Color::from_rgb(160, 60, 40),
+            ResourceKind::Gold => Color::from_rgb(255, 215, 0),
+            ResourceKind::Sulfur => Color::from_rgb(200, 230, 40),
+        }
+    }
+
+    // Größte Menge, die beim Erzeugen der Karte in ein Tile kommt
+    pub fn max_amount(self) -> u8 {
+        match self {
+            ResourceKind::Fish => FISH_MAX,
+            _ => 12,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Deposit {
+    pub kind: ResourceKind,
+    pub amount: u8,
+}
+
+fn is_water(tt: TileType) -> bool {
+    (tt == TileType::TtOcean) | (tt == TileType::TtRiver)
+}
+
+fn is_forest_ground(tt: TileType) -> bool {
+    matches!(tt, TileType::TtGras | TileType::TtDirt)
+}
+
+impl Map {
+    // Verteilt Wälder, Steine, Fische und Erze über die Karte
+    pub fn generate_resources(&mut self, rng: &mut GameRng) {
+        let positions: Vec<(u16, u16)> = (0..self.width)
+            .flat_map(|x| (0..self.height).map(move |y| (x, y)))
+            .collect();
+
+        // Wälder: erst vereinzelte Bäume, dann wächst um jeden Baum herum ein Wald
+        for (x, y) in &positions {
+            let tile = self.tile_mut(*x, *y);
+            if is_forest_ground(tile.tile_type) & rng.chance(8) {
+                tile.object = Some(MapObject::Tree);
+            }
+        }
+        for _ in 0..2 {
+            for (x, y) in &positions {
+                let trees = self.count_neighbours(*x, *y, |o| o == Some(MapObject::Tree));
+                let tile = self.tile(*x, *y);
+                if is_forest_ground(tile.tile_type)
+                    & tile.object.is_none()
+                    & rng.chance(25 * trees as u32)
+                {
+                    self.tile_mut(*x, *y).object = Some(MapObject::Tree);
+                }
+            }
+        }
+
+        // Steine liegen vor allem am Fuß der Berge
+        for (x, y) in &positions {
+            let near_mountain = self
+                .neighbours(*x, *y)
+                .iter()
+                .any(|(nx, ny)| self.tile(*nx, *ny).tile_type == TileType::TtMountain);
+            let tile = self.tile(*x, *y);
+            let chance = if near_mountain { 12 } else { 2 };
+            if tile.tile_type.is_buildable() & tile.object.is_none() & rng.chance(chance) {
+                let amount = rng.range(4, 9) as u8;
+                self.tile_mut(*x, *y).object = Some(MapObject::Stone(amount));
+            }
+        }
+
+        self.generate_deposits(rng);
+    }
+
+    // Fische und Erze, auch für Karten, deren Gelände von woanders kommt
+    pub fn generate_deposits(&mut self, rng: &mut GameRng) {
+        let positions: Vec<(u16, u16)> = (0..self.width)
+            .flat_map(|x| (0..self.height).map(move |y| (x, y)))
+            .collect();
+
+        // Fische in jedem Wasser, im Meer mehr als im Fluss
+        for (x, y) in &positions {
+            let tile = self.tile_mut(*x, *y);
+            let amount = match tile.tile_type {
+                TileType::TtOcean => rng.range(8, FISH_MAX as u32 + 1),
+                TileType::TtRiver => rng.range(3, 9),
+                _ => continue,
+            };
+            tile.deposit = Some(Deposit {
+                kind: ResourceKind::Fish,
+                amount: amount as u8,
+            });
+        }
+
+        // Erze im Berg. Liegt nebenan schon ein Erz, setzt sich die Ader oft fort.
+        for (x, y) in &positions {
+            if self.tile(*x, *y).tile_type != TileType::TtMountain {
+                continue;
+            }
+            let vein = self
+                .neighbours(*x, *y)
+                .iter()
+                .filter_map(|(nx, ny)| self.tile(*nx, *ny).deposit)
+                .find(|d| d.kind != ResourceKind::Fish)
+                .map(|d| d.kind);
+            let kind = match vein {
+                Some(kind) if rng.chance(50) => Some(kind),
+                _ => match rng.range(0, 100) {
+                    0..=34 => Some(ResourceKind::Coal),
+                    35..=59 => Some(ResourceKind::Iron),
+                    60..=67 => Some(ResourceKind::Gold),
+                    68..=77 => Some(ResourceKind::Sulfur),
+                    _ => None,
+                },
+            };
+            if let Some(kind) = kind {
+                let amount = rng.range(4, kind.max_amount() as u32 + 1) as u8;
+                self.tile_mut(*x, *y).deposit = Some(Deposit { kind, amount });
+            }
+        }
+    }
+
+    fn count_neighbours<F: Fn(Option<MapObject>) -> bool>(&self, x: u16, y: u16, f: F) -> usize {
+        self.neighbours(x, y)
+            .iter()
+            .filter(|(nx, ny)| f(self.tile(*nx, *ny).object))
+            .count()
+    }
+
+    // Nächstes Tile im Umkreis mit einem Vorkommen dieser Art
+    pub fn find_deposit(
+        &self,
+        x: u16,
+        y: u16,
+        radius: u16,
+        kind: ResourceKind,
+    ) -> Option<(u16, u16)> {
+        self.spiral(x, y, radius).into_iter().find(|(tx, ty)| {
+            self.tile(*tx, *ty)
+                .deposit
+                .is_some_and(|d| (d.kind == kind) & (d.amount > 0))
+        })
+    }
+
+    // Nimmt eine Einheit aus dem Vorkommen. Erz ist irgendwann weg, Fische wachsen nach.
+    pub fn take_deposit(&mut self, x: u16, y: u16) -> bool {
+        let tile = self.tile_mut(x, y);
+        match tile.deposit {
+            Some(d) if d.amount > 0 => {
+                let amount = d.amount - 1;
+                tile.deposit = if (amount == 0) & (d.kind != ResourceKind::Fish) {
+                    None
+                } else {
+                    Some(Deposit { amount, ..d })
+                };
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+// Nachwachsen der Rohstoffe
+impl World {
+    pub(crate) fn update_resources(&mut self) {
+        if !self.tick.is_multiple_of(RESOURCE_TICKS) {
+            return;
+        }
+        let tick = self.tick;
+        let map = &mut self.map;
+
+        // Junge Bäume werden groß
+        for tile in map.tiles.iter_mut().flatten() {
+            if let Some(MapObject::Sapling { planted }) = tile.object {
+                if tick >= planted + TREE_GROW_TICKS {
+                    tile.object = Some(MapObject::Tree);
+                }
+            }
+        }
+
+        // Wälder breiten sich aus, aber nicht direkt an Gebäude, Flaggen und Straßen
+        let attempts = (map.width as u32 * map.height as u32) / 100 + 1;
+        for _ in 0..attempts {
+            let x = self.rng.range(0, map.width as u32) as u16;
+            let y = self.rng.range(0, map.height as u32) as u16;
+            let tile = map.tile(x, y);
+            if !is_forest_ground(tile.tile_type) | tile.object.is_some() {
+                continue;
+            }
+            let mut area = map.neighbours(x, y);
+            area.push((x, y));
+            let blocked = area.iter().any(|(nx, ny)| {
+                let t = map.tile(*nx, *ny);
+                t.building.is_some() | t.flag.is_some() | t.road.is_some()
+            });
+            if !blocked & (map.count_neighbours(x, y, |o| o == Some(MapObject::Tree)) > 0) {
+                map.tile_mut(x, y).object = Some(MapObject::Sapling { planted: tick });
+            }
+        }
+
+        // Fische vermehren sich
+        for tile in map.tiles.iter_mut().flatten() {
+            if !is_water(tile.tile_type) {
+                continue;
+            }
+            if let Some(deposit) = tile.deposit.as_mut() {
+                if (deposit.kind == ResourceKind::Fish)
+                    & (deposit.amount < FISH_MAX)
+                    & self.rng.chance(FISH_REGROW_CHANCE)
+                {
+                    deposit.amount += 1;
+                }
+            }
+        }
+    }
+
+    // Rohstoff-Ansicht: Vorkommen als Punkte, je größer desto mehr. Nur auf erkundetem
+    // Land, sonst verrät sie, was unter dem Nebel liegt.
+    pub fn draw_resource_overlay(&mut self, param: &mut crate::DrawParameter) {
+        for tile in self.map.tiles.iter().flatten() {
+            if param
+                .viewer
+                .is_some_and(|player| self.sight(player, tile.x, tile.y) == Sight::Unexplored)
+            {
+                continue;
+            }
+            let (color, fill) = match (tile.deposit, tile.object) {
+                (Some(d), _) if d.amount > 0 => {
+                    (d.kind.color(), d.amount as f32 / d.kind.max_amount() as f32)
+                }
+                (_, Some(MapObject::Stone(n))) => (MapObject::Stone(n).color(), n as f32 / 8.0),
+                _ => continue,
+            };
+            let center = tile.get_center(param.camera.cameraoffset_x, param.camera.cameraoffset_y);
+            let radius = tile.height as f32 * (0.15 + 0.3 * fill.min(1.0));
+            param
+                .camera
+                .mesh
+                .fill(Shape::Circle { center, radius }, color);
+            param.camera.mesh.stroke(
+                Shape::Circle { center, radius },
+                Color::from_rgb(255, 255, 255),
+                1.0,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{editor::blank_map, symmetry::Symmetry};
+
+    // Bäume, Steine und Vorkommen jedes Tiles
+    fn resources(map: &Map) -> Vec<(Option<MapObject>, Option<Deposit>)> {
+        map.tiles
+            .iter()
+            .flatten()
+            .map(|t| (t.object, t.deposit))
+            .collect()
+    }
+
+    #[test]
+    fn same_seed_same_resources() {
+        let map = Map::generate_symmetric(40, 40, 4, Symmetry::None);
+        assert_eq!(
+            resources(&map),
+            resources(&Map::generate_symmetric(40, 40, 4, Symmetry::None))
+        );
+        assert_ne!(
+            resources(&map),
+            resources(&Map::generate_symmetric(40, 40, 5, Symmetry::None))
+        );
+        for tile in map.tiles.iter().flatten() {
+            if let Some(MapObject::Tree) = tile.object {
+                assert!(is_forest_ground(tile.tile_type));
+            }
+            if let Some(d) = tile.deposit {
+                assert!((d.amount > 0) & (d.amount <= d.kind.max_amount()));
+                assert_eq!(
+                    d.kind == ResourceKind::Fish,
+                    is_water(tile.tile_type),
+                    "{:?} on {:?}",
+                    d.kind,
+                    tile.tile_type
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn deposits_follow_the_ground() {
+        let mut map = blank_map(20, 20);
+        for y in 0..20 {
+            map.tile_mut(3, y).tile_type = TileType::TtOcean;
+            map.tile_mut(10, y).tile_type = TileType::TtMountain;
+        }
+        let mut again = map.clone();
+        map.generate_deposits(&mut GameRng::new(9));
+        again.generate_deposits(&mut GameRng::new(9));
+        assert_eq!(resources(&map), resources(&again));
+        assert!((0..20).all(|y| map.tile(3, y).deposit.unwrap().kind == ResourceKind::Fish));
+        assert!((0..20).any(|y| map.tile(10, y).deposit.is_some()));
+        assert!(map
+            .tiles
+            .iter()
+            .flatten()
+            .filter(|t| (t.x != 3) & (t.x != 10))
+            .all(|t| t.deposit.is_none()));
+    }
+
+    #[test]
+    fn ore_runs_out_but_fish_stay() {
+        let mut map = blank_map(4, 4);
+        map.tile_mut(1, 1).deposit = Some(Deposit {
+            kind: ResourceKind::Coal,
+            amount: 1,
+        });
+        map.tile_mut(2, 2).deposit = Some(Deposit {
+            kind: ResourceKind::Fish,
+            amount: 1,
+        });
+        assert!(map.take_deposit(1, 1) & map.take_deposit(2, 2));
+        assert_eq!(map.tile(1, 1).deposit, None);
+        assert_eq!(map.tile(2, 2).deposit.unwrap().amount, 0);
+        assert!(!map.take_deposit(1, 1) & !map.take_deposit(2, 2));
+        assert_eq!(map.find_deposit(0, 0, 4, ResourceKind::Fish), None);
+    }
+}
@@ -0,0 +1,397 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, BinaryHeap},
+};
+
+use super::{
+    building::BuildingId, map::Map, player::PlayerId, settler::SettlerId, transport::WareId,
+};
+
+pub type FlagId = u32;
+pub type RoadId = u32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RoadError {
+    OutOfMap,
+    Terrain,
+    Territory,
+    Blocked,
+    FlagTooClose,
+    NotAdjacent,
+    NoStartFlag,
+    TooShort,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Flag {
+    pub id: FlagId,
+    pub owner: PlayerId,
+    pub x: u16,
+    pub y: u16,
+    pub building: Option<BuildingId>,
+    // Waren, die hier auf einen Träger warten
+    pub wares: Vec<WareId>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Road {
+    pub id: RoadId,
+    pub owner: PlayerId,
+    pub from: FlagId,
+    pub to: FlagId,
+    // Alle Tiles inklusive der beiden Flaggen
+    pub path: Vec<(u16, u16)>,
+    pub carrier: Option<SettlerId>,
+}
+
+impl Road {
+    pub fn length(&self) -> u16 {
+        (self.path.len() - 1) as u16
+    }
+
+    pub fn other_end(&self, flag: FlagId) -> FlagId {
+        if self.from == flag {
+            self.to
+        } else {
+            self.from
+        }
+    }
+}
+
+impl Default for RoadNetwork {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Flaggen sind die Knoten, Straßen die Kanten
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoadNetwork {
+    pub flags: BTreeMap<FlagId, Flag>,
+    pub roads: BTreeMap<RoadId, Road>,
+    pub next_id: u32,
+}
+
+impl RoadNetwork {
+    pub fn new() -> Self {
+        RoadNetwork {
+            flags: BTreeMap::new(),
+            roads: BTreeMap::new(),
+            next_id: 1,
+        }
+    }
+
+    fn next_id(&mut self) -> u32 {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
+    pub fn roads_at(&self, flag: FlagId) -> Vec<RoadId> {
+        self.roads
+            .values()
+            .filter(|r| (r.from == flag) | (r.to == flag))
+            .map(|r| r.id)
+            .collect()
+    }
+
+    // Nachbarflaggen mit der Straße dorthin
+    pub fn neighbours(&self, flag: FlagId) -> Vec<(FlagId, RoadId)> {
+        self.roads_at(flag)
+            .into_iter()
+            .map(|r| (self.roads[&r].other_end(flag), r))
+            .collect()
+    }
+
+    // Erste Straße auf dem kürzesten Weg von `from` nach `to` (Dijkstra über die Flaggen)
+    pub fn next_hop(&self, from: FlagId, to: FlagId) -> Option<RoadId> {
+        self.shortest_path(from, to)
+            .and_then(|(_, roads)| roads.first().copied())
+    }
+
+    pub fn distance(&self, from: FlagId, to: FlagId) -> Option<u32> {
+        self.shortest_path(from, to).map(|(dist, _)| dist)
+    }
+
+    pub fn shortest_path(&self, from: FlagId, to: FlagId) -> Option<(u32, Vec<RoadId>)> {
+        if !self.flags.contains_key(&from) | !self.flags.contains_key(&to) {
+            return None;
+        }
+        let mut dist: BTreeMap<FlagId, u32> = BTreeMap::new();
+        let mut came_from: BTreeMap<FlagId, (FlagId, RoadId)> = BTreeMap::new();
+        // Gleich lange Wege werden über die Flaggen-Id entschieden, damit das Ergebnis
+        // auf jedem Rechner gleich ist
+        let mut queue = BinaryHeap::new();
+        dist.insert(from, 0);
+        queue.push(Reverse((0u32, from)));
+        while let Some(Reverse((d, flag))) = queue.pop() {
+            if flag == to {
+                let mut roads = Vec::new();
+                let mut current = to;
+                while let Some((prev, road)) = came_from.get(&current) {
+                    roads.push(*road);
+                    current = *prev;
+                }
+                roads.reverse();
+                return Some((d, roads));
+            }
+            if dist.get(&flag).is_some_and(|best| d > *best) {
+                continue;
+            }
+            for (next, road) in self.neighbours(flag) {
+                let nd = d + self.roads[&road].length() as u32;
+                if dist.get(&next).is_none_or(|best| nd < *best) {
+                    dist.insert(next, nd);
+                    came_from.insert(next, (flag, road));
+                    queue.push(Reverse((nd, next)));
+                }
+            }
+        }
+        None
+    }
+
+    pub fn check_flag(map: &Map, player: PlayerId, x: u16, y: u16) -> Result<(), RoadError> {
+        if !map.contains(x as i32, y as i32) {
+            return Err(RoadError::OutOfMap);
+        }
+        let tile = map.tile(x, y);
+        if !tile.tile_type.is_walkable() {
+            return Err(RoadError::Terrain);
+        }
+        if tile.owner != Some(player) {
+            return Err(RoadError::Territory);
+        }
+        if tile.building.is_some() | tile.flag.is_some() | tile.object.is_some() {
+            return Err(RoadError::Blocked);
+        }
+        // Zwischen zwei Flaggen muss immer mindestens ein Tile frei sein
+        if map
+            .neighbours(x, y)
+            .iter()
+            .any(|(nx, ny)| map.tile(*nx, *ny).flag.is_some())
+        {
+            return Err(RoadError::FlagTooClose);
+        }
+        Ok(())
+    }
+
+    // Setzt eine Flagge. Liegt sie auf einer Straße, wird die Straße geteilt.
+    pub fn place_flag(
+        &mut self,
+        map: &mut Map,
+        player: PlayerId,
+        x: u16,
+        y: u16,
+    ) -> Result<FlagId, RoadError> {
+        Self::check_flag(map, player, x, y)?;
+        let id = self.next_id();
+        self.flags.insert(
+            id,
+            Flag {
+                id,
+                owner: player,
+                x,
+                y,
+                building: None,
+                wares: Vec::new(),
+            },
+        );
+        let split = map.tile(x, y).road;
+        map.tile_mut(x, y).flag = Some(id);
+        if let Some(road_id) = split {
+            self.split_road(map, road_id, id, x, y);
+        }
+        Ok(id)
+    }
+
+    fn split_road(&mut self, map: &mut Map, road_id: RoadId, flag: FlagId, x: u16, y: u16) {
+        let new_id = self.next_id();
+        let road = self.roads.get_mut(&road_id).unwrap();
+        let index = road.path.iter().position(|p| *p == (x, y)).unwrap();
+        let second_path = road.path.split_off(index);
+        road.path.push((x, y));
+        let second = Road {
+            id: new_id,
+            owner: road.owner,
+            from: flag,
+            to: road.to,
+            path: second_path,
+            carrier: None,
+        };
+        road.to = flag;
+        map.tile_mut(x, y).road = None;
+        for (tx, ty) in &second.path[1..second.path.len() - 1] {
+            map.tile_mut(*tx, *ty).road = Some(new_id);
+        }
+        self.roads.insert(new_id, second);
+    }
+
+    pub fn check_road(
+        &self,
+        map: &Map,
+        player: PlayerId,
+        path: &[(u16, u16)],
+    ) -> Result<(), RoadError> {
+        if path.len() < 2 {
+            return Err(RoadError::TooShort);
+        }
+        if path
+            .iter()
+            .any(|(x, y)| !map.contains(*x as i32, *y as i32))
+        {
+            return Err(RoadError::OutOfMap);
+        }
+        let (sx, sy) = path[0];
+        if map.tile(sx, sy).flag.is_none() {
+            return Err(RoadError::NoStartFlag);
+        }
+        for pair in path.windows(2) {
+            if Map::hex_distance(pair[0].0, pair[0].1, pair[1].0, pair[1].1) != 1 {
+                return Err(RoadError::NotAdjacent);
+            }
+        }
+        for (i, (x, y)) in path.iter().enumerate() {
+            if path[..i].contains(&(*x, *y)) {
+                return Err(RoadError::Blocked);
+            }
+            let tile = map.tile(*x, *y);
+            if tile.owner != Some(player) {
+                return Err(RoadError::Territory);
+            }
+            if !tile.tile_type.is_walkable() {
+                return Err(RoadError::Terrain);
+            }
+            let inner = (i > 0) & (i < path.len() - 1);
+            let blocked = tile.flag.is_some()
+                | tile.road.is_some()
+                | tile.building.is_some()
+                | tile.object.is_some();
+            if inner & blocked {
+                return Err(RoadError::Blocked);
+            }
+        }
+        // Endet die Straße nicht an einer Flagge, muss dort eine hin dürfen
+        let (ex, ey) = path[path.len() - 1];
+        if map.tile(ex, ey).flag.is_none() {
+            Self::check_flag(map, player, ex, ey)?;
+        }
+        Ok(())
+    }
+
+    pub fn build_road(
+        &mut self,
+        map: &mut Map,
+        player: PlayerId,
+        path: Vec<(u16, u16)>,
+    ) -> Result<RoadId, RoadError> {
+        self.check_road(map, player, &path)?;
+        let (sx, sy) = path[0];
+        let (ex, ey) = path[path.len() - 1];
+        let from = map.tile(sx, sy).flag.unwrap();
+        let to = match map.tile(ex, ey).flag {
+            Some(flag) => flag,
+            None => self.place_flag(map, player, ex, ey)?,
+        };
+        let id = self.next_id();
+        for (x, y) in &path[1..path.len() - 1] {
+            map.tile_mut(*x, *y).road = Some(id);
+        }
+        self.roads.insert(
+            id,
+            Road {
+                id,
+                owner: player,
+                from,
+                to,
+                path,
+                carrier: None,
+            },
+        );
+        Ok(id)
+    }
+
+    pub fn remove_road(&mut self, map: &mut Map, id: RoadId) {
+        if let Some(road) = self.roads.remove(&id) {
+            for (x, y) in &road.path[1..road.path.len() - 1] {
+                map.tile_mut(*x, *y).road = None;
+            }
+        }
+    }
+
+    // Entfernt die Flagge mit allen Straßen. Liefert das Gebäude, das daran hing.
+    pub fn remove_flag(&mut self, map: &mut Map, id: FlagId) -> Option<BuildingId> {
+        for road in self.roads_at(id) {
+            self.remove_road(map, road);
+        }
+        let flag = self.flags.remove(&id)?;
+        map.tile_mut(flag.x, flag.y).flag = None;
+        flag.building
+    }
+
+    // Verlängert den Pfad für das Straßenwerkzeug Schritt für Schritt bis (x, y).
+    // Liegt (x, y) schon auf dem Pfad, wird bis dorthin zurückgenommen.
+    pub fn extend_path(map: &Map, path: &mut Vec<(u16, u16)>, x: u16, y: u16) {
+        if let Some(index) = path.iter().position(|p| *p == (x, y)) {
+            path.truncate(index + 1);
+            return;
+        }
+        while let Some(&(lx, ly)) = path.last() {
+            if (lx, ly) == (x, y) {
+                break;
+            }
+            let next = map
+                .neighbours(lx, ly)
+                .into_iter()
+                .filter(|n| !path.contains(n))
+                .min_by_key(|(nx, ny)| Map::hex_distance(*nx, *ny, x, y));
+            match next {
+                Some(n) if Map::hex_distance(n.0, n.1, x, y) < Map::hex_distance(lx, ly, x, y) => {
+                    path.push(n)
+                }
+                _ => break,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::testing;
+
+    #[test]
+    fn roads_off_the_map_are_rejected() {
+        let (world, castle) = testing::world();
+        let flag = &world.roads.flags[&world.buildings[&castle].flag];
+        let start = (flag.x, flag.y);
+        let roads = [
+            vec![start, (start.0, world.map.height)],
+            vec![(u16::MAX, u16::MAX), start],
+            vec![start, (start.0 + 1, start.1), (world.map.width + 5, 0)],
+        ];
+        for path in roads.iter() {
+            assert_eq!(
+                world.roads.check_road(&world.map, 0, path),
+                Err(RoadError::OutOfMap)
+            );
+        }
+    }
+
+    #[test]
+    fn roads_need_a_start_flag_and_neighbouring_tiles() {
+        let (world, castle) = testing::world();
+        let flag = &world.roads.flags[&world.buildings[&castle].flag];
+        let start = (flag.x, flag.y);
+        let check = |path: &[(u16, u16)]| world.roads.check_road(&world.map, 0, path);
+        assert_eq!(check(&[start]), Err(RoadError::TooShort));
+        assert_eq!(check(&[(14, 14), (15, 14)]), Err(RoadError::NoStartFlag));
+        assert_eq!(
+            check(&[start, (start.0 + 3, start.1)]),
+            Err(RoadError::NotAdjacent)
+        );
+        let mut path = vec![start];
+        RoadNetwork::extend_path(&world.map, &mut path, start.0 + 4, start.1);
+        assert_eq!(check(&path), Ok(()));
+    }
+}
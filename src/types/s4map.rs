@@ -0,0 +1,760 @@
+use std::{
+    convert::TryInto,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use super::{
+    hextile::{MapObject, Tile, TileType},
+    map::Map,
+    random::GameRng,
+    resources::{Deposit, RESOURCE_KINDS},
+};
+
+// Kartenformat von Siedler 4 (.map, .edm). Aufbau:
+//   u32 Prüfsumme, u32 Version
+//   danach Blöcke bis zum Dateiende, jeder mit 24 Byte Kopf:
+//   i32 Typ, u32 gepackte Länge, u32 entpackte Länge, u32 Prüfsumme, 2 × u32 unbekannt
+// Alle Zahlen little-endian. Die Prüfsummen werden nicht nachgerechnet.
+// Die Karten aus dem Spiel sind gepackt, der Editor schreibt auch ungepackte Blöcke.
+// Geschrieben wird immer ungepackt.
+//
+// Gepackte Blöcke sind ein Bitstrom, höchstes Bit zuerst. Jedes Zeichen steht an einer
+// Stelle einer Tabelle aus SYMBOLS Zeichen: 4 Bit Gruppe, dann CODE_BITS[Gruppe] Bits
+// für die Stelle in der Gruppe. Zeichen 0 bis 255 sind Bytes, SYMBOL_COPY bis 271
+// kopieren schon Entpacktes, SYMBOL_SORT sortiert die Tabelle nach Häufigkeit neu und
+// SYMBOL_END beendet den Block. Am Anfang steht jedes Zeichen an seiner eigenen Stelle.
+// Eine Kopie liest nach dem Zeichen LENGTH_BITS Bits für die Länge, dann 4 Bit n und
+// n Bits für den Abstand zurück, der ist 2^n plus diese Bits.
+
+pub const CHUNK_MAP_INFO: i32 = 1;
+pub const CHUNK_PLAYERS: i32 = 2;
+pub const CHUNK_OBJECTS: i32 = 6;
+pub const CHUNK_LANDSCAPE: i32 = 13;
+// Gibt es im Original nicht: Rohstoffe im Boden, ein Byte pro Tile wie die Landschaft.
+// Oben die Art ab 1, unten die Menge, 0 heißt nichts. Das Original überspringt
+// unbekannte Blöcke.
+pub const CHUNK_RESOURCES: i32 = 100;
+
+const SYMBOLS: usize = 274;
+const SYMBOL_COPY: u16 = 256;
+const SYMBOL_SORT: u16 = 272;
+const SYMBOL_END: u16 = 273;
+
+// Bits für die Stelle in jeder der 16 Gruppen, die Gruppen liegen hintereinander.
+// Die letzten Gruppen reichen über die Tabelle hinaus.
+const CODE_BITS: [u8; 16] = [2, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 6, 6, 6, 7, 7];
+
+// Zusätzliche Bits der 16 Kopierlängen. Die kürzeste Kopie ist MIN_COPY lang, jede
+// Länge beginnt, wo die vorige aufhört.
+const LENGTH_BITS: [u8; 16] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 2, 3, 4, 5, 6, 7, 8];
+const MIN_COPY: usize = 4;
+
+const FILE_HEADER_LEN: usize = 8;
+const CHUNK_HEADER_LEN: usize = 24;
+
+// Größere Blöcke gibt es in keiner Karte, das ist dann ein kaputter Kopf
+const MAX_CHUNK_LEN: u32 = 64 * 1024 * 1024;
+
+// Kartengrößen im Original gehen bis 1024
+const MAX_MAP_SIZE: usize = 1024;
+
+// Spieltyp, Spielerzahl, Startausstattung, Kartengröße, je i32
+const MAP_INFO_LEN: usize = 16;
+
+// Volk, x, y als i32 und 33 Byte Name
+const PLAYER_RECORD_LEN: usize = 45;
+const PLAYER_NAME_LEN: usize = 33;
+
+// Höhe, Boden, Flags, unbekannt
+const LANDSCAPE_TILE_LEN: usize = 4;
+
+// Die Originalhöhen sind viel feiner als unsere Stufen
+const HEIGHT_STEP: u8 = 8;
+
+// Höher lässt sich ein Tile nicht speichern
+pub const MAX_ELEVATION: u8 = u8::MAX / HEIGHT_STEP;
+
+// Mehr passt nicht in die untere Hälfte des Rohstoff-Bytes
+const MAX_RESOURCE_AMOUNT: u8 = 15;
+
+// Gibt es im Original nicht, dort sind Wege keine Böden. Liegt hinter allen Böden des
+// Originals, damit Straßen aus dem Editor das Speichern überstehen.
+const GROUND_ROAD: u8 = 250;
+
+// Objektnummern: erst die Baumarten, weiter hinten Steine mit 1 bis 13 Brocken
+const TREE_OBJECTS: (u8, u8) = (1, 18);
+const STONE_OBJECTS: (u8, u8) = (124, 136);
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_i32(data: &[u8], offset: usize) -> i32 {
+    i32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct S4Chunk {
+    pub chunk_type: i32,
+    pub unpacked_len: u32,
+    pub checksum: u32,
+    pub unknown: [u32; 2],
+    // So wie in der Datei, gepackt wenn die Längen verschieden sind
+    pub data: Vec<u8>,
+}
+
+impl S4Chunk {
+    // Ungepackter Block, so schreiben wir selbst
+    pub fn stored(chunk_type: i32, data: Vec<u8>) -> Self {
+        S4Chunk {
+            chunk_type,
+            unpacked_len: data.len() as u32,
+            checksum: 0,
+            unknown: [0, 0],
+            data,
+        }
+    }
+
+    pub fn is_packed(&self) -> bool {
+        self.data.len() != self.unpacked_len as usize
+    }
+
+    pub fn data(&self) -> io::Result<Vec<u8>> {
+        if self.is_packed() {
+            unpack(&self.data, self.unpacked_len as usize)
+                .map_err(|e| invalid(format!("chunk {}: {}", self.chunk_type, e)))
+        } else {
+            Ok(self.data.clone())
+        }
+    }
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    // In Bits
+    pos: usize,
+}
+
+impl BitReader<'_> {
+    fn read(&mut self, bits: u8) -> io::Result<usize> {
+        let mut value = 0;
+        for _ in 0..bits {
+            let byte = self
+                .data
+                .get(self.pos / 8)
+                .ok_or_else(|| invalid(String::from("packed data ends early")))?;
+            value = (value << 1) | (byte >> (7 - self.pos % 8) & 1) as usize;
+            self.pos += 1;
+        }
+        Ok(value)
+    }
+}
+
+// Wo jede Gruppe oder Länge beginnt, wenn sie mit first anfangen und jede 2^bits umfasst
+fn group_start(bits: &[u8], first: usize, index: usize) -> usize {
+    first + bits[..index].iter().map(|b| 1 << b).sum::<usize>()
+}
+
+fn unpack(data: &[u8], len: usize) -> io::Result<Vec<u8>> {
+    let mut reader = BitReader { data, pos: 0 };
+    let mut table: Vec<u16> = (0..SYMBOLS as u16).collect();
+    let mut counts = [0u32; SYMBOLS];
+    // Nicht nach dem Kopf vorbelegen, der kann aus einer kaputten Datei stammen
+    let mut out = Vec::new();
+    loop {
+        let group = reader.read(4)?;
+        let position = group_start(&CODE_BITS, 0, group) + reader.read(CODE_BITS[group])?;
+        let symbol = *table
+            .get(position)
+            .ok_or_else(|| invalid(format!("code {} is not in the table", position)))?;
+        counts[symbol as usize] += 1;
+        match symbol {
+            0..=255 => out.push(symbol as u8),
+            // Stabil, bei gleicher Häufigkeit bleibt die alte Reihenfolge
+            SYMBOL_SORT => table.sort_by_key(|s| std::cmp::Reverse(counts[*s as usize])),
+            SYMBOL_END => break,
+            _ => {
+                let index = (symbol - SYMBOL_COPY) as usize;
+                let length =
+                    group_start(&LENGTH_BITS, MIN_COPY, index) + reader.read(LENGTH_BITS[index])?;
+                let bits = reader.read(4)? as u8;
+                let distance = (1 << bits) + reader.read(bits)?;
+                if distance > out.len() {
+                    return Err(invalid(format!(
+                        "copy from {} bytes back after {} bytes",
+                        distance,
+                        out.len()
+                    )));
+                }
+                for _ in 0..length {
+                    out.push(out[out.len() - distance]);
+                }
+            }
+        }
+        if out.len() > len {
+            return Err(invalid(format!("unpacks to more than {} bytes", len)));
+        }
+    }
+    if out.len() != len {
+        return Err(invalid(format!(
+            "unpacks to {} bytes instead of {}",
+            out.len(),
+            len
+        )));
+    }
+    Ok(out)
+}
+
+// Die Datei als Folge von Blöcken, noch ohne sie zu deuten
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct S4MapFile {
+    pub checksum: u32,
+    pub version: u32,
+    pub chunks: Vec<S4Chunk>,
+}
+
+impl S4MapFile {
+    pub fn parse(data: &[u8]) -> io::Result<Self> {
+        if data.len() < FILE_HEADER_LEN {
+            return Err(invalid(String::from("file too short for a map header")));
+        }
+        let mut chunks = Vec::new();
+        let mut offset = FILE_HEADER_LEN;
+        while offset < data.len() {
+            if data.len() - offset < CHUNK_HEADER_LEN {
+                return Err(invalid(format!("truncated chunk header at {}", offset)));
+            }
+            let chunk_type = read_i32(data, offset);
+            let packed_len = read_u32(data, offset + 4);
+            let unpacked_len = read_u32(data, offset + 8);
+            if (chunk_type < 0) | (packed_len > MAX_CHUNK_LEN) | (unpacked_len > MAX_CHUNK_LEN) {
+                return Err(invalid(format!("unreadable chunk header at {}", offset)));
+            }
+            let start = offset + CHUNK_HEADER_LEN;
+            let end = start + packed_len as usize;
+            if end > data.len() {
+                return Err(invalid(format!(
+                    "chunk {} at {} is cut off",
+                    chunk_type, offset
+                )));
+            }
+            chunks.push(S4Chunk {
+                chunk_type,
+                unpacked_len,
+                checksum: read_u32(data, offset + 12),
+                unknown: [read_u32(data, offset + 16), read_u32(data, offset + 20)],
+                data: data[start..end].to_vec(),
+            });
+            offset = end;
+        }
+        Ok(S4MapFile {
+            checksum: read_u32(data, 0),
+            version: read_u32(data, 4),
+            chunks,
+        })
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::parse(&fs::read(path)?)
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&self.checksum.to_le_bytes());
+        data.extend_from_slice(&self.version.to_le_bytes());
+        for chunk in &self.chunks {
+            data.extend_from_slice(&chunk.chunk_type.to_le_bytes());
+            data.extend_from_slice(&(chunk.data.len() as u32).to_le_bytes());
+            data.extend_from_slice(&chunk.unpacked_len.to_le_bytes());
+            data.extend_from_slice(&chunk.checksum.to_le_bytes());
+            for unknown in &chunk.unknown {
+                data.extend_from_slice(&unknown.to_le_bytes());
+            }
+            data.extend_from_slice(&chunk.data);
+        }
+        data
+    }
+
+    pub fn chunk(&self, chunk_type: i32) -> Option<&S4Chunk> {
+        self.chunks.iter().find(|c| c.chunk_type == chunk_type)
+    }
+}
+
+// Böden des Originals, in Sechzehnergruppen mit ihren Übergängen
+fn tile_type_from_ground(ground: u8) -> TileType {
+    match ground {
+        0..=7 => TileType::TtOcean,
+        32..=47 => TileType::TtMountain,
+        // Strand und Wüste
+        48..=79 => TileType::TtSand,
+        // Sumpf und Schlamm
+        80..=95 | 144..=159 => TileType::TtDirt,
+        96..=111 => TileType::TtRiver,
+        128..=143 => TileType::TtSnow,
+        GROUND_ROAD => TileType::TtRoad,
+        _ => TileType::TtGras,
+    }
+}
+
+fn ground_from_tile_type(tt: TileType) -> u8 {
+    match tt {
+        TileType::TtOcean => 0,
+        TileType::TtGras => 16,
+        TileType::TtRoad => GROUND_ROAD,
+        TileType::TtMountain => 32,
+        TileType::TtSand => 48,
+        TileType::TtRiver => 96,
+        TileType::TtSnow => 128,
+        TileType::TtDirt => 144,
+    }
+}
+
+fn object_from_id(id: u8) -> Option<MapObject> {
+    if (id >= TREE_OBJECTS.0) & (id <= TREE_OBJECTS.1) {
+        Some(MapObject::Tree)
+    } else if (id >= STONE_OBJECTS.0) & (id <= STONE_OBJECTS.1) {
+        Some(MapObject::Stone(id - STONE_OBJECTS.0 + 1))
+    } else {
+        None
+    }
+}
+
+fn id_from_object(object: Option<MapObject>) -> u8 {
+    match object {
+        Some(MapObject::Tree) => TREE_OBJECTS.0,
+        Some(MapObject::Stone(n)) => {
+            STONE_OBJECTS.0 + n.clamp(1, STONE_OBJECTS.1 - STONE_OBJECTS.0 + 1) - 1
+        }
+        // Junge Bäume und Felder gibt es im Original nicht als Kartenobjekt
+        _ => 0,
+    }
+}
+
+fn deposit_from_byte(byte: u8) -> Option<Deposit> {
+    let kind = *RESOURCE_KINDS.get((byte >> 4).checked_sub(1)? as usize)?;
+    Some(Deposit {
+        kind,
+        amount: byte & MAX_RESOURCE_AMOUNT,
+    })
+}
+
+fn byte_from_deposit(deposit: Option<Deposit>) -> u8 {
+    match deposit {
+        Some(d) => {
+            let kind = d.kind.index() as u8 + 1;
+            (kind << 4) | d.amount.min(MAX_RESOURCE_AMOUNT)
+        }
+        None => 0,
+    }
+}
+
+// Eine gelesene Karte: Gelände, Bäume und Steine und die Startpunkte der Spieler
+pub struct S4Map {
+    pub map: Map,
+    pub starts: Vec<(u16, u16)>,
+    // Nur selbst gespeicherte Karten bringen ihre Rohstoffe im Boden mit
+    pub has_resources: bool,
+}
+
+impl S4Map {
+    pub fn decode(file: &S4MapFile) -> io::Result<Self> {
+        let landscape = file
+            .chunk(CHUNK_LANDSCAPE)
+            .ok_or_else(|| invalid(String::from("map has no landscape")))?
+            .data()?;
+        let tiles = landscape.len() / LANDSCAPE_TILE_LEN;
+        let size = (tiles as f64).sqrt() as usize;
+        if (size == 0)
+            | (size > MAX_MAP_SIZE)
+            | (size * size * LANDSCAPE_TILE_LEN != landscape.len())
+        {
+            return Err(invalid(format!(
+                "landscape of {} bytes is not a square map",
+                landscape.len()
+            )));
+        }
+        let info = match file.chunk(CHUNK_MAP_INFO) {
+            Some(chunk) => Some(chunk.data()?),
+            None => None,
+        };
+        if let Some(info) = info.as_ref().filter(|info| info.len() >= MAP_INFO_LEN) {
+            let stated = read_i32(info, 12);
+            if stated as usize != size {
+                return Err(invalid(format!(
+                    "map size {} does not match landscape size {}",
+                    stated, size
+                )));
+            }
+        }
+
+        let objects = match file.chunk(CHUNK_OBJECTS) {
+            Some(chunk) => chunk.data()?,
+            None => Vec::new(),
+        };
+        let object_len = objects.len() / tiles;
+        if !objects.is_empty() & ((object_len == 0) | (object_len * tiles != objects.len())) {
+            return Err(invalid(format!(
+                "objects of {} bytes don't fit {} tiles",
+                objects.len(),
+                tiles
+            )));
+        }
+
+        let resources = match file.chunk(CHUNK_RESOURCES) {
+            Some(chunk) => chunk.data()?,
+            None => Vec::new(),
+        };
+        if !resources.is_empty() & (resources.len() != tiles) {
+            return Err(invalid(format!(
+                "resources of {} bytes don't fit {} tiles",
+                resources.len(),
+                tiles
+            )));
+        }
+
+        // Zeilenweise gespeichert, y außen
+        let map = Map {
+            width: size as u16,
+            height: size as u16,
+            tiles: (0..size)
+                .map(|x| {
+                    (0..size)
+                        .map(|y| {
+                            let i = y * size + x;
+                            let record = &landscape[i * LANDSCAPE_TILE_LEN..];
+                            let mut tile =
+                                Tile::new(tile_type_from_ground(record[1]), x as u16, y as u16);
+                            tile.elevation = record[0] / HEIGHT_STEP;
+                            if object_len > 0 {
+                                tile.object = object_from_id(objects[i * object_len]);
+                            }
+                            if let Some(byte) = resources.get(i) {
+                                tile.deposit = deposit_from_byte(*byte);
+                            }
+                            tile
+                        })
+                        .collect()
+                })
+                .collect(),
+        };
+
+        let starts = match file.chunk(CHUNK_PLAYERS) {
+            Some(chunk) => {
+                let players = chunk.data()?;
+                let count = info
+                    .as_ref()
+                    .filter(|info| info.len() >= MAP_INFO_LEN)
+                    .map_or(players.len() / PLAYER_RECORD_LEN, |info| {
+                        read_i32(info, 4).max(0) as usize
+                    });
+                if count * PLAYER_RECORD_LEN > players.len() {
+                    return Err(invalid(format!(
+                        "{} players don't fit into {} bytes",
+                        count,
+                        players.len()
+                    )));
+                }
+                (0..count)
+                    .map(|i| {
+                        let record = i * PLAYER_RECORD_LEN;
+                        let x = read_i32(&players, record + 4);
+                        let y = read_i32(&players, record + 8);
+                        if map.contains(x, y) {
+                            Ok((x as u16, y as u16))
+                        } else {
+                            Err(invalid(format!(
+                                "start of player {} at {}/{} is outside the map",
+                                i, x, y
+                            )))
+                        }
+                    })
+                    .collect::<io::Result<Vec<_>>>()?
+            }
+            None => Vec::new(),
+        };
+        Ok(S4Map {
+            map,
+            starts,
+            has_resources: !resources.is_empty(),
+        })
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::decode(&S4MapFile::load(path)?)
+    }
+
+    // Die Rohstoffe im Boden der Originalkarten liest der Import noch nicht, sie werden
+    // wie bei erzeugten Karten verteilt. Gleiche Datei, gleiche Rohstoffe.
+    pub fn load_with_resources<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = S4MapFile::load(path)?;
+        let mut s4 = Self::decode(&file)?;
+        if !s4.has_resources {
+            s4.map
+                .generate_deposits(&mut GameRng::new(file.checksum as u64));
+        }
+        Ok(s4)
+    }
+
+    // Schreibt eine Karte ungepackt im Format des Originals. Karten im Original sind
+    // quadratisch, der Rest wird mit Meer aufgefüllt.
+    pub fn encode(map: &Map, starts: &[(u16, u16)]) -> S4MapFile {
+        let size = map.width.max(map.height) as usize;
+        let mut landscape = vec![0; size * size * LANDSCAPE_TILE_LEN];
+        let mut objects = vec![0; size * size * LANDSCAPE_TILE_LEN];
+        let mut resources = vec![0; size * size];
+        for tile in map.tiles.iter().flatten() {
+            let i = tile.y as usize * size + tile.x as usize;
+            landscape[i * LANDSCAPE_TILE_LEN] = tile.elevation.saturating_mul(HEIGHT_STEP);
+            landscape[i * LANDSCAPE_TILE_LEN + 1] = ground_from_tile_type(tile.tile_type);
+            objects[i * LANDSCAPE_TILE_LEN] = id_from_object(tile.object);
+            resources[i] = byte_from_deposit(tile.deposit);
+        }
+
+        let mut info = Vec::new();
+        for value in &[0, starts.len() as i32, 0, size as i32] {
+            info.extend_from_slice(&value.to_le_bytes());
+        }
+        let mut players = Vec::new();
+        for (x, y) in starts {
+            for value in &[0, *x as i32, *y as i32] {
+                players.extend_from_slice(&value.to_le_bytes());
+            }
+            players.extend_from_slice(&[0; PLAYER_NAME_LEN]);
+        }
+        S4MapFile {
+            checksum: 0,
+            version: 0,
+            chunks: vec![
+                S4Chunk::stored(CHUNK_MAP_INFO, info),
+                S4Chunk::stored(CHUNK_PLAYERS, players),
+                S4Chunk::stored(CHUNK_OBJECTS, objects),
+                S4Chunk::stored(CHUNK_LANDSCAPE, landscape),
+                S4Chunk::stored(CHUNK_RESOURCES, resources),
+            ],
+        }
+    }
+}
+
+// Alle Karten (.map, .edm) im Verzeichnis, sortiert. Keine, wenn es das nicht gibt.
+pub fn fixture_paths(dir: &Path) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+        .map(|entries| entries.filter_map(|e| e.ok()).map(|e| e.path()).collect())
+        .unwrap_or_default();
+    paths.retain(|p| {
+        p.extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("map") | ext.eq_ignore_ascii_case("edm"))
+    });
+    paths.sort();
+    paths
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{
+        assets::{self, Assets},
+        hextile::TILE_TYPES,
+    };
+
+    // Schreibt Zeichen als Stellen der Tabelle, so wie unpack sie liest
+    #[derive(Default)]
+    struct BitWriter {
+        data: Vec<u8>,
+        bits: usize,
+    }
+
+    impl BitWriter {
+        fn write(&mut self, value: usize, bits: u8) {
+            for bit in (0..bits).rev() {
+                if self.bits.is_multiple_of(8) {
+                    self.data.push(0);
+                }
+                let last = self.data.last_mut().unwrap();
+                *last |= ((value >> bit) as u8 & 1) << (7 - self.bits % 8);
+                self.bits += 1;
+            }
+        }
+
+        fn code(&mut self, position: usize) {
+            let group = (0..16)
+                .rfind(|g| group_start(&CODE_BITS, 0, *g) <= position)
+                .unwrap();
+            self.write(group, 4);
+            self.write(
+                position - group_start(&CODE_BITS, 0, group),
+                CODE_BITS[group],
+            );
+        }
+    }
+
+    // Nur Bytes, die Tabelle bleibt wie am Anfang
+    fn pack_bytes(data: &[u8]) -> Vec<u8> {
+        let mut writer = BitWriter::default();
+        for byte in data {
+            writer.code(*byte as usize);
+        }
+        writer.code(SYMBOL_END as usize);
+        writer.data
+    }
+
+    #[test]
+    fn unpacks_a_hand_built_chunk() {
+        let mut writer = BitWriter::default();
+        // "ab", dann 6 Bytes ab 2 zurück: "abababab"
+        writer.write(7, 4);
+        writer.write(13, 5);
+        writer.write(7, 4);
+        writer.write(14, 5);
+        writer.write(11, 4);
+        writer.write(46, 6);
+        writer.write(1, 4);
+        writer.write(0, 1);
+        // Neu sortiert: 'a', 'b' und die Kopie kamen je einmal, das Sortieren selbst
+        // auch. Danach ist 'b' an Stelle 1 und 'a' an Stelle 0.
+        writer.code(SYMBOL_SORT as usize);
+        writer.write(0, 4);
+        writer.write(1, 2);
+        writer.write(0, 4);
+        writer.write(0, 2);
+        // Das Ende kam noch nie und bleibt deshalb an seiner Stelle
+        writer.code(SYMBOL_END as usize);
+        let chunk = S4Chunk {
+            chunk_type: CHUNK_MAP_INFO,
+            unpacked_len: 10,
+            checksum: 0,
+            unknown: [0, 0],
+            data: writer.data,
+        };
+        assert!(chunk.is_packed());
+        assert_eq!(chunk.data().unwrap(), b"ababababba".to_vec());
+    }
+
+    #[test]
+    fn rejects_broken_packed_chunks() {
+        let packed = pack_bytes(b"settlers");
+        assert_eq!(unpack(&packed, 8).unwrap(), b"settlers".to_vec());
+        // Zu lang, zu kurz, abgeschnitten
+        assert!(unpack(&packed, 7).is_err());
+        assert!(unpack(&packed, 9).is_err());
+        assert!(unpack(&packed[..packed.len() - 2], 8).is_err());
+        // Kopie vor den Anfang
+        let mut writer = BitWriter::default();
+        writer.code(b'a' as usize);
+        writer.code(SYMBOL_COPY as usize);
+        writer.write(1, 4);
+        writer.write(0, 1);
+        assert!(unpack(&writer.data, 5).is_err());
+        // Stelle hinter der Tabelle
+        let mut writer = BitWriter::default();
+        writer.write(15, 4);
+        writer.write(127, 7);
+        assert!(unpack(&writer.data, 1).is_err());
+    }
+
+    #[test]
+    fn reads_packed_maps() {
+        let map = Map::generate(32, 32, 2);
+        let starts = map.start_positions(2);
+        let mut file = S4Map::encode(&map, &starts);
+        for chunk in file.chunks.iter_mut() {
+            chunk.data = pack_bytes(&chunk.data);
+        }
+        let file = S4MapFile::parse(&file.encode()).unwrap();
+        let read = S4Map::decode(&file).unwrap();
+        assert_eq!(read.starts, starts);
+        let stored = S4Map::decode(&S4Map::encode(&map, &starts)).unwrap();
+        for (a, b) in read
+            .map
+            .tiles
+            .iter()
+            .flatten()
+            .zip(stored.map.tiles.iter().flatten())
+        {
+            assert_eq!(
+                (a.tile_type, a.elevation, a.object, a.deposit),
+                (b.tile_type, b.elevation, b.object, b.deposit)
+            );
+        }
+    }
+
+    // Schreibt eine erzeugte Karte als Datei, liest sie wieder und vergleicht
+    #[test]
+    fn synthetic_maps_round_trip() {
+        for seed in 1..=3 {
+            let mut map = Map::generate(48, 40, seed);
+            map.tile_mut(3, 4).elevation = 5;
+            // Jeder Boden einmal, auch die, die der Generator nicht würfelt
+            for (x, tt) in TILE_TYPES.iter().enumerate() {
+                map.tile_mut(x as u16, 2).tile_type = *tt;
+            }
+            let starts = map.start_positions(3);
+            let data = S4Map::encode(&map, &starts).encode();
+            let read = S4Map::decode(&S4MapFile::parse(&data).unwrap()).unwrap();
+            assert_eq!((read.map.width, read.map.height), (48, 48));
+            assert_eq!(read.starts, starts);
+            for tile in map.tiles.iter().flatten() {
+                let other = read.map.tile(tile.x, tile.y);
+                let object = match tile.object {
+                    Some(MapObject::Tree) | Some(MapObject::Stone(_)) => tile.object,
+                    _ => None,
+                };
+                assert_eq!(
+                    (
+                        other.tile_type,
+                        other.elevation,
+                        other.object,
+                        other.deposit
+                    ),
+                    (tile.tile_type, tile.elevation, object, tile.deposit),
+                    "tile {}/{}",
+                    tile.x,
+                    tile.y
+                );
+            }
+            // Aufgefüllte Zeilen sind Meer
+            assert_eq!(read.map.tile(0, 47).tile_type, TileType::TtOcean);
+        }
+    }
+
+    // Kaputte Dateien kommen als Fehler an und nicht als Karte
+    #[test]
+    fn broken_files_are_rejected() {
+        let map = Map::generate(16, 16, 1);
+        let data = S4Map::encode(&map, &[]).encode();
+        let broken: Vec<(&str, Vec<u8>)> = vec![
+            ("cut off", data[..data.len() - 1].to_vec()),
+            ("no header", data[..4].to_vec()),
+            ("no landscape", {
+                let mut file = S4MapFile::parse(&data).unwrap();
+                file.chunks.retain(|c| c.chunk_type != CHUNK_LANDSCAPE);
+                file.encode()
+            }),
+            ("wrong length", {
+                let mut file = S4MapFile::parse(&data).unwrap();
+                file.chunks[0].unpacked_len += 1;
+                file.encode()
+            }),
+        ];
+        for (name, data) in broken {
+            if let Ok(file) = S4MapFile::parse(&data) {
+                assert!(S4Map::decode(&file).is_err(), "{} file was read", name);
+            }
+        }
+    }
+
+    // Läuft nur, wenn unter res/maps/s4 echte Karten liegen
+    #[test]
+    fn fixtures_can_be_read() {
+        let dir = Assets::new(None).resolve(assets::S4_FIXTURES);
+        for path in dir.map(|dir| fixture_paths(&dir)).unwrap_or_default() {
+            if let Err(e) = S4Map::load(&path) {
+                panic!("{}: {}", path.display(), e);
+            }
+        }
+    }
+}
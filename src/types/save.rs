@@ -0,0 +1,176 @@
+use serde::{Deserialize, Serialize};
+use std::{fs, io, path::Path};
+
+use super::{
+    player::PlayerId,
+    production::ProductionDefs,
+    replay::{GameSetup, Replay},
+    world::World,
+};
+
+// Aktuelle Version der Spielstände. Felder, die nur dazukommen, brauchen keine neue
+// Version, dafür reicht #[serde(default)]. Wird etwas umgebaut, kommt eine neue Version
+// und eine Umwandlung in migrate.
+pub const SAVE_VERSION: u32 = 1;
+
+// Erste Zeile jedes Spielstands, danach die Version
+const SAVE_HEADER: &str = "settlers_rs save";
+
+pub const QUICKSAVE_FILE: &str = "saves/quicksave.ron";
+pub const AUTOSAVE_FILE: &str = "saves/autosave.ron";
+
+// Standard für das automatische Speichern, 0 schaltet es ab
+pub const AUTOSAVE_MINUTES: u64 = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct CameraPosition {
+    pub x: i16,
+    pub y: i16,
+}
+
+// Alles, um ein Spiel genau dort fortzusetzen, wo es gespeichert wurde
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SaveGame {
+    // Woraus das Spiel einmal entstand, damit Wiederholungen weiter funktionieren
+    pub setup: GameSetup,
+    pub local_player: PlayerId,
+    pub camera: CameraPosition,
+    pub world: World,
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+impl SaveGame {
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let body = ron::ser::to_string(self).map_err(|e| invalid(e.to_string()))?;
+        if let Some(dir) = path.as_ref().parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, format!("{} {}\n{}", SAVE_HEADER, SAVE_VERSION, body))
+    }
+
+    // Die Produktionsdaten stehen nicht im Spielstand, sie kommen aus den Spieldaten
+    pub fn load<P: AsRef<Path>>(path: P, production: ProductionDefs) -> io::Result<Self> {
+        Self::parse(&fs::read_to_string(path)?, production)
+    }
+
+    pub fn parse(data: &str, production: ProductionDefs) -> io::Result<Self> {
+        // Ohne Kopfzeile ist es Version 0
+        let (version, body) = match data.strip_prefix(SAVE_HEADER) {
+            Some(rest) => {
+                let (version, body) = rest
+                    .split_once('\n')
+                    .ok_or_else(|| invalid(String::from("save game has no body")))?;
+                let version = version
+                    .trim()
+                    .parse()
+                    .map_err(|_| invalid(format!("unreadable save game version {}", version)))?;
+                (version, body)
+            }
+            None => (0, data),
+        };
+        let mut game = migrate(version, body, &production)?;
+        game.world.production = production;
+        Ok(game)
+    }
+}
+
+// Bringt einen Spielstand einer älteren Version auf den aktuellen Stand. Jede neue
+// Version bekommt hier einen Zweig, der die alte Struktur liest und umwandelt.
+fn migrate(version: u32, body: &str, production: &ProductionDefs) -> io::Result<SaveGame> {
+    match version {
+        0 => {
+            let replay: Replay = ron::de::from_str(body)
+                .map_err(|e| invalid(format!("neither a save game nor a replay: {}", e)))?;
+            from_replay(replay, production)
+        }
+        SAVE_VERSION => ron::de::from_str(body).map_err(|e| invalid(e.to_string())),
+        v => Err(invalid(format!(
+            "save game version {} is newer than this game ({})",
+            v, SAVE_VERSION
+        ))),
+    }
+}
+
+// Version 0: bevor es Spielstände gab, ließ sich ein Spiel nur als Wiederholung ohne
+// Kopfzeile sichern. Der Stand wird aus ihren Befehlen nachgerechnet, gespielt hat
+// dann Spieler 0.
+fn from_replay(replay: Replay, production: &ProductionDefs) -> io::Result<SaveGame> {
+    let mut world = replay.setup.create_world(production.clone())?;
+    let mut commands = replay.commands.into_iter().peekable();
+    while world.tick < replay.ticks {
+        while let Some(timed) = commands.next_if(|c| c.tick <= world.tick) {
+            let _ = world.execute(timed.player, timed.command);
+        }
+        world.tick();
+    }
+    Ok(SaveGame {
+        setup: replay.setup,
+        local_player: 0,
+        camera: CameraPosition::default(),
+        world,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{ai, building::BuildingType, replay::Playback, symmetry::Symmetry, testing};
+
+    fn game(world: World) -> SaveGame {
+        SaveGame {
+            setup: GameSetup::generated(40, 40, 1, 1, Symmetry::None),
+            local_player: 0,
+            camera: CameraPosition { x: 3, y: -4 },
+            world,
+        }
+    }
+
+    #[test]
+    fn loaded_game_continues_like_the_original() {
+        let (mut world, castle) = testing::world();
+        testing::connected_site(&mut world, castle, BuildingType::Woodcutter, 16, 10);
+        testing::connected_site(&mut world, castle, BuildingType::Sawmill, 4, 10);
+        for _ in 0..300 {
+            world.tick();
+        }
+        let path = std::env::temp_dir().join("settlers_rs_save_test.ron");
+        game(world.clone()).save(&path).unwrap();
+        let loaded = SaveGame::load(&path, testing::production()).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.camera, CameraPosition { x: 3, y: -4 });
+        let mut loaded = loaded.world;
+        assert_eq!(loaded.state_hash(), world.state_hash());
+        for _ in 0..500 {
+            world.tick();
+            loaded.tick();
+        }
+        assert_eq!(loaded.state_hash(), world.state_hash());
+    }
+
+    #[test]
+    fn replays_load_as_version_0() {
+        let (_, replay) =
+            ai::run_match(testing::production(), 3, 2, 40, Symmetry::None, 400).unwrap();
+        assert!(!replay.commands.is_empty());
+        let body = ron::ser::to_string(&replay).unwrap();
+        let game = SaveGame::parse(&body, testing::production()).unwrap();
+        let mut playback = Playback::new(replay.clone());
+        let mut world = playback.create_world(testing::production()).unwrap();
+        playback.fast_forward(&mut world, replay.ticks);
+        assert_eq!(game.world.tick, replay.ticks);
+        assert_eq!(game.world.state_hash(), world.state_hash());
+        assert_eq!(game.setup, replay.setup);
+    }
+
+    #[test]
+    fn rejects_newer_and_broken_saves() {
+        let newer = format!("{} {}\n()", SAVE_HEADER, SAVE_VERSION + 1);
+        assert!(SaveGame::parse(&newer, testing::production()).is_err());
+        let broken = format!("{} x\n()", SAVE_HEADER);
+        assert!(SaveGame::parse(&broken, testing::production()).is_err());
+        assert!(SaveGame::parse("hello", testing::production()).is_err());
+    }
+}
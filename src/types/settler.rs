@@ -0,0 +1,143 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+use super::{
+    building::BuildingId,
+    goods::Good,
+    map::Map,
+    military::Soldier,
+    player::PlayerId,
+    road::{FlagId, RoadId},
+    transport::WareId,
+};
+
+pub type SettlerId = u32;
+
+// Ticks pro Schritt auf das nächste Tile
+pub const WALK_TICKS: u32 = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+pub enum Job {
+    // Ohne Beruf, wartet im Lager auf Arbeit
+    Settler,
+    Builder,
+    Carrier,
+    Woodcutter,
+    Stonecutter,
+    Sawyer,
+    Farmer,
+    Miller,
+    Baker,
+    Fisher,
+    Miner,
+    Soldier,
+}
+
+impl Job {
+    // Werkzeug, das ein Siedler für diesen Beruf aus dem Lager holen muss
+    pub fn tool(self) -> Option<Good> {
+        match self {
+            Job::Builder => Some(Good::Hammer),
+            Job::Woodcutter => Some(Good::Axe),
+            Job::Stonecutter | Job::Miner => Some(Good::Pickaxe),
+            Job::Sawyer => Some(Good::Saw),
+            Job::Farmer => Some(Good::Scythe),
+            Job::Fisher => Some(Good::FishingRod),
+            Job::Soldier => Some(Good::Sword),
+            Job::Settler | Job::Carrier | Job::Miller | Job::Baker => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SettlerState {
+    Idle,
+    Walking { x: u16, y: u16 },
+    Working,
+}
+
+// Was ein Träger auf seiner Straße gerade vorhat
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CarrierTask {
+    None,
+    Fetch { flag: FlagId, ware: WareId },
+    Deliver { flag: FlagId },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Settler {
+    pub id: SettlerId,
+    pub owner: PlayerId,
+    pub job: Job,
+    pub x: u16,
+    pub y: u16,
+    pub workplace: Option<BuildingId>,
+    pub state: SettlerState,
+    pub move_ticks: u32,
+    // Noch zu laufende Tiles
+    pub route: VecDeque<(u16, u16)>,
+    pub road: Option<RoadId>,
+    pub carrying: Option<WareId>,
+    pub task: CarrierTask,
+    // Was ein Arbeiter gerade nach Hause trägt
+    pub carrying_good: Option<Good>,
+    // Nur für Soldaten
+    pub soldier: Option<Soldier>,
+}
+
+impl Settler {
+    pub fn new(id: SettlerId, owner: PlayerId, job: Job, x: u16, y: u16) -> Self {
+        Settler {
+            id,
+            owner,
+            job,
+            x,
+            y,
+            workplace: None,
+            state: SettlerState::Idle,
+            move_ticks: 0,
+            route: VecDeque::new(),
+            road: None,
+            carrying: None,
+            task: CarrierTask::None,
+            carrying_good: None,
+            soldier: None,
+        }
+    }
+
+    // Läuft auf dem kürzesten Weg über begehbare Tiles. false, wenn es keinen gibt.
+    pub fn walk_to(&mut self, map: &Map, x: u16, y: u16) -> bool {
+        match map.find_path((self.x, self.y), (x, y)) {
+            Some(route) => {
+                self.walk_path(route);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Läuft die Tiles der Reihe nach ab
+    pub fn walk_path(&mut self, route: Vec<(u16, u16)>) {
+        let (x, y) = route.last().copied().unwrap_or((self.x, self.y));
+        self.route = route.into_iter().collect();
+        self.state = SettlerState::Walking { x, y };
+        self.move_ticks = 0;
+    }
+
+    // Ein Tick laufen. true, wenn das Ziel erreicht ist.
+    pub fn step(&mut self) -> bool {
+        if let SettlerState::Walking { x, y } = self.state {
+            if let Some(&(nx, ny)) = self.route.front() {
+                self.move_ticks += 1;
+                if self.move_ticks >= WALK_TICKS {
+                    self.move_ticks = 0;
+                    self.route.pop_front();
+                    self.x = nx;
+                    self.y = ny;
+                }
+            }
+            return self.route.is_empty() & ((self.x, self.y) == (x, y));
+        }
+        false
+    }
+}
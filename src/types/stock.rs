@@ -0,0 +1,55 @@
+use std::collections::BTreeMap;
+
+use super::{goods::Good, player::PlayerId, world::World};
+
+// Bestand einer Ware über alle Lager eines Spielers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct StockCount {
+    // Liegt in den Lagern
+    pub stored: u16,
+    // Auf dem Weg in ein Lager
+    pub inbound: u16,
+    // Liegt noch im Lager, ist aber schon einer Anfrage versprochen
+    pub outbound: u16,
+}
+
+impl StockCount {
+    // Was noch frei vergeben werden kann
+    pub fn available(&self) -> u16 {
+        self.stored.saturating_sub(self.outbound)
+    }
+}
+
+impl World {
+    pub fn player_stock(&self, player: PlayerId) -> BTreeMap<Good, StockCount> {
+        let mut result: BTreeMap<Good, StockCount> = BTreeMap::new();
+        for b in self.buildings.values() {
+            if (b.owner != player) | !b.is_finished() | !b.is_storage() {
+                continue;
+            }
+            for (good, count) in &b.stock {
+                result.entry(*good).or_default().stored += count;
+            }
+            for (good, count) in &b.reserved {
+                result.entry(*good).or_default().outbound += count;
+            }
+        }
+        for req in self.transport.requests.values() {
+            let to_storage = self
+                .buildings
+                .get(&req.building)
+                .is_some_and(|b| b.is_storage());
+            if (req.owner == player) & to_storage & req.source.is_some() {
+                result.entry(req.good).or_default().inbound += 1;
+            }
+        }
+        result
+    }
+
+    pub fn stock_count(&self, player: PlayerId, good: Good) -> StockCount {
+        self.player_stock(player)
+            .get(&good)
+            .copied()
+            .unwrap_or_default()
+    }
+}
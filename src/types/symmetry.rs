@@ -0,0 +1,238 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+use super::{hextile::TileType, map::Map};
+
+// Symmetrie einer erzeugten Karte, damit jeder Spieler dieselben Voraussetzungen hat.
+// Gedreht wird um die Kartenmitte, gespiegelt an der langen Diagonale der Raute, in der
+// die Tiles liegen (x und y getauscht). Spiegeln ist für quadratische Karten gedacht,
+// sonst bleibt viel Meer übrig.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Symmetry {
+    #[default]
+    None,
+    // Halbe Drehung
+    Rotate2,
+    Mirror2,
+    // Drehung um 120°
+    Rotate3,
+    // Beide Diagonalen gespiegelt, dazu die halbe Drehung
+    Mirror4,
+    // Drehung um 60°
+    Rotate6,
+}
+
+pub const SYMMETRIES: [Symmetry; 6] = [
+    Symmetry::None,
+    Symmetry::Rotate2,
+    Symmetry::Mirror2,
+    Symmetry::Rotate3,
+    Symmetry::Mirror4,
+    Symmetry::Rotate6,
+];
+
+impl Symmetry {
+    pub fn name(self) -> &'static str {
+        match self {
+            Symmetry::None => "none",
+            Symmetry::Rotate2 => "rot2",
+            Symmetry::Mirror2 => "mirror2",
+            Symmetry::Rotate3 => "rot3",
+            Symmetry::Mirror4 => "mirror4",
+            Symmetry::Rotate6 => "rot6",
+        }
+    }
+
+    // Der Name oder nur die Zahl der Teile
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "2" => Some(Symmetry::Rotate2),
+            "3" => Some(Symmetry::Rotate3),
+            "4" => Some(Symmetry::Mirror4),
+            "6" => Some(Symmetry::Rotate6),
+            _ => SYMMETRIES.iter().copied().find(|sym| sym.name() == s),
+        }
+    }
+
+    // In so viele gleiche Teile zerfällt die Karte
+    pub fn order(self) -> usize {
+        match self {
+            Symmetry::None => 1,
+            Symmetry::Rotate2 | Symmetry::Mirror2 => 2,
+            Symmetry::Rotate3 => 3,
+            Symmetry::Mirror4 => 4,
+            Symmetry::Rotate6 => 6,
+        }
+    }
+
+    // Das Tile und alle seine Gegenstücke, das Tile selbst zuerst. Bei den Drehungen in
+    // Drehrichtung. Gegenstücke können außerhalb der Karte liegen.
+    pub fn images(self, width: u16, height: u16, x: u16, y: u16) -> Vec<(i32, i32)> {
+        let (w, h) = (width as i32, height as i32);
+        let (x, y) = (x as i32, y as i32);
+        let turn = |x: i32, y: i32| (w - 1 - x, h - 1 - y);
+        // Axiale Koordinaten um die mittlere Kachel, eine Sechsteldrehung im Uhrzeigersinn
+        let (cx, cy) = ((w - 1) / 2, (h - 1) / 2);
+        let rotations = |steps: usize, by: usize| {
+            let (mut dx, mut dy) = (x - cx, y - cy);
+            let mut images = Vec::new();
+            for _ in 0..steps {
+                images.push((cx + dx, cy + dy));
+                for _ in 0..by {
+                    let next = (-dy, dx + dy);
+                    dx = next.0;
+                    dy = next.1;
+                }
+            }
+            images
+        };
+        match self {
+            Symmetry::None => vec![(x, y)],
+            Symmetry::Rotate2 => vec![(x, y), turn(x, y)],
+            Symmetry::Mirror2 => vec![(x, y), (y, x)],
+            Symmetry::Rotate3 => rotations(3, 2),
+            Symmetry::Mirror4 => vec![(x, y), (y, x), turn(x, y), turn(y, x)],
+            Symmetry::Rotate6 => rotations(6, 1),
+        }
+    }
+
+    // Alle Tiles, die über Gegenstücke von Gegenstücken zusammenhängen. Auf Karten, die
+    // nicht quadratisch sind, reichen die Gegenstücke allein beim Spiegeln nicht. Endet an
+    // Tiles außerhalb der Karte.
+    fn orbit(self, map: &Map, x: u16, y: u16) -> BTreeSet<(i32, i32)> {
+        let mut orbit = BTreeSet::new();
+        let mut open = vec![(x as i32, y as i32)];
+        while let Some((x, y)) = open.pop() {
+            if !orbit.insert((x, y)) | !map.contains(x, y) {
+                continue;
+            }
+            open.extend(self.images(map.width, map.height, x as u16, y as u16));
+        }
+        orbit
+    }
+}
+
+impl Map {
+    // Übernimmt für jedes Tile den Boden, die Höhe, Objekte und Rohstoffe des ersten Tiles
+    // seines Orbits. Liegt eines außerhalb, wird der ganze Orbit zu Meer, so bleibt die
+    // Karte genau symmetrisch. Dieses Meer hat keine Fische, sonst hätten die Starts am
+    // Rand mehr davon als die anderen.
+    pub fn symmetrize(&mut self, symmetry: Symmetry) {
+        if symmetry == Symmetry::None {
+            return;
+        }
+        for x in 0..self.width {
+            for y in 0..self.height {
+                let orbit = symmetry.orbit(self, x, y);
+                if orbit.iter().any(|(ox, oy)| !self.contains(*ox, *oy)) {
+                    let tile = self.tile_mut(x, y);
+                    tile.tile_type = TileType::TtOcean;
+                    tile.elevation = 0;
+                    tile.object = None;
+                    tile.deposit = None;
+                    continue;
+                }
+                let (sx, sy) = *orbit.iter().next().unwrap();
+                if (sx as u16, sy as u16) != (x, y) {
+                    let source = self.tile(sx as u16, sy as u16).clone();
+                    let tile = self.tile_mut(x, y);
+                    tile.tile_type = source.tile_type;
+                    tile.elevation = source.elevation;
+                    tile.object = source.object;
+                    tile.deposit = source.deposit;
+                }
+            }
+        }
+    }
+
+    // Startpunkte, die zueinander liegen wie die Teile der Karte. Bei weniger Spielern
+    // als Teilen so weit auseinander wie möglich. Ohne Symmetrie oder bei mehr Spielern
+    // als Teilen im Kreis wie bisher.
+    pub fn symmetric_starts(&self, symmetry: Symmetry, players: usize) -> Vec<(u16, u16)> {
+        let order = symmetry.order();
+        if (symmetry == Symmetry::None) | (players == 0) | (players > order) {
+            return self.start_positions(players);
+        }
+        // Gleicher Abstand zur Mitte wie bei start_positions, auf der Höhe der Mitte
+        let (cx, cy) = ((self.width - 1) / 2, (self.height - 1) / 2);
+        let radius = (cx.min(cy) as f32 * 0.6).round() as u16;
+        let images = symmetry.images(self.width, self.height, cx + radius, cy);
+        // Gespiegelt auf einer Karte, die nicht quadratisch ist
+        if images.iter().any(|(x, y)| !self.contains(*x, *y)) {
+            return self.start_positions(players);
+        }
+        let step = if order.is_multiple_of(players) {
+            order / players
+        } else {
+            1
+        };
+        images
+            .into_iter()
+            .step_by(step)
+            .take(players)
+            .map(|(x, y)| (x as u16, y as u16))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_image_of_a_tile_looks_the_same() {
+        for symmetry in SYMMETRIES.iter().copied() {
+            let map = Map::generate_symmetric(40, 40, 7, symmetry);
+            for tile in map.tiles.iter().flatten() {
+                for (x, y) in symmetry.images(map.width, map.height, tile.x, tile.y) {
+                    if !map.contains(x, y) {
+                        continue;
+                    }
+                    let image = map.tile(x as u16, y as u16);
+                    assert_eq!(
+                        (
+                            image.tile_type,
+                            image.elevation,
+                            image.object,
+                            image.deposit
+                        ),
+                        (tile.tile_type, tile.elevation, tile.object, tile.deposit),
+                        "{} at {}/{}",
+                        symmetry.name(),
+                        tile.x,
+                        tile.y
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn symmetric_starts_are_images_of_each_other() {
+        for symmetry in SYMMETRIES.iter().copied().skip(1) {
+            let map = Map::generate_symmetric(40, 40, 7, symmetry);
+            let players = symmetry.order();
+            let starts = map.symmetric_starts(symmetry, players);
+            assert_eq!(starts.len(), players);
+            let (x, y) = starts[0];
+            let images = symmetry.images(map.width, map.height, x, y);
+            for (sx, sy) in &starts {
+                assert!(images.contains(&(*sx as i32, *sy as i32)));
+            }
+            let mut distinct = starts.clone();
+            distinct.sort_unstable();
+            distinct.dedup();
+            assert_eq!(distinct.len(), players, "{}", symmetry.name());
+        }
+    }
+
+    #[test]
+    fn symmetric_maps_are_fair() {
+        for symmetry in SYMMETRIES.iter().copied().skip(1) {
+            let map = Map::generate_symmetric(48, 48, 11, symmetry);
+            let starts = map.symmetric_starts(symmetry, symmetry.order());
+            let report = map.analyze_fairness(&starts);
+            assert_eq!(report.unfair(), Vec::new(), "{}", symmetry.name());
+        }
+    }
+}
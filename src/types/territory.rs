@@ -0,0 +1,216 @@
+use coffee::graphics::{Color, Shape};
+use std::collections::{BTreeMap, BTreeSet};
+
+use super::{
+    building::BuildingId,
+    map::{Map, HEX_DIRECTIONS},
+    player::{player_color, PlayerId},
+    road::{FlagId, RoadId},
+    world::World,
+};
+
+// Direkt um ein Militärgebäude gehört das Land immer dem Besitzer, auch nach einer Eroberung
+pub const CORE_RADIUS: u16 = 2;
+
+// Wer ein Tile für sich beansprucht und wie stark
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct Claim {
+    distance: u16,
+    // Größerer Radius gewinnt bei gleichem Abstand, daher negiert
+    strength: i32,
+    building: BuildingId,
+    owner: PlayerId,
+}
+
+// Gebiet der Spieler
+impl World {
+    // Fertige Burgen und Militärgebäude mit ihrem Radius
+    pub fn territory_sources(&self) -> Vec<(BuildingId, PlayerId, u16, u16, u16)> {
+        self.buildings
+            .values()
+            .filter(|b| b.is_finished())
+            .filter_map(|b| {
+                b.building_type
+                    .territory_radius()
+                    .map(|radius| (b.id, b.owner, b.x, b.y, radius))
+            })
+            .collect()
+    }
+
+    // Berechnet, wem jedes Tile gehört. Regeln für umstrittene Tiles:
+    // 0. Der Kern um ein Gebäude gehört dem nächsten Gebäude.
+    // 1. Wer ein Tile hat und es noch mit einem eigenen Gebäude abdeckt, behält es.
+    // 2. Sonst bekommt es das nächste Gebäude, bei Gleichstand das mit dem größeren
+    //    Radius und dann das ältere.
+    // 3. Was niemand mehr abdeckt, wird wieder frei.
+    pub fn compute_territory(&self) -> BTreeMap<(u16, u16), Option<PlayerId>> {
+        let mut best: BTreeMap<(u16, u16), Claim> = BTreeMap::new();
+        let mut covered: BTreeMap<(u16, u16), BTreeSet<PlayerId>> = BTreeMap::new();
+        for (building, owner, x, y, radius) in self.territory_sources() {
+            for (tx, ty) in self.map.spiral(x, y, radius) {
+                let claim = Claim {
+                    distance: Map::hex_distance(x, y, tx, ty),
+                    strength: -(radius as i32),
+                    building,
+                    owner,
+                };
+                let entry = best.entry((tx, ty)).or_insert(claim);
+                if claim < *entry {
+                    *entry = claim;
+                }
+                covered.entry((tx, ty)).or_default().insert(owner);
+            }
+        }
+        let mut territory = BTreeMap::new();
+        for x in 0..self.map.width {
+            for y in 0..self.map.height {
+                let old = self.map.tile(x, y).owner;
+                let keeps =
+                    old.is_some_and(|p| covered.get(&(x, y)).is_some_and(|c| c.contains(&p)));
+                let core = best
+                    .get(&(x, y))
+                    .filter(|claim| claim.distance <= CORE_RADIUS);
+                let owner = if let Some(claim) = core {
+                    Some(claim.owner)
+                } else if keeps {
+                    old
+                } else {
+                    best.get(&(x, y)).map(|claim| claim.owner)
+                };
+                territory.insert((x, y), owner);
+            }
+        }
+        territory
+    }
+
+    // Gebiet neu verteilen. Was danach auf fremdem oder freiem Land steht, geht verloren.
+    pub(crate) fn update_territory(&mut self) {
+        for ((x, y), owner) in self.compute_territory() {
+            self.map.tile_mut(x, y).owner = owner;
+        }
+
+        let map = &self.map;
+        let lost_flags: Vec<FlagId> = self
+            .roads
+            .flags
+            .values()
+            .filter(|f| map.tile(f.x, f.y).owner != Some(f.owner))
+            .map(|f| f.id)
+            .collect();
+        for flag in lost_flags {
+            self.remove_flag(flag);
+        }
+
+        let map = &self.map;
+        let lost_roads: Vec<RoadId> = self
+            .roads
+            .roads
+            .values()
+            .filter(|r| {
+                r.path
+                    .iter()
+                    .any(|(x, y)| map.tile(*x, *y).owner != Some(r.owner))
+            })
+            .map(|r| r.id)
+            .collect();
+        for road in lost_roads {
+            self.remove_road(road);
+        }
+
+        let lost_buildings: Vec<BuildingId> = self
+            .buildings
+            .values()
+            .filter(|b| {
+                b.building_type.size().footprint().iter().any(|(dx, dy)| {
+                    self.map
+                        .offset(b.x, b.y, *dx, *dy)
+                        .is_none_or(|(tx, ty)| self.map.tile(tx, ty).owner != Some(b.owner))
+                })
+            })
+            .map(|b| b.id)
+            .collect();
+        for building in lost_buildings {
+            self.demolish_building(building);
+        }
+    }
+
+    // Grenzen als Linien entlang der Hex-Kanten, wo das Nachbar-Tile jemand anderem gehört
+    pub fn draw_borders(&self, param: &mut crate::DrawParameter) {
+        for tile in self.map.tiles.iter().flatten() {
+            let owner = match tile.owner {
+                Some(owner) => owner,
+                None => continue,
+            };
+            let corners =
+                tile.get_hex_point_vec(param.camera.cameraoffset_x, param.camera.cameraoffset_y);
+            // Kante i liegt zwischen Ecke i und i + 1, passend zu HEX_DIRECTIONS
+            for (i, (dx, dy)) in HEX_DIRECTIONS.iter().enumerate() {
+                let neighbour = self
+                    .map
+                    .offset(tile.x, tile.y, *dx, *dy)
+                    .and_then(|(nx, ny)| self.map.tile(nx, ny).owner);
+                if neighbour == Some(owner) {
+                    continue;
+                }
+                let points = vec![corners[i], corners[(i + 1) % 6]];
+                param.camera.mesh.stroke(
+                    Shape::Polyline { points },
+                    Color {
+                        a: 0.8,
+                        ..player_color(owner)
+                    },
+                    2.0,
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{
+        building::{BuildingState, BuildingType},
+        testing,
+    };
+
+    // Fertiges Militärgebäude von Spieler 0
+    fn military(world: &mut World, bt: BuildingType, x: u16, y: u16) -> BuildingId {
+        let id = world.place_building(0, bt, x, y).unwrap();
+        world.buildings.get_mut(&id).unwrap().state = BuildingState::Finished;
+        world.update_territory();
+        id
+    }
+
+    #[test]
+    fn military_buildings_claim_land_around_them() {
+        let (mut world, _) = testing::world();
+        assert_eq!(world.map.tile(23, 10).owner, None);
+        let guard = military(&mut world, BuildingType::GuardHouse, 17, 10);
+        // Sechs Tiles weit um das Wachhaus, dahinter bleibt es frei
+        assert_eq!(world.map.tile(23, 10).owner, Some(0));
+        assert_eq!(world.map.tile(24, 10).owner, None);
+        assert_eq!(world.map.tile(17, 4).owner, Some(0));
+        // Ohne das Wachhaus bleibt nur das Land der Burg
+        world.demolish_building(guard);
+        world.update_territory();
+        assert_eq!(world.map.tile(23, 10).owner, None);
+        assert_eq!(world.map.tile(19, 10).owner, Some(0));
+    }
+
+    #[test]
+    fn owners_keep_what_they_still_cover() {
+        let (mut world, _) = testing::world();
+        world.add_player_start(1, 30, 10).unwrap();
+        assert_eq!(world.map.tile(21, 10).owner, Some(1));
+        military(&mut world, BuildingType::GuardHouse, 17, 10);
+        // Das Wachhaus ist näher, aber die Burg von Spieler 1 deckt das Tile noch ab
+        assert_eq!(world.map.tile(21, 10).owner, Some(1));
+        assert_eq!(world.map.tile(20, 10).owner, Some(0));
+        assert!(world.compute_territory().iter().all(|((x, y), owner)| world
+            .map
+            .tile(*x, *y)
+            .owner
+            == *owner));
+    }
+}
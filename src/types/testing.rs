@@ -0,0 +1,50 @@
+use super::{
+    building::{BuildingId, BuildingType},
+    editor::blank_map,
+    production::ProductionDefs,
+    road::{FlagId, RoadId, RoadNetwork},
+    world::World,
+};
+
+// Hilfen für die Tests: kleine Welten auf flacher Wiese
+
+pub fn production() -> ProductionDefs {
+    ProductionDefs::parse(include_str!("../../res/data/production.ron")).unwrap()
+}
+
+// Eine Burg von Spieler 0 bei (10, 10)
+pub fn world() -> (World, BuildingId) {
+    let mut world = World::new(blank_map(40, 40), production());
+    let castle = world.add_player_start(0, 10, 10).unwrap();
+    (world, castle)
+}
+
+// Gerade Straße zwischen zwei Flaggen
+pub fn connect(world: &mut World, from: FlagId, to: FlagId) -> RoadId {
+    let (a, b) = (&world.roads.flags[&from], &world.roads.flags[&to]);
+    let mut path = vec![(a.x, a.y)];
+    RoadNetwork::extend_path(&world.map, &mut path, b.x, b.y);
+    world.build_road(0, path).unwrap()
+}
+
+// Baustelle, die mit der Burg verbunden ist
+pub fn connected_site(
+    world: &mut World,
+    castle: BuildingId,
+    bt: BuildingType,
+    x: u16,
+    y: u16,
+) -> BuildingId {
+    let site = world.place_building(0, bt, x, y).unwrap();
+    let (from, to) = (world.buildings[&castle].flag, world.buildings[&site].flag);
+    connect(world, from, to);
+    site
+}
+
+// Tickt, bis done gilt, höchstens limit Ticks. Die Zahl der Ticks, None wenn nie.
+pub fn run_until(world: &mut World, limit: u64, done: impl Fn(&World) -> bool) -> Option<u64> {
+    (1..=limit).find(|_| {
+        world.tick();
+        done(world)
+    })
+}
@@ -0,0 +1,712 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+use super::{
+    building::BuildingId,
+    goods::Good,
+    map::Map,
+    player::PlayerId,
+    production::INPUT_BUFFER,
+    road::{FlagId, RoadId},
+    settler::{CarrierTask, Job, Settler, SettlerId, SettlerState},
+    world::World,
+};
+
+// So viele Waren passen auf eine Flagge
+pub const FLAG_CAPACITY: usize = 8;
+
+pub type RequestId = u32;
+pub type WareId = u32;
+
+// Ein Gebäude braucht eine Ware
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Request {
+    pub id: RequestId,
+    pub owner: PlayerId,
+    pub building: BuildingId,
+    pub good: Good,
+    pub priority: u8,
+    pub tick: u64,
+    // Gebäude, in dem die Ware für diese Anfrage reserviert ist
+    pub source: Option<BuildingId>,
+    // Die Ware, die gerade unterwegs ist
+    pub ware: Option<WareId>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WareLocation {
+    Flag(FlagId),
+    Carried(SettlerId),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Ware {
+    pub id: WareId,
+    pub good: Good,
+    pub request: RequestId,
+    pub location: WareLocation,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Transport {
+    pub requests: BTreeMap<RequestId, Request>,
+    pub wares: BTreeMap<WareId, Ware>,
+    pub next_id: u32,
+}
+
+impl Default for Transport {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Transport {
+    pub fn new() -> Self {
+        Transport {
+            requests: BTreeMap::new(),
+            wares: BTreeMap::new(),
+            next_id: 1,
+        }
+    }
+
+    fn next_id(&mut self) -> u32 {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
+    pub fn request(
+        &mut self,
+        owner: PlayerId,
+        building: BuildingId,
+        good: Good,
+        priority: u8,
+        tick: u64,
+    ) -> RequestId {
+        let id = self.next_id();
+        self.requests.insert(
+            id,
+            Request {
+                id,
+                owner,
+                building,
+                good,
+                priority,
+                tick,
+                source: None,
+                ware: None,
+            },
+        );
+        id
+    }
+
+    // Anzahl der Anfragen (offen oder unterwegs) eines Gebäudes für eine Ware
+    pub fn requested(&self, building: BuildingId, good: Good) -> usize {
+        self.requests
+            .values()
+            .filter(|r| (r.building == building) & (r.good == good))
+            .count()
+    }
+
+    // Anfragen ohne Quelle in der Reihenfolge, in der sie bedient werden:
+    // höchste Priorität zuerst, dann die älteste
+    pub fn open_requests(&self) -> Vec<RequestId> {
+        self.sorted(|r| r.source.is_none() & r.ware.is_none())
+    }
+
+    // Anfragen, deren Ware reserviert ist, aber noch im Gebäude liegt
+    pub fn reserved_requests(&self) -> Vec<RequestId> {
+        self.sorted(|r| r.source.is_some() & r.ware.is_none())
+    }
+
+    fn sorted<F: Fn(&Request) -> bool>(&self, filter: F) -> Vec<RequestId> {
+        let mut requests: Vec<&Request> = self.requests.values().filter(|r| filter(r)).collect();
+        requests.sort_by_key(|r| (std::cmp::Reverse(r.priority), r.tick, r.id));
+        requests.iter().map(|r| r.id).collect()
+    }
+
+    // Waren dieser Art, die für das Gebäude reserviert oder schon unterwegs sind
+    pub fn inbound(&self, building: BuildingId, good: Good) -> u16 {
+        self.requests
+            .values()
+            .filter(|r| (r.building == building) & (r.good == good) & r.source.is_some())
+            .count() as u16
+    }
+
+    pub fn create_ware(&mut self, request: RequestId, flag: FlagId) -> WareId {
+        let id = self.next_id();
+        let req = self.requests.get_mut(&request).unwrap();
+        req.ware = Some(id);
+        self.wares.insert(
+            id,
+            Ware {
+                id,
+                good: req.good,
+                request,
+                location: WareLocation::Flag(flag),
+            },
+        );
+        id
+    }
+
+    // Ware geht verloren, die Anfrage ist wieder offen
+    pub fn lose_ware(&mut self, ware: WareId) {
+        if let Some(ware) = self.wares.remove(&ware) {
+            if let Some(req) = self.requests.get_mut(&ware.request) {
+                req.source = None;
+                req.ware = None;
+            }
+        }
+    }
+
+    // Ware ist angekommen, Anfrage erledigt
+    pub fn complete(&mut self, ware: WareId) -> Option<Request> {
+        let ware = self.wares.remove(&ware)?;
+        self.requests.remove(&ware.request)
+    }
+
+    pub fn destination(&self, ware: WareId) -> Option<BuildingId> {
+        let ware = self.wares.get(&ware)?;
+        self.requests.get(&ware.request).map(|r| r.building)
+    }
+
+    pub fn priority(&self, ware: WareId) -> u8 {
+        self.wares
+            .get(&ware)
+            .and_then(|w| self.requests.get(&w.request))
+            .map_or(0, |r| r.priority)
+    }
+}
+
+// Träger und Warenverteilung
+impl World {
+    // Baustellen fordern an, was ihnen fehlt, Betriebe füllen ihren Vorrat auf.
+    // Anfragen von verschwundenen Gebäuden verfallen.
+    pub(crate) fn update_requests(&mut self) {
+        let stale: Vec<RequestId> = self
+            .transport
+            .requests
+            .values()
+            .filter(|r| {
+                self.buildings
+                    .get(&r.building)
+                    .is_none_or(|b| b.owner != r.owner)
+            })
+            .map(|r| r.id)
+            .collect();
+        for id in stale {
+            let (owner, good, ware, source) = {
+                let req = &self.transport.requests[&id];
+                (req.owner, req.good, req.ware, req.source)
+            };
+            if let Some(ware) = ware.filter(|w| self.transport.wares.contains_key(w)) {
+                // Schon unterwegs, die Ware geht ins Lager
+                let flag = match self.transport.wares.get(&ware).map(|w| w.location) {
+                    Some(WareLocation::Flag(flag)) => Some(flag),
+                    Some(WareLocation::Carried(settler)) => {
+                        self.settlers.get(&settler).and_then(|s| match s.task {
+                            CarrierTask::Deliver { flag } => Some(flag),
+                            _ => None,
+                        })
+                    }
+                    None => None,
+                };
+                self.return_ware(ware, owner, flag);
+                continue;
+            }
+            self.transport.requests.remove(&id);
+            if let Some(b) = source.and_then(|s| self.buildings.get_mut(&s)) {
+                b.unreserve(good);
+            }
+        }
+        // Ist die Quelle weg oder erobert, bevor die Ware losging, muss eine neue gefunden werden
+        let buildings = &self.buildings;
+        for req in self.transport.requests.values_mut() {
+            let lost = req
+                .source
+                .is_some_and(|s| buildings.get(&s).is_none_or(|b| b.owner != req.owner));
+            if req.ware.is_none() & lost {
+                req.source = None;
+            }
+        }
+
+        let mut new_requests = Vec::new();
+        for b in self.buildings.values() {
+            if let Some(site) = b.site() {
+                for (good, missing) in &site.missing {
+                    let requested = self.transport.requested(b.id, *good);
+                    for _ in requested..*missing as usize {
+                        new_requests.push((b.owner, b.id, *good, b.priority));
+                    }
+                }
+            } else if b.workshop.is_some() {
+                let def = match self.production.get(b.building_type) {
+                    Some(def) => def,
+                    None => continue,
+                };
+                for (good, _) in &def.inputs {
+                    let have = b.stock_of(*good) as usize + self.transport.requested(b.id, *good);
+                    for _ in have..INPUT_BUFFER as usize {
+                        new_requests.push((b.owner, b.id, *good, b.priority));
+                    }
+                }
+            }
+        }
+        for (owner, building, good, priority) in new_requests {
+            self.transport
+                .request(owner, building, good, priority, self.tick);
+        }
+    }
+
+    // Offene Anfragen bekommen eine Ware aus dem nächsten Lager oder Betrieb reserviert
+    pub(crate) fn assign_requests(&mut self) {
+        for req_id in self.transport.open_requests() {
+            let (owner, building, good) = {
+                let req = &self.transport.requests[&req_id];
+                (req.owner, req.building, req.good)
+            };
+            let dest_flag = self.buildings[&building].flag;
+            let source = self
+                .buildings
+                .values()
+                .filter(|b| {
+                    (b.owner == owner)
+                        & (b.id != building)
+                        & b.is_finished()
+                        & (b.available(good) > 0)
+                })
+                .filter_map(|b| {
+                    self.roads
+                        .distance(b.flag, dest_flag)
+                        .map(|dist| (dist, b.id))
+                })
+                .min();
+            if let Some((_, source)) = source {
+                self.buildings.get_mut(&source).unwrap().reserve(good);
+                self.transport.requests.get_mut(&req_id).unwrap().source = Some(source);
+            }
+        }
+    }
+
+    // Reservierte Waren gehen los, sobald an der Flagge Platz ist
+    pub(crate) fn ship_reserved(&mut self) {
+        for req_id in self.transport.reserved_requests() {
+            let (building, good, source) = {
+                let req = &self.transport.requests[&req_id];
+                (req.building, req.good, req.source.unwrap())
+            };
+            let dest_flag = self.buildings[&building].flag;
+            let flag = self.buildings[&source].flag;
+            if (flag != dest_flag) & (self.roads.flags[&flag].wares.len() >= FLAG_CAPACITY) {
+                continue;
+            }
+            if flag == dest_flag {
+                // Gleiche Flagge, kein Träger nötig. Braucht das Ziel die Ware nicht mehr,
+                // bleibt sie in der Quelle.
+                let delivered = self.deliver(building, good);
+                let source = self.buildings.get_mut(&source).unwrap();
+                if delivered {
+                    source.take_reserved(good);
+                } else {
+                    source.unreserve(good);
+                }
+                self.transport.requests.remove(&req_id);
+            } else {
+                self.buildings.get_mut(&source).unwrap().take_reserved(good);
+                let ware = self.transport.create_ware(req_id, flag);
+                self.roads.flags.get_mut(&flag).unwrap().wares.push(ware);
+            }
+        }
+    }
+
+    // Eine Ware, deren Ziel sie nicht mehr annimmt, geht von flag aus ins nächste Lager.
+    // Ohne erreichbares Lager ist sie verloren.
+    fn return_ware(&mut self, ware: WareId, owner: PlayerId, flag: Option<FlagId>) {
+        let (good, old) = match self.transport.wares.get(&ware) {
+            Some(w) => (w.good, w.request),
+            None => return,
+        };
+        let storage = flag.and_then(|flag| {
+            self.buildings
+                .values()
+                .filter(|s| (s.owner == owner) & s.is_finished() & s.is_storage())
+                .filter_map(|s| self.roads.distance(flag, s.flag).map(|d| (d, s.id)))
+                .min()
+        });
+        let storage = match storage {
+            Some((_, storage)) => storage,
+            None => {
+                self.transport.requests.remove(&old);
+                self.discard_ware(ware);
+                return;
+            }
+        };
+        let source = self.transport.requests.remove(&old).and_then(|r| r.source);
+        let req = self.transport.request(owner, storage, good, 0, self.tick);
+        let request = self.transport.requests.get_mut(&req).unwrap();
+        request.source = source;
+        request.ware = Some(ware);
+        self.transport.wares.get_mut(&ware).unwrap().request = req;
+        // Liegt schon an der Flagge des Lagers
+        if self.transport.wares[&ware].location == WareLocation::Flag(self.buildings[&storage].flag)
+        {
+            self.discard_ware(ware);
+            self.transport.requests.remove(&req);
+            self.deliver(storage, good);
+        }
+    }
+
+    // Was gerade kein Gebäude braucht, bringen die Betriebe ins nächste Lager
+    pub(crate) fn store_surplus(&mut self) {
+        let wanted: BTreeSet<(PlayerId, Good)> = self
+            .transport
+            .requests
+            .values()
+            .filter(|r| r.source.is_none())
+            .map(|r| (r.owner, r.good))
+            .collect();
+        let mut surplus = Vec::new();
+        for b in self.buildings.values() {
+            // Eine Ware nach der anderen
+            if b.is_storage() | !b.reserved.is_empty() {
+                continue;
+            }
+            if let Some(good) = b
+                .output
+                .keys()
+                .find(|good| (b.available(**good) > 0) & !wanted.contains(&(b.owner, **good)))
+            {
+                let storage = self
+                    .buildings
+                    .values()
+                    .filter(|s| (s.owner == b.owner) & s.is_finished() & s.is_storage())
+                    .filter_map(|s| self.roads.distance(b.flag, s.flag).map(|d| (d, s.id)))
+                    .min();
+                if let Some((_, storage)) = storage {
+                    surplus.push((b.id, b.owner, *good, storage));
+                }
+            }
+        }
+        for (building, owner, good, storage) in surplus {
+            self.buildings.get_mut(&building).unwrap().reserve(good);
+            let req = self.transport.request(owner, storage, good, 0, self.tick);
+            self.transport.requests.get_mut(&req).unwrap().source = Some(building);
+        }
+    }
+
+    // Entfernt eine Ware von Flagge oder Träger
+    fn discard_ware(&mut self, ware: WareId) {
+        if let Some(w) = self.transport.wares.remove(&ware) {
+            match w.location {
+                WareLocation::Flag(flag) => {
+                    if let Some(flag) = self.roads.flags.get_mut(&flag) {
+                        flag.wares.retain(|id| *id != ware);
+                    }
+                }
+                WareLocation::Carried(settler) => {
+                    if let Some(settler) = self.settlers.get_mut(&settler) {
+                        settler.carrying = None;
+                    }
+                }
+            }
+        }
+    }
+
+    // Flagge, an der die Ware abgegeben werden muss
+    fn destination_flag(&self, ware: WareId) -> Option<FlagId> {
+        self.transport
+            .destination(ware)
+            .and_then(|b| self.buildings.get(&b))
+            .map(|b| b.flag)
+    }
+
+    pub(crate) fn update_carriers(&mut self) {
+        let ids: Vec<SettlerId> = self
+            .settlers
+            .values()
+            .filter(|s| (s.job == Job::Carrier) & s.road.is_some())
+            .map(|s| s.id)
+            .collect();
+        for id in ids {
+            let mut carrier = self.settlers.remove(&id).unwrap();
+            if !self.update_carrier(&mut carrier) {
+                // Straße weg, Träger geht zurück ins Lager und die Ware ist verloren
+                if let Some(ware) = carrier.carrying.take() {
+                    self.transport.lose_ware(ware);
+                }
+                self.go_home(&mut carrier);
+            }
+            self.settlers.insert(id, carrier);
+        }
+    }
+
+    // false, wenn der Träger seine Straße verloren hat
+    fn update_carrier(&mut self, c: &mut Settler) -> bool {
+        let road_id = match c.road {
+            Some(road) => road,
+            None => return false,
+        };
+        let (from, to, path) = match self.roads.roads.get(&road_id) {
+            Some(road) => (road.from, road.to, road.path.clone()),
+            None => return false,
+        };
+
+        // Nach dem Teilen einer Straße kann die Aufgabe an einer fremden Flagge hängen
+        match c.task {
+            CarrierTask::Fetch { flag, .. } if (flag != from) & (flag != to) => {
+                c.task = CarrierTask::None;
+            }
+            CarrierTask::Deliver { flag } if (flag != from) & (flag != to) => {
+                let (fx, fy) = path[0];
+                let (tx, ty) = path[path.len() - 1];
+                let end =
+                    if Map::hex_distance(c.x, c.y, fx, fy) <= Map::hex_distance(c.x, c.y, tx, ty) {
+                        from
+                    } else {
+                        to
+                    };
+                c.task = CarrierTask::Deliver { flag: end };
+                self.carrier_walk(c, &path, end == from);
+            }
+            _ => {}
+        }
+
+        if let SettlerState::Walking { .. } = c.state {
+            if !c.step() {
+                return true;
+            }
+            c.state = SettlerState::Working;
+        }
+
+        match c.task {
+            CarrierTask::Fetch { flag, ware } => {
+                let at_flag = self.roads.flags.get(&flag).map(|f| (f.x, f.y)) == Some((c.x, c.y));
+                let still_there = self.roads.flags[&flag].wares.contains(&ware);
+                let still_routed = self
+                    .destination_flag(ware)
+                    .and_then(|dest| self.roads.next_hop(flag, dest))
+                    == Some(road_id);
+                if !at_flag {
+                    self.carrier_walk(c, &path, flag == from);
+                } else if still_there & still_routed {
+                    self.roads
+                        .flags
+                        .get_mut(&flag)
+                        .unwrap()
+                        .wares
+                        .retain(|id| *id != ware);
+                    self.transport.wares.get_mut(&ware).unwrap().location =
+                        WareLocation::Carried(c.id);
+                    c.carrying = Some(ware);
+                    let other = if flag == from { to } else { from };
+                    c.task = CarrierTask::Deliver { flag: other };
+                    self.carrier_walk(c, &path, other == from);
+                } else {
+                    c.task = CarrierTask::None;
+                }
+            }
+            CarrierTask::Deliver { flag } => {
+                let at_flag = self.roads.flags.get(&flag).map(|f| (f.x, f.y)) == Some((c.x, c.y));
+                if at_flag {
+                    self.carrier_drop(c, road_id, flag, from, to, &path);
+                } else {
+                    self.carrier_walk(c, &path, flag == from);
+                }
+            }
+            CarrierTask::None => {
+                if let Some((flag, ware)) = self.find_carrier_work(road_id, from, to) {
+                    c.task = CarrierTask::Fetch { flag, ware };
+                    self.carrier_walk(c, &path, flag == from);
+                } else if c.state != SettlerState::Idle {
+                    // Ohne Arbeit in der Mitte der Straße warten
+                    let (mx, my) = path[path.len() / 2];
+                    if (c.x, c.y) == (mx, my) {
+                        c.state = SettlerState::Idle;
+                    } else {
+                        self.carrier_walk_to_index(c, &path, path.len() / 2);
+                    }
+                }
+            }
+        }
+        true
+    }
+
+    // Ware mit der höchsten Priorität, die über diese Straße muss
+    fn find_carrier_work(
+        &self,
+        road: RoadId,
+        from: FlagId,
+        to: FlagId,
+    ) -> Option<(FlagId, WareId)> {
+        let mut best: Option<(std::cmp::Reverse<u8>, WareId, FlagId)> = None;
+        for flag in [from, to].iter() {
+            for ware in &self.roads.flags[flag].wares {
+                let routed = self
+                    .destination_flag(*ware)
+                    .and_then(|dest| self.roads.next_hop(*flag, dest))
+                    == Some(road);
+                if routed {
+                    let candidate = (
+                        std::cmp::Reverse(self.transport.priority(*ware)),
+                        *ware,
+                        *flag,
+                    );
+                    if best.is_none_or(|b| candidate < b) {
+                        best = Some(candidate);
+                    }
+                }
+            }
+        }
+        best.map(|(_, ware, flag)| (flag, ware))
+    }
+
+    fn carrier_drop(
+        &mut self,
+        c: &mut Settler,
+        road: RoadId,
+        flag: FlagId,
+        from: FlagId,
+        to: FlagId,
+        path: &[(u16, u16)],
+    ) {
+        let ware = match c.carrying {
+            Some(ware) => ware,
+            None => {
+                c.task = CarrierTask::None;
+                return;
+            }
+        };
+        let dest = match self.transport.destination(ware) {
+            Some(dest) => dest,
+            None => {
+                // Anfrage verfallen
+                self.transport.wares.remove(&ware);
+                c.carrying = None;
+                c.task = CarrierTask::None;
+                return;
+            }
+        };
+        if self.buildings[&dest].flag == flag {
+            // Am Ziel: direkt ins Gebäude
+            let good = self.transport.wares[&ware].good;
+            if self.deliver(dest, good) {
+                self.transport.complete(ware);
+            } else {
+                // Wird nicht mehr gebraucht, von hier aus ins Lager
+                let owner = self.buildings[&dest].owner;
+                self.return_ware(ware, owner, Some(flag));
+                if self.transport.wares.contains_key(&ware) {
+                    return self.carrier_drop(c, road, flag, from, to, path);
+                }
+            }
+            c.carrying = None;
+            c.task = CarrierTask::None;
+            return;
+        }
+        if self.roads.flags[&flag].wares.len() < FLAG_CAPACITY {
+            self.roads.flags.get_mut(&flag).unwrap().wares.push(ware);
+            self.transport.wares.get_mut(&ware).unwrap().location = WareLocation::Flag(flag);
+            c.carrying = None;
+            c.task = CarrierTask::None;
+            return;
+        }
+        // Flagge voll: tauschen, wenn eine Ware in die Gegenrichtung wartet, sonst warten
+        let other = if flag == from { to } else { from };
+        let swap = self.roads.flags[&flag].wares.iter().copied().find(|w| {
+            self.destination_flag(*w)
+                .and_then(|dest| self.roads.next_hop(flag, dest))
+                == Some(road)
+        });
+        if let Some(swap) = swap {
+            let wares = &mut self.roads.flags.get_mut(&flag).unwrap().wares;
+            let index = wares.iter().position(|w| *w == swap).unwrap();
+            wares[index] = ware;
+            self.transport.wares.get_mut(&ware).unwrap().location = WareLocation::Flag(flag);
+            self.transport.wares.get_mut(&swap).unwrap().location = WareLocation::Carried(c.id);
+            c.carrying = Some(swap);
+            c.task = CarrierTask::Deliver { flag: other };
+            self.carrier_walk(c, path, other == from);
+        }
+    }
+
+    // Zum Anfang (to_start) oder Ende der Straße laufen
+    fn carrier_walk(&self, c: &mut Settler, path: &[(u16, u16)], to_start: bool) {
+        let index = if to_start { 0 } else { path.len() - 1 };
+        self.carrier_walk_to_index(c, path, index);
+    }
+
+    fn carrier_walk_to_index(&self, c: &mut Settler, path: &[(u16, u16)], index: usize) {
+        match path.iter().position(|p| *p == (c.x, c.y)) {
+            Some(current) if current <= index => c.walk_path(path[current + 1..=index].to_vec()),
+            Some(current) => c.walk_path(path[index..current].iter().rev().copied().collect()),
+            None => {
+                let (x, y) = path[index];
+                c.walk_to(&self.map, x, y);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{building::BuildingType, testing};
+
+    fn site_world() -> (World, BuildingId) {
+        let (mut world, castle) = testing::world();
+        let site = testing::connected_site(&mut world, castle, BuildingType::Woodcutter, 16, 10);
+        (world, site)
+    }
+
+    #[test]
+    fn carriers_deliver_the_same_way_every_time() {
+        let (mut a, site) = site_world();
+        let (mut b, _) = site_world();
+        for _ in 0..1000 {
+            a.tick();
+            b.tick();
+            assert_eq!(a.state_hash(), b.state_hash());
+        }
+        assert!(a.buildings[&site].is_finished());
+        assert!(a.transport.wares.is_empty() & a.transport.requests.is_empty());
+    }
+
+    #[test]
+    fn flags_hold_at_most_flag_capacity() {
+        let (mut world, castle) = testing::world();
+        let flag = world.buildings[&castle].flag;
+        let farm = testing::connected_site(&mut world, castle, BuildingType::Farm, 16, 10);
+        let mill = testing::connected_site(&mut world, castle, BuildingType::Sawmill, 4, 10);
+        let mut most = 0;
+        for _ in 0..1000 {
+            world.tick();
+            let wares = world.roads.flags[&flag].wares.len();
+            assert!(wares <= FLAG_CAPACITY);
+            most = most.max(wares);
+        }
+        // Beide Baustellen brauchen zusammen mehr, als an die Flagge passt
+        assert_eq!(most, FLAG_CAPACITY);
+        assert!(world.buildings[&farm].is_finished() & world.buildings[&mill].is_finished());
+    }
+
+    #[test]
+    fn wares_go_back_when_the_site_is_demolished() {
+        let (mut world, site) = site_world();
+        let stored = |w: &World| {
+            let stock = w.player_stock(0);
+            (stock[&Good::Board].stored, stock[&Good::Stone].stored)
+        };
+        let before = stored(&world);
+        let carried = testing::run_until(&mut world, 500, |w| {
+            w.transport
+                .wares
+                .values()
+                .any(|ware| matches!(ware.location, WareLocation::Carried(_)))
+        });
+        assert!(carried.is_some());
+        world.demolish_building(site);
+        testing::run_until(&mut world, 1000, |w| w.transport.wares.is_empty()).unwrap();
+        assert!(world.transport.requests.is_empty());
+        assert_eq!(stored(&world), before);
+    }
+}
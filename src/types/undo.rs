@@ -0,0 +1,277 @@
+use std::collections::{BTreeMap, VecDeque};
+
+use super::{
+    hextile::{MapObject, Tile, TileType},
+    map::Map,
+    resources::Deposit,
+};
+
+// So viele Schritte lassen sich höchstens rückgängig machen
+pub const MAX_UNDO_STEPS: usize = 100;
+
+// Geänderte Tiles über alle Schritte, zwei ganze Karten von 512x512. Wird es mehr,
+// fallen die ältesten Schritte weg.
+pub const MAX_UNDO_TILES: usize = 2 * 512 * 512;
+
+// Was der Editor an einem Tile ändern kann
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct TileEdit {
+    tile_type: TileType,
+    elevation: u8,
+    object: Option<MapObject>,
+    deposit: Option<Deposit>,
+}
+
+impl TileEdit {
+    fn of(tile: &Tile) -> Self {
+        TileEdit {
+            tile_type: tile.tile_type,
+            elevation: tile.elevation,
+            object: tile.object,
+            deposit: tile.deposit,
+        }
+    }
+
+    fn apply(self, tile: &mut Tile) {
+        tile.tile_type = self.tile_type;
+        tile.elevation = self.elevation;
+        tile.object = self.object;
+        tile.deposit = self.deposit;
+    }
+}
+
+struct TileChange {
+    x: u16,
+    y: u16,
+    before: TileEdit,
+    after: TileEdit,
+}
+
+struct StartsChange {
+    before: Vec<(u16, u16)>,
+    after: Vec<(u16, u16)>,
+}
+
+// Ein Pinselstrich vom Drücken bis zum Loslassen der Maustaste. Gemerkt werden nur die
+// Tiles, die danach anders sind als vorher.
+struct EditStep {
+    tiles: Vec<TileChange>,
+    // Nur wenn sich die Startpunkte geändert haben
+    starts: Option<StartsChange>,
+}
+
+// Der Strich, der gerade gemalt wird. Jedes Tile mit seinem Zustand vor dem Strich.
+struct Stroke {
+    before: BTreeMap<(u16, u16), TileEdit>,
+    starts: Vec<(u16, u16)>,
+}
+
+#[derive(Default)]
+pub struct EditHistory {
+    undo: VecDeque<EditStep>,
+    redo: Vec<EditStep>,
+    stroke: Option<Stroke>,
+    // Geänderte Tiles in undo und redo zusammen
+    tiles: usize,
+}
+
+impl EditHistory {
+    // Vor jeder Änderung aufrufen, mit allen Tiles, die sie berühren kann
+    pub fn record(&mut self, map: &Map, area: &[(u16, u16)], starts: &[(u16, u16)]) {
+        let stroke = self.stroke.get_or_insert_with(|| Stroke {
+            before: BTreeMap::new(),
+            starts: starts.to_vec(),
+        });
+        for (x, y) in area {
+            stroke
+                .before
+                .entry((*x, *y))
+                .or_insert_with(|| TileEdit::of(map.tile(*x, *y)));
+        }
+    }
+
+    // Schließt den Strich ab. Hat er nichts verändert, gibt es auch keinen Schritt.
+    pub fn end_stroke(&mut self, map: &Map, starts: &[(u16, u16)]) {
+        let Stroke {
+            before,
+            starts: starts_before,
+        } = match self.stroke.take() {
+            Some(stroke) => stroke,
+            None => return,
+        };
+        let tiles: Vec<TileChange> = before
+            .into_iter()
+            .filter_map(|((x, y), before)| {
+                let after = TileEdit::of(map.tile(x, y));
+                (after != before).then_some(TileChange {
+                    x,
+                    y,
+                    before,
+                    after,
+                })
+            })
+            .collect();
+        let starts = (starts_before != starts).then(|| StartsChange {
+            before: starts_before,
+            after: starts.to_vec(),
+        });
+        if tiles.is_empty() & starts.is_none() {
+            return;
+        }
+        // Neues verwirft, was zuletzt rückgängig gemacht wurde
+        for step in self.redo.drain(..) {
+            self.tiles -= step.tiles.len();
+        }
+        self.tiles += tiles.len();
+        self.undo.push_back(EditStep { tiles, starts });
+        // Der neueste Schritt bleibt immer, auch wenn er allein zu groß ist
+        while (self.undo.len() > MAX_UNDO_STEPS)
+            | ((self.tiles > MAX_UNDO_TILES) & (self.undo.len() > 1))
+        {
+            let oldest = self.undo.pop_front().unwrap();
+            self.tiles -= oldest.tiles.len();
+        }
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    // Nimmt den letzten Schritt zurück. false, wenn es keinen gibt.
+    pub fn undo(&mut self, map: &mut Map, starts: &mut Vec<(u16, u16)>) -> bool {
+        self.end_stroke(map, starts);
+        match self.undo.pop_back() {
+            Some(step) => {
+                for change in &step.tiles {
+                    change.before.apply(map.tile_mut(change.x, change.y));
+                }
+                if let Some(change) = &step.starts {
+                    *starts = change.before.clone();
+                }
+                self.redo.push(step);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn redo(&mut self, map: &mut Map, starts: &mut Vec<(u16, u16)>) -> bool {
+        self.end_stroke(map, starts);
+        match self.redo.pop() {
+            Some(step) => {
+                for change in &step.tiles {
+                    change.after.apply(map.tile_mut(change.x, change.y));
+                }
+                if let Some(change) = &step.starts {
+                    *starts = change.after.clone();
+                }
+                self.undo.push_back(step);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::editor::blank_map;
+
+    // Ein Strich, der alle Tiles in area auf tile_type setzt
+    fn paint(history: &mut EditHistory, map: &mut Map, area: &[(u16, u16)], tile_type: TileType) {
+        history.record(map, area, &[]);
+        for (x, y) in area {
+            map.tile_mut(*x, *y).tile_type = tile_type;
+        }
+        history.end_stroke(map, &[]);
+    }
+
+    fn all_tiles(map: &Map) -> Vec<(u16, u16)> {
+        (0..map.width)
+            .flat_map(|x| (0..map.height).map(move |y| (x, y)))
+            .collect()
+    }
+
+    #[test]
+    fn undo_and_redo_swap_the_states() {
+        let mut map = blank_map(8, 8);
+        let mut history = EditHistory::default();
+        let mut starts = vec![(1, 1)];
+        history.record(&map, &[(2, 2), (3, 3)], &starts);
+        map.tile_mut(2, 2).tile_type = TileType::TtSand;
+        map.tile_mut(3, 3).elevation = 5;
+        starts.push((6, 6));
+        history.end_stroke(&map, &starts);
+        let after = format!("{:?}", map);
+
+        assert!(history.undo(&mut map, &mut starts));
+        assert_eq!(format!("{:?}", map), format!("{:?}", blank_map(8, 8)));
+        assert_eq!(starts, vec![(1, 1)]);
+        assert!(!history.can_undo() & history.can_redo());
+
+        assert!(history.redo(&mut map, &mut starts));
+        assert_eq!(format!("{:?}", map), after);
+        assert_eq!(starts, vec![(1, 1), (6, 6)]);
+        assert!(!history.redo(&mut map, &mut starts));
+    }
+
+    #[test]
+    fn new_strokes_clear_redo() {
+        let mut map = blank_map(8, 8);
+        let mut history = EditHistory::default();
+        paint(&mut history, &mut map, &[(1, 1)], TileType::TtSand);
+        assert!(history.undo(&mut map, &mut Vec::new()));
+        assert!(history.can_redo());
+        paint(&mut history, &mut map, &[(2, 2)], TileType::TtSand);
+        assert!(!history.can_redo());
+        assert_eq!(map.tile(1, 1).tile_type, TileType::TtGras);
+        // Ein Strich ohne Änderung ist kein Schritt und lässt redo in Ruhe
+        assert!(history.undo(&mut map, &mut Vec::new()));
+        paint(&mut history, &mut map, &[(3, 3)], TileType::TtGras);
+        assert!(history.can_redo() & !history.can_undo());
+    }
+
+    #[test]
+    fn too_many_steps_drop_the_oldest() {
+        let mut map = blank_map(8, 8);
+        let mut history = EditHistory::default();
+        for i in 0..=MAX_UNDO_STEPS {
+            let tile_type = if i % 2 == 0 {
+                TileType::TtSand
+            } else {
+                TileType::TtGras
+            };
+            paint(&mut history, &mut map, &[(1, 1)], tile_type);
+        }
+        for _ in 0..MAX_UNDO_STEPS {
+            assert!(history.undo(&mut map, &mut Vec::new()));
+        }
+        assert!(!history.undo(&mut map, &mut Vec::new()));
+        // Der erste Strich lässt sich nicht mehr zurücknehmen
+        assert_eq!(map.tile(1, 1).tile_type, TileType::TtSand);
+    }
+
+    #[test]
+    fn too_many_tiles_drop_the_oldest() {
+        let mut map = blank_map(512, 512);
+        let mut history = EditHistory::default();
+        let all = all_tiles(&map);
+        paint(&mut history, &mut map, &[(1, 1)], TileType::TtDirt);
+        paint(&mut history, &mut map, &all, TileType::TtSand);
+        assert_eq!(history.undo.len(), 2);
+        // Zwei ganze Karten sind genau die Grenze, der kleine erste Strich fällt weg
+        paint(&mut history, &mut map, &all, TileType::TtMountain);
+        assert_eq!(history.undo.len(), 2);
+        assert_eq!(history.tiles, MAX_UNDO_TILES);
+        assert!(history.undo(&mut map, &mut Vec::new()));
+        assert!(history.undo(&mut map, &mut Vec::new()));
+        assert!(!history.undo(&mut map, &mut Vec::new()));
+        assert_eq!(map.tile(1, 1).tile_type, TileType::TtDirt);
+        assert_eq!(map.tile(2, 2).tile_type, TileType::TtGras);
+    }
+}
@@ -0,0 +1,444 @@
+use std::{collections::VecDeque, fmt};
+
+use super::{
+    fairness::{StartReport, FAIRNESS_RADIUS},
+    hextile::{Tile, TileType},
+    map::Map,
+    resources::ResourceKind,
+};
+
+// So viel freies Bauland braucht ein Start im Umkreis von FAIRNESS_RADIUS
+pub const MIN_BUILDABLE: u32 = 100;
+
+// Ohne Kohle und Eisen gibt es keine Werkzeuge und Waffen. Holz und Stein braucht
+// jeder Start sowieso.
+pub const REQUIRED_DEPOSITS: [ResourceKind; 2] = [ResourceKind::Coal, ResourceKind::Iron];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Problem {
+    NoStarts,
+    // Auf dem Start kann die Burg nicht stehen
+    StartBlocked {
+        start: usize,
+    },
+    LittleLand {
+        start: usize,
+        buildable: u32,
+    },
+    Unreachable {
+        start: usize,
+        resource: &'static str,
+    },
+    // Kein anderer Start ist zu Fuß erreichbar, "water" oder "mountains"
+    Isolated {
+        start: usize,
+        by: &'static str,
+    },
+    // Fluss, der weder ins Meer noch über den Kartenrand fließt
+    RiverEnd,
+    // Straßenstück, das nicht am größten Straßennetz hängt
+    RoadGap,
+}
+
+// Ein Problem und die Tiles, die der Editor dafür markiert
+#[derive(Debug, Clone, PartialEq)]
+pub struct MapWarning {
+    pub problem: Problem,
+    pub tiles: Vec<(u16, u16)>,
+}
+
+// Die Namen in Problem auf Deutsch für die Anzeige
+fn german(name: &str) -> &str {
+    match name {
+        "trees" => "Bäume",
+        "stone" => "Steine",
+        "Coal" => "Kohle",
+        "Iron" => "Eisen",
+        "water" => "Wasser",
+        "mountains" => "Berge",
+        other => other,
+    }
+}
+
+impl fmt::Display for MapWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let at = match self.tiles.first() {
+            Some((x, y)) => format!("{}/{}", x, y),
+            None => String::from("-"),
+        };
+        match &self.problem {
+            Problem::NoStarts => write!(f, "Die Karte hat keine Startpunkte"),
+            Problem::StartBlocked { start } => {
+                write!(f, "Start {} bei {}: hier kann keine Burg stehen", start, at)
+            }
+            Problem::LittleLand { start, buildable } => write!(
+                f,
+                "Start {} bei {}: nur {} bebaubare Tiles in der Nähe, nötig sind {}",
+                start, at, buildable, MIN_BUILDABLE
+            ),
+            Problem::Unreachable { start, resource } => write!(
+                f,
+                "Start {} bei {}: {} nicht erreichbar",
+                start,
+                at,
+                german(resource)
+            ),
+            Problem::Isolated { start, by } => write!(
+                f,
+                "Start {} bei {}: durch {} von allen anderen Spielern abgeschnitten",
+                start,
+                at,
+                german(by)
+            ),
+            Problem::RiverEnd => write!(
+                f,
+                "Fluss bei {} ({} Tiles) erreicht weder das Meer noch den Kartenrand",
+                at,
+                self.tiles.len()
+            ),
+            Problem::RoadGap => write!(
+                f,
+                "Straße bei {} ({} Tiles) hängt nicht am Straßennetz",
+                at,
+                self.tiles.len()
+            ),
+        }
+    }
+}
+
+// Was für den Start noch fehlt, aus dem Vergleich der Startpunkte
+fn missing_resources(report: &StartReport) -> Vec<&'static str> {
+    let mut missing = Vec::new();
+    if report.nearest_trees.is_none() {
+        missing.push("trees");
+    }
+    if report.nearest_stone.is_none() {
+        missing.push("stone");
+    }
+    for kind in REQUIRED_DEPOSITS.iter() {
+        if report.nearest_deposits[kind.index()].is_none() {
+            missing.push(kind.name());
+        }
+    }
+    missing
+}
+
+impl Map {
+    // Alle Tiles, die von (x, y) aus über Tiles mit pass erreichbar sind und noch nicht
+    // in reached stehen. Markiert sie dort, Index x * height + y.
+    fn flood(
+        &self,
+        x: u16,
+        y: u16,
+        pass: impl Fn(&Tile) -> bool,
+        reached: &mut [bool],
+    ) -> Vec<(u16, u16)> {
+        let index = |x: u16, y: u16| x as usize * self.height as usize + y as usize;
+        let mut tiles = vec![(x, y)];
+        let mut queue = VecDeque::new();
+        reached[index(x, y)] = true;
+        queue.push_back((x, y));
+        while let Some((cx, cy)) = queue.pop_front() {
+            for (nx, ny) in self.neighbours(cx, cy) {
+                if !reached[index(nx, ny)] & pass(self.tile(nx, ny)) {
+                    reached[index(nx, ny)] = true;
+                    tiles.push((nx, ny));
+                    queue.push_back((nx, ny));
+                }
+            }
+        }
+        tiles
+    }
+
+    // Zusammenhängende Stücke aus Tiles eines Bodens
+    fn pieces(&self, tile_type: TileType) -> Vec<Vec<(u16, u16)>> {
+        let mut done = vec![false; self.width as usize * self.height as usize];
+        let mut pieces = Vec::new();
+        for tile in self.tiles.iter().flatten() {
+            let i = tile.x as usize * self.height as usize + tile.y as usize;
+            if (tile.tile_type == tile_type) & !done[i] {
+                pieces.push(self.flood(tile.x, tile.y, |t| t.tile_type == tile_type, &mut done));
+            }
+        }
+        pieces
+    }
+
+    fn at_edge(&self, x: u16, y: u16) -> bool {
+        (x == 0) | (y == 0) | (x + 1 == self.width) | (y + 1 == self.height)
+    }
+
+    // Prüft eine Karte vor dem Veröffentlichen
+    pub fn validate(&self, starts: &[(u16, u16)]) -> ValidationReport {
+        let mut warnings = Vec::new();
+        let mut warn = |problem: Problem, tiles: Vec<(u16, u16)>| {
+            warnings.push(MapWarning { problem, tiles });
+        };
+        if starts.is_empty() {
+            warn(Problem::NoStarts, Vec::new());
+        }
+        let report = self.analyze_fairness(starts);
+        for (start, (s, (x, y))) in report.starts.iter().zip(starts.iter()).enumerate() {
+            let at = vec![(*x, *y)];
+            let tile = self.tile(*x, *y);
+            if !tile.tile_type.is_buildable() | tile.object.is_some() {
+                warn(Problem::StartBlocked { start }, at.clone());
+            }
+            if s.buildable < MIN_BUILDABLE {
+                warn(
+                    Problem::LittleLand {
+                        start,
+                        buildable: s.buildable,
+                    },
+                    at.clone(),
+                );
+            }
+            for resource in missing_resources(s) {
+                warn(Problem::Unreachable { start, resource }, at.clone());
+            }
+            if starts.len() < 2 {
+                continue;
+            }
+            // Zu Fuß gar nicht, oder nur über die Berge
+            let by = if s.nearest_opponent.is_none() {
+                Some("water")
+            } else {
+                let mut lowland = vec![false; self.width as usize * self.height as usize];
+                self.flood(
+                    *x,
+                    *y,
+                    |t| t.tile_type.is_walkable() & (t.tile_type != TileType::TtMountain),
+                    &mut lowland,
+                );
+                let reached = starts.iter().enumerate().any(|(other, (ox, oy))| {
+                    (other != start) & lowland[*ox as usize * self.height as usize + *oy as usize]
+                });
+                (!reached).then_some("mountains")
+            };
+            if let Some(by) = by {
+                warn(Problem::Isolated { start, by }, at);
+            }
+        }
+        for river in self.pieces(TileType::TtRiver) {
+            let flows = river.iter().any(|(x, y)| {
+                self.at_edge(*x, *y)
+                    | self
+                        .neighbours(*x, *y)
+                        .iter()
+                        .any(|(nx, ny)| self.tile(*nx, *ny).tile_type == TileType::TtOcean)
+            });
+            if !flows {
+                warn(Problem::RiverEnd, river);
+            }
+        }
+        let mut roads = self.pieces(TileType::TtRoad);
+        roads.sort_by_key(|road| std::cmp::Reverse(road.len()));
+        for road in roads.into_iter().skip(1) {
+            warn(Problem::RoadGap, road);
+        }
+        ValidationReport { warnings }
+    }
+}
+
+// Ohne Warnungen ist die Karte bereit zum Veröffentlichen
+#[derive(Debug, Clone, Default)]
+pub struct ValidationReport {
+    pub warnings: Vec<MapWarning>,
+}
+
+impl ValidationReport {
+    // Alle markierten Tiles, ein Tile kann zu mehreren Warnungen gehören
+    pub fn tiles(&self) -> Vec<(u16, u16)> {
+        let mut tiles: Vec<(u16, u16)> = self
+            .warnings
+            .iter()
+            .flat_map(|w| w.tiles.iter().copied())
+            .collect();
+        tiles.sort_unstable();
+        tiles.dedup();
+        tiles
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for warning in self.warnings.iter() {
+            writeln!(f, "Warnung: {}", warning)?;
+        }
+        if self.warnings.is_empty() {
+            writeln!(f, "Die Karte ist in Ordnung")
+        } else {
+            writeln!(
+                f,
+                "{} Warnungen, Starts im Umkreis von {} Tiles geprüft",
+                self.warnings.len(),
+                FAIRNESS_RADIUS
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{editor::blank_map, hextile::MapObject, resources::Deposit};
+
+    const STARTS: [(u16, u16); 2] = [(12, 20), (27, 19)];
+
+    // Alles, was ein Start braucht, bei beiden Starts um die Kartenmitte gedreht
+    fn good_map() -> Map {
+        let mut map = blank_map(40, 40);
+        for (i, (x, y)) in STARTS.iter().enumerate() {
+            let at = |dx: i32, dy: i32| {
+                let turn = if i == 0 { 1 } else { -1 };
+                (
+                    (*x as i32 + dx * turn) as u16,
+                    (*y as i32 + dy * turn) as u16,
+                )
+            };
+            let (tx, ty) = at(-3, 0);
+            map.tile_mut(tx, ty).object = Some(MapObject::Tree);
+            let (sx, sy) = at(0, -3);
+            map.tile_mut(sx, sy).object = Some(MapObject::Stone(6));
+            for (dx, kind) in [(2, ResourceKind::Coal), (3, ResourceKind::Iron)] {
+                let (dx, dy) = at(dx, 3);
+                let tile = map.tile_mut(dx, dy);
+                tile.tile_type = TileType::TtMountain;
+                tile.deposit = Some(Deposit { kind, amount: 8 });
+            }
+        }
+        map
+    }
+
+    fn problems(map: &Map, starts: &[(u16, u16)]) -> Vec<Problem> {
+        map.validate(starts)
+            .warnings
+            .into_iter()
+            .map(|w| w.problem)
+            .collect()
+    }
+
+    #[test]
+    fn good_maps_have_no_warnings() {
+        let report = good_map().validate(&STARTS);
+        assert!(report.warnings.is_empty(), "{}", report);
+        assert_eq!(report.to_string(), "Die Karte ist in Ordnung\n");
+        assert_eq!(good_map().analyze_fairness(&STARTS).unfair(), Vec::new());
+    }
+
+    #[test]
+    fn maps_need_starts() {
+        assert_eq!(problems(&good_map(), &[]), vec![Problem::NoStarts]);
+    }
+
+    #[test]
+    fn blocked_starts_are_found() {
+        let mut map = good_map();
+        map.tile_mut(12, 20).object = Some(MapObject::Tree);
+        map.tile_mut(27, 19).tile_type = TileType::TtRiver;
+        let report = map.validate(&STARTS);
+        let blocked: Vec<&MapWarning> = report
+            .warnings
+            .iter()
+            .filter(|w| matches!(w.problem, Problem::StartBlocked { .. }))
+            .collect();
+        assert_eq!(blocked.len(), 2);
+        assert_eq!(blocked[1].tiles, vec![(27, 19)]);
+        assert_eq!(
+            blocked[0].to_string(),
+            "Start 0 bei 12/20: hier kann keine Burg stehen"
+        );
+    }
+
+    #[test]
+    fn small_maps_have_little_land_and_nothing_to_reach() {
+        let map = blank_map(8, 8);
+        let found = problems(&map, &[(4, 4)]);
+        assert!(matches!(
+            found[0],
+            Problem::LittleLand {
+                start: 0,
+                buildable: 64
+            }
+        ));
+        let resources: Vec<&str> = found[1..]
+            .iter()
+            .map(|p| match p {
+                Problem::Unreachable { start: 0, resource } => *resource,
+                other => panic!("{:?}", other),
+            })
+            .collect();
+        assert_eq!(resources, vec!["trees", "stone", "Coal", "Iron"]);
+        assert_eq!(
+            map.validate(&[(4, 4)]).warnings[3].to_string(),
+            "Start 0 bei 4/4: Kohle nicht erreichbar"
+        );
+    }
+
+    #[test]
+    fn isolated_starts_are_found() {
+        for (tile_type, by) in [
+            (TileType::TtOcean, "water"),
+            (TileType::TtMountain, "mountains"),
+        ] {
+            let mut map = good_map();
+            for y in 0..40 {
+                map.tile_mut(20, y).tile_type = tile_type;
+            }
+            let isolated: Vec<Problem> = problems(&map, &STARTS)
+                .into_iter()
+                .filter(|p| matches!(p, Problem::Isolated { .. }))
+                .collect();
+            assert_eq!(
+                isolated,
+                vec![
+                    Problem::Isolated { start: 0, by },
+                    Problem::Isolated { start: 1, by }
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn rivers_must_flow_somewhere() {
+        let mut map = good_map();
+        // Einer endet am Rand, einer im Meer, einer mitten im Land
+        for x in 0..4 {
+            map.tile_mut(x, 5).tile_type = TileType::TtRiver;
+        }
+        map.tile_mut(30, 30).tile_type = TileType::TtOcean;
+        for x in 25..30 {
+            map.tile_mut(x, 30).tile_type = TileType::TtRiver;
+        }
+        for x in 15..18 {
+            map.tile_mut(x, 35).tile_type = TileType::TtRiver;
+        }
+        let report = map.validate(&STARTS);
+        assert_eq!(report.warnings.len(), 1, "{}", report);
+        assert_eq!(report.warnings[0].problem, Problem::RiverEnd);
+        assert_eq!(report.warnings[0].tiles.len(), 3);
+        assert_eq!(report.tiles(), vec![(15, 35), (16, 35), (17, 35)]);
+    }
+
+    #[test]
+    fn roads_must_be_connected() {
+        let mut map = good_map();
+        for x in 5..15 {
+            map.tile_mut(x, 30).tile_type = TileType::TtRoad;
+        }
+        for x in 30..33 {
+            map.tile_mut(x, 5).tile_type = TileType::TtRoad;
+        }
+        let report = map.validate(&STARTS);
+        assert_eq!(report.warnings.len(), 1, "{}", report);
+        assert_eq!(report.warnings[0].problem, Problem::RoadGap);
+        assert_eq!(report.warnings[0].tiles.len(), 3);
+        assert_eq!(
+            report.to_string(),
+            format!(
+                "Warnung: Straße bei 30/5 (3 Tiles) hängt nicht am Straßennetz\n\
+                 1 Warnungen, Starts im Umkreis von {} Tiles geprüft\n",
+                FAIRNESS_RADIUS
+            )
+        );
+    }
+}
@@ -0,0 +1,216 @@
+use coffee::graphics::{Color, Shape};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    building::{Building, BuildingId},
+    map::Map,
+    player::PlayerId,
+    settler::{Job, Settler},
+    world::World,
+};
+
+// Alle so viele Ticks wird neu berechnet, was jeder Spieler sieht
+pub const VISIBILITY_TICKS: u64 = 10;
+
+// Sichtweite eines Siedlers, Soldaten sehen weiter
+pub const SETTLER_SIGHT: u16 = 3;
+pub const SOLDIER_SIGHT: u16 = 5;
+
+// Zivile Gebäude sehen etwas über ihre Umgebung hinaus
+pub const BUILDING_SIGHT: u16 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Sight {
+    // Nie gesehen, wird schwarz gemalt
+    Unexplored,
+    // Schon einmal gesehen, aber gerade nicht, wird abgedunkelt
+    Explored,
+    Visible,
+}
+
+// Sichtmaske eines Spielers über die ganze Karte
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Visibility {
+    pub width: u16,
+    pub height: u16,
+    pub explored: Vec<bool>,
+    pub visible: Vec<bool>,
+}
+
+impl Visibility {
+    pub fn new(width: u16, height: u16) -> Self {
+        let size = width as usize * height as usize;
+        Visibility {
+            width,
+            height,
+            explored: vec![false; size],
+            visible: vec![false; size],
+        }
+    }
+
+    fn index(&self, x: u16, y: u16) -> usize {
+        x as usize * self.height as usize + y as usize
+    }
+
+    pub fn sight(&self, x: u16, y: u16) -> Sight {
+        let i = self.index(x, y);
+        if self.visible[i] {
+            Sight::Visible
+        } else if self.explored[i] {
+            Sight::Explored
+        } else {
+            Sight::Unexplored
+        }
+    }
+
+    pub fn is_visible(&self, x: u16, y: u16) -> bool {
+        self.visible[self.index(x, y)]
+    }
+
+    // Alles wieder unsichtbar, erkundet bleibt erkundet
+    pub fn clear_visible(&mut self) {
+        for v in self.visible.iter_mut() {
+            *v = false;
+        }
+    }
+
+    pub fn reveal(&mut self, map: &Map, x: u16, y: u16, radius: u16) {
+        for (tx, ty) in map.spiral(x, y, radius) {
+            let i = self.index(tx, ty);
+            self.visible[i] = true;
+            self.explored[i] = true;
+        }
+    }
+}
+
+impl Building {
+    pub fn sight_radius(&self) -> u16 {
+        match self.building_type.territory_radius() {
+            Some(radius) => radius + 4,
+            None => BUILDING_SIGHT,
+        }
+    }
+}
+
+impl Settler {
+    pub fn sight_radius(&self) -> u16 {
+        if self.job == Job::Soldier {
+            SOLDIER_SIGHT
+        } else {
+            SETTLER_SIGHT
+        }
+    }
+}
+
+// Nebel des Krieges
+impl World {
+    pub(crate) fn update_visibility(&mut self) {
+        if !self.tick.is_multiple_of(VISIBILITY_TICKS) {
+            return;
+        }
+        let players: Vec<PlayerId> = self.visibility.keys().copied().collect();
+        for player in players {
+            self.refresh_visibility(player);
+        }
+    }
+
+    // Berechnet die Sicht eines Spielers aus seinen Gebäuden und Siedlern neu
+    pub fn refresh_visibility(&mut self, player: PlayerId) {
+        let (width, height) = (self.map.width, self.map.height);
+        let vis = self
+            .visibility
+            .entry(player)
+            .or_insert_with(|| Visibility::new(width, height));
+        vis.clear_visible();
+        for b in self.buildings.values().filter(|b| b.owner == player) {
+            vis.reveal(&self.map, b.x, b.y, b.sight_radius());
+        }
+        for s in self.settlers.values().filter(|s| s.owner == player) {
+            vis.reveal(&self.map, s.x, s.y, s.sight_radius());
+        }
+    }
+
+    // Ohne Sichtmaske sieht ein Spieler nichts
+    pub fn sight(&self, player: PlayerId, x: u16, y: u16) -> Sight {
+        self.visibility
+            .get(&player)
+            .map_or(Sight::Unexplored, |vis| vis.sight(x, y))
+    }
+
+    pub fn is_visible(&self, player: PlayerId, x: u16, y: u16) -> bool {
+        self.sight(player, x, y) == Sight::Visible
+    }
+
+    // Gebäude, die der Spieler gerade sieht oder schon einmal gesehen hat
+    pub fn known_buildings(&self, player: PlayerId) -> Vec<BuildingId> {
+        self.buildings
+            .values()
+            .filter(|b| (b.owner == player) | (self.sight(player, b.x, b.y) != Sight::Unexplored))
+            .map(|b| b.id)
+            .collect()
+    }
+
+    // Fremde Siedler nur, wenn sie gerade zu sehen sind
+    pub fn visible_settlers(&self, player: PlayerId) -> Vec<&Settler> {
+        self.settlers
+            .values()
+            .filter(|s| (s.owner == player) | self.is_visible(player, s.x, s.y))
+            .collect()
+    }
+
+    // Unerkundetes schwarz, Erkundetes abgedunkelt
+    pub fn draw_fog(&self, param: &mut crate::DrawParameter, player: PlayerId) {
+        for tile in self.map.tiles.iter().flatten() {
+            let color = match self.sight(player, tile.x, tile.y) {
+                Sight::Visible => continue,
+                Sight::Explored => Color::new(0.0, 0.0, 0.0, 0.5),
+                Sight::Unexplored => Color::BLACK,
+            };
+            let points =
+                tile.get_hex_point_vec(param.camera.cameraoffset_x, param.camera.cameraoffset_y);
+            param.camera.mesh.fill(Shape::Polyline { points }, color);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::testing;
+
+    #[test]
+    fn moving_units_explore_and_leave_the_rest_explored() {
+        let (mut world, castle) = testing::world();
+        let enemy = world.add_player_start(1, 30, 30).unwrap();
+        assert_eq!(world.sight(0, 10, 10), Sight::Visible);
+        assert_eq!(world.sight(0, 30, 30), Sight::Unexplored);
+        assert_eq!(world.known_buildings(0), vec![castle]);
+
+        let scout = world
+            .settlers
+            .values()
+            .find(|s| s.owner == 0)
+            .map(|s| s.id)
+            .unwrap();
+        let move_scout = |world: &mut World, x: u16, y: u16| {
+            let settler = world.settlers.get_mut(&scout).unwrap();
+            settler.x = x;
+            settler.y = y;
+            world.refresh_visibility(0);
+        };
+        move_scout(&mut world, 29, 29);
+        assert_eq!(world.sight(0, 30, 30), Sight::Visible);
+        assert_eq!(world.sight(0, 33, 33), Sight::Unexplored);
+        assert!(world.visible_settlers(0).iter().any(|s| s.owner == 1));
+
+        move_scout(&mut world, 10, 11);
+        assert_eq!(world.sight(0, 30, 30), Sight::Explored);
+        assert_eq!(world.sight(0, 10, 11), Sight::Visible);
+        // Fremde Siedler verschwinden im Nebel, das Gebäude bleibt bekannt
+        assert!(world.visible_settlers(0).iter().all(|s| s.owner == 0));
+        assert_eq!(world.known_buildings(0), vec![castle, enemy]);
+        // Die Masken des anderen Spielers bleiben unberührt
+        assert_eq!(world.sight(1, 29, 29), Sight::Visible);
+        assert_eq!(world.sight(1, 10, 10), Sight::Unexplored);
+    }
+}
@@ -0,0 +1,570 @@
+use coffee::graphics::{Color, Point, Rectangle, Shape};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use super::{
+    building::{Building, BuildingId, BuildingSize, BuildingState, BuildingType, PlacementError},
+    command::{Command, CommandError, TimedCommand},
+    construction::ConstructionSite,
+    desync::{StateHash, HASH_TICKS},
+    goods::Good,
+    hextile::TileType,
+    map::Map,
+    player::{player_color, PlayerId},
+    production::ProductionDefs,
+    random::GameRng,
+    road::{FlagId, RoadError, RoadId, RoadNetwork},
+    settler::{Job, Settler, SettlerId, SettlerState},
+    transport::{Transport, FLAG_CAPACITY},
+    visibility::Visibility,
+};
+
+// Was zu Beginn in der Burg liegt
+pub const START_STOCK: [(Good, u16); 10] = [
+    (Good::Board, 40),
+    (Good::Stone, 30),
+    (Good::Log, 10),
+    (Good::Hammer, 6),
+    (Good::Axe, 3),
+    (Good::Pickaxe, 4),
+    (Good::Saw, 2),
+    (Good::Scythe, 2),
+    (Good::FishingRod, 2),
+    (Good::Sword, 4),
+];
+
+// So viele Siedler ohne Beruf warten zu Beginn in der Burg
+pub const START_SETTLERS: u16 = 20;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct World {
+    pub map: Map,
+    pub buildings: BTreeMap<BuildingId, Building>,
+    pub settlers: BTreeMap<SettlerId, Settler>,
+    pub roads: RoadNetwork,
+    pub transport: Transport,
+    // Kommt aus den Spieldaten, nicht aus dem Spielstand
+    #[serde(skip)]
+    pub production: ProductionDefs,
+    pub rng: GameRng,
+    // Nebel des Krieges pro Spieler
+    pub visibility: BTreeMap<PlayerId, Visibility>,
+    // Befehle für den nächsten Tick und die, die dabei abgelehnt wurden
+    pub commands: Vec<(PlayerId, Command)>,
+    #[serde(skip)]
+    pub rejected: Vec<(PlayerId, Command, CommandError)>,
+    // Alles, was bisher ausgeführt wurde
+    pub command_log: Vec<TimedCommand>,
+    // Zustandshash alle HASH_TICKS Ticks
+    pub hash_log: Vec<StateHash>,
+    pub next_id: u32,
+    pub tick: u64,
+}
+
+impl World {
+    pub fn new(map: Map, production: ProductionDefs) -> Self {
+        World {
+            map,
+            buildings: BTreeMap::new(),
+            settlers: BTreeMap::new(),
+            roads: RoadNetwork::new(),
+            transport: Transport::new(),
+            production,
+            rng: GameRng::new(1),
+            visibility: BTreeMap::new(),
+            commands: Vec::new(),
+            rejected: Vec::new(),
+            command_log: Vec::new(),
+            hash_log: Vec::new(),
+            next_id: 1,
+            tick: 0,
+        }
+    }
+
+    pub fn next_id(&mut self) -> u32 {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
+    // Startposition: Gebiet beanspruchen, Platz für die Burg frei machen und Burg hinstellen.
+    // Passt die Burg dort nicht hin (z.B. zu nah am Rand), bleibt die Karte unverändert
+    pub fn add_player_start(
+        &mut self,
+        player: PlayerId,
+        x: u16,
+        y: u16,
+    ) -> Result<BuildingId, PlacementError> {
+        if !self.map.contains(x as i32, y as i32) {
+            return Err(PlacementError::OutOfMap);
+        }
+        let before = self.map.clone();
+        let radius = BuildingType::Castle.territory_radius().unwrap();
+        for (tx, ty) in self.map.spiral(x, y, radius) {
+            let tile = self.map.tile_mut(tx, ty);
+            if tile.owner.is_none() {
+                tile.owner = Some(player);
+            }
+        }
+        // Burg und Flagge davor
+        for (tx, ty) in self.map.spiral(x, y, 2) {
+            let tile = self.map.tile_mut(tx, ty);
+            tile.tile_type = TileType::TtGras;
+            tile.elevation = 0;
+            tile.object = None;
+        }
+        let id = match self.place_building(player, BuildingType::Castle, x, y) {
+            Ok(id) => id,
+            Err(err) => {
+                self.map = before;
+                return Err(err);
+            }
+        };
+        let castle = self.buildings.get_mut(&id).unwrap();
+        castle.state = BuildingState::Finished;
+        castle.stock = START_STOCK.iter().copied().collect();
+        let (cx, cy) = (castle.x, castle.y);
+        for _ in 0..START_SETTLERS {
+            let settler_id = self.next_id();
+            self.settlers.insert(
+                settler_id,
+                Settler::new(settler_id, player, Job::Settler, cx, cy),
+            );
+        }
+        self.update_territory();
+        self.refresh_visibility(player);
+        Ok(id)
+    }
+
+    pub fn check_placement(
+        &self,
+        player: PlayerId,
+        bt: BuildingType,
+        x: u16,
+        y: u16,
+    ) -> Result<Vec<(u16, u16)>, PlacementError> {
+        Building::check_placement(&self.map, player, bt, x, y)
+    }
+
+    // Setzt eine Baustelle, wenn der Platz gültig ist
+    pub fn place_building(
+        &mut self,
+        player: PlayerId,
+        bt: BuildingType,
+        x: u16,
+        y: u16,
+    ) -> Result<BuildingId, PlacementError> {
+        let tiles = self.check_placement(player, bt, x, y)?;
+        let (fx, fy) = bt.size().flag_offset();
+        let (fx, fy) = self.map.offset(x, y, fx, fy).unwrap();
+        let flag = match self.map.tile(fx, fy).flag {
+            Some(flag) => flag,
+            None => self
+                .roads
+                .place_flag(&mut self.map, player, fx, fy)
+                .map_err(|_| PlacementError::Flag)?,
+        };
+        let id = self.next_id();
+        for (tx, ty) in tiles {
+            self.map.tile_mut(tx, ty).building = Some(id);
+        }
+        self.roads.flags.get_mut(&flag).unwrap().building = Some(id);
+        self.buildings.insert(
+            id,
+            Building {
+                id,
+                building_type: bt,
+                owner: player,
+                x,
+                y,
+                flag,
+                state: BuildingState::ConstructionSite(ConstructionSite::new(bt.cost())),
+                stock: BTreeMap::new(),
+                output: BTreeMap::new(),
+                reserved: BTreeMap::new(),
+                priority: 1,
+                workshop: None,
+                residents: 0,
+                garrison: Vec::new(),
+            },
+        );
+        Ok(id)
+    }
+
+    // Abriss. Die Flagge bleibt stehen. Ohne Militärgebäude schrumpft das Gebiet.
+    pub fn demolish_building(&mut self, id: BuildingId) {
+        if let Some(building) = self.buildings.remove(&id) {
+            let claims_land =
+                building.is_finished() & building.building_type.territory_radius().is_some();
+            if let Some(workshop) = building.workshop {
+                self.send_home(workshop.worker);
+            }
+            for soldier in &building.garrison {
+                self.send_home(*soldier);
+            }
+            for (dx, dy) in building.building_type.size().footprint() {
+                if let Some((tx, ty)) = self.map.offset(building.x, building.y, *dx, *dy) {
+                    self.map.tile_mut(tx, ty).building = None;
+                }
+            }
+            if let Some(flag) = self.roads.flags.get_mut(&building.flag) {
+                flag.building = None;
+            }
+            if claims_land {
+                self.update_territory();
+            }
+        }
+    }
+
+    pub fn place_flag(&mut self, player: PlayerId, x: u16, y: u16) -> Result<FlagId, RoadError> {
+        self.roads.place_flag(&mut self.map, player, x, y)
+    }
+
+    pub fn build_road(
+        &mut self,
+        player: PlayerId,
+        path: Vec<(u16, u16)>,
+    ) -> Result<RoadId, RoadError> {
+        self.roads.build_road(&mut self.map, player, path)
+    }
+
+    pub fn remove_road(&mut self, id: RoadId) {
+        self.roads.remove_road(&mut self.map, id);
+    }
+
+    // Mit der Flagge verschwinden auch ihre Straßen, die Waren darauf und das Gebäude dahinter
+    pub fn remove_flag(&mut self, id: FlagId) {
+        if let Some(flag) = self.roads.flags.get(&id) {
+            for ware in flag.wares.clone() {
+                self.transport.lose_ware(ware);
+            }
+        }
+        if let Some(building) = self.roads.remove_flag(&mut self.map, id) {
+            self.demolish_building(building);
+        }
+    }
+
+    // Ware an einem Gebäude abliefern. false, wenn eine Baustelle sie nicht braucht.
+    pub fn deliver(&mut self, building: BuildingId, good: Good) -> bool {
+        match self.buildings.get_mut(&building) {
+            Some(b) => match b.site_mut() {
+                Some(site) => site.deliver(good),
+                None => {
+                    *b.stock.entry(good).or_insert(0) += 1;
+                    true
+                }
+            },
+            None => false,
+        }
+    }
+
+    pub fn set_priority(&mut self, building: BuildingId, priority: u8) {
+        if let Some(b) = self.buildings.get_mut(&building) {
+            b.priority = priority;
+        }
+        for req in self.transport.requests.values_mut() {
+            if req.building == building {
+                req.priority = priority;
+            }
+        }
+    }
+
+    pub fn castle_of(&self, player: PlayerId) -> Option<&Building> {
+        self.buildings.values().find(|b| {
+            (b.owner == player) & (b.building_type == BuildingType::Castle) & b.is_finished()
+        })
+    }
+
+    pub fn tick(&mut self) {
+        // Befehle gehören noch zum alten Tick, wie die der KI zwischen zwei Ticks
+        self.apply_commands();
+        self.tick += 1;
+        self.update_residences();
+        self.assign_jobs();
+        self.update_requests();
+        self.assign_requests();
+        self.store_surplus();
+        self.ship_reserved();
+        self.update_settlers();
+        self.update_idle_settlers();
+        self.update_workers();
+        self.update_military();
+        self.update_carriers();
+        self.update_resources();
+        self.update_visibility();
+        if self.tick.is_multiple_of(HASH_TICKS) {
+            let hash = self.state_hash();
+            self.hash_log.push(hash);
+        }
+    }
+
+    fn update_settlers(&mut self) {
+        let mut leaving = Vec::new();
+        let mut borders_changed = false;
+        let buildings = &mut self.buildings;
+        for settler in self.settlers.values_mut() {
+            if (settler.job != Job::Builder) | settler.workplace.is_none() {
+                continue;
+            }
+            let workplace = settler.workplace.and_then(|id| buildings.get_mut(&id));
+            let building = match workplace {
+                Some(b) => b,
+                None => {
+                    // Baustelle weg, Bauarbeiter geht zurück ins Lager
+                    leaving.push(settler.id);
+                    continue;
+                }
+            };
+            match settler.state {
+                SettlerState::Walking { .. } => {
+                    if settler.step() {
+                        settler.state = SettlerState::Working;
+                        if let Some(site) = building.site_mut() {
+                            site.builder_arrived = true;
+                        }
+                    }
+                }
+                SettlerState::Working => {
+                    if settler.job == Job::Builder {
+                        let done = building.site_mut().is_none_or(|site| site.work());
+                        if done {
+                            building.state = BuildingState::Finished;
+                            borders_changed |= building.building_type.territory_radius().is_some();
+                            leaving.push(settler.id);
+                        }
+                    }
+                }
+                SettlerState::Idle => {}
+            }
+        }
+        for id in leaving {
+            self.send_home(id);
+        }
+        if borders_changed {
+            self.update_territory();
+        }
+    }
+
+    // Mittelpunkt der Grundfläche in Bildschirmkoordinaten
+    pub(crate) fn footprint_center(
+        &mut self,
+        size: BuildingSize,
+        x: u16,
+        y: u16,
+        x_offset: i16,
+        y_offset: i16,
+    ) -> Point {
+        let mut sum_x = 0f32;
+        let mut sum_y = 0f32;
+        let mut count = 0f32;
+        for (dx, dy) in size.footprint() {
+            if let Some((tx, ty)) = self.map.offset(x, y, *dx, *dy) {
+                let center = self.map.tile_mut(tx, ty).get_center(x_offset, y_offset);
+                sum_x += center.coords.x;
+                sum_y += center.coords.y;
+                count += 1f32;
+            }
+        }
+        Point::new(sum_x / count, sum_y / count)
+    }
+
+    // Vorschau beim Platzieren: grün wenn gültig, rot wenn nicht
+    pub fn draw_ghost(
+        &mut self,
+        param: &mut crate::DrawParameter,
+        player: PlayerId,
+        bt: BuildingType,
+        x: u16,
+        y: u16,
+    ) {
+        let color = match self.check_placement(player, bt, x, y) {
+            Ok(_) => Color::new(0.0, 1.0, 0.0, 0.5),
+            Err(_) => Color::new(1.0, 0.0, 0.0, 0.5),
+        };
+        for (dx, dy) in bt.size().footprint() {
+            if let Some((tx, ty)) = self.map.offset(x, y, *dx, *dy) {
+                let points = self
+                    .map
+                    .tile_mut(tx, ty)
+                    .get_hex_point_vec(param.camera.cameraoffset_x, param.camera.cameraoffset_y);
+                param.camera.mesh.fill(Shape::Polyline { points }, color);
+            }
+        }
+    }
+
+    // Vorschau beim Straßenbau, grün wenn gültig, rot wenn nicht
+    pub fn draw_road_ghost(
+        &mut self,
+        param: &mut crate::DrawParameter,
+        player: PlayerId,
+        path: &[(u16, u16)],
+    ) {
+        if path.len() < 2 {
+            return;
+        }
+        let color = match self.roads.check_road(&self.map, player, path) {
+            Ok(_) => Color::new(0.0, 1.0, 0.0, 0.7),
+            Err(_) => Color::new(1.0, 0.0, 0.0, 0.7),
+        };
+        let points = path
+            .iter()
+            .map(|(x, y)| {
+                self.map
+                    .tile(*x, *y)
+                    .get_center(param.camera.cameraoffset_x, param.camera.cameraoffset_y)
+            })
+            .collect();
+        param
+            .camera
+            .mesh
+            .stroke(Shape::Polyline { points }, color, 3.0);
+    }
+}
+
+// So groß wird ein Gebäude gemalt
+pub fn building_radius(size: BuildingSize) -> f32 {
+    match size {
+        BuildingSize::Small => crate::FIELDWIDTH as f32 * 0.35,
+        BuildingSize::Medium => crate::FIELDWIDTH as f32 * 0.6,
+        BuildingSize::Large => crate::FIELDWIDTH as f32 * 0.9,
+    }
+}
+
+impl crate::Drawable for World {
+    fn draw(&mut self, param: &mut crate::DrawParameter) {
+        self.map.draw(param);
+        self.draw_borders(param);
+
+        let map = &self.map;
+        for road in self.roads.roads.values() {
+            let points = road
+                .path
+                .iter()
+                .map(|(x, y)| {
+                    map.tiles[*x as usize][*y as usize]
+                        .get_center(param.camera.cameraoffset_x, param.camera.cameraoffset_y)
+                })
+                .collect();
+            param
+                .camera
+                .mesh
+                .stroke(Shape::Polyline { points }, Color::from_rgb(90, 70, 40), 3.0);
+        }
+        for flag in self.roads.flags.values() {
+            let center = self.map.tiles[flag.x as usize][flag.y as usize]
+                .get_center(param.camera.cameraoffset_x, param.camera.cameraoffset_y);
+            param.camera.mesh.fill(
+                Shape::Polyline {
+                    points: vec![
+                        center,
+                        Point::new(center.coords.x, center.coords.y - 8.0),
+                        Point::new(center.coords.x + 5.0, center.coords.y - 6.0),
+                        Point::new(center.coords.x, center.coords.y - 4.0),
+                    ],
+                },
+                player_color(flag.owner),
+            );
+            // Wartende Waren im Kreis um die Flagge
+            for (i, ware) in flag.wares.iter().enumerate() {
+                let angle = i as f32 * std::f32::consts::PI * 2.0 / FLAG_CAPACITY as f32;
+                let good = self.transport.wares[ware].good;
+                param.camera.mesh.fill(
+                    Shape::Rectangle(Rectangle {
+                        x: center.coords.x + angle.cos() * 6.0 - 1.5,
+                        y: center.coords.y + angle.sin() * 4.0 - 1.5,
+                        width: 3.0,
+                        height: 3.0,
+                    }),
+                    good.color(),
+                );
+            }
+        }
+
+        // progress: None = fertig
+        let buildings: Vec<(BuildingType, PlayerId, Option<f32>, u16, u16)> = self
+            .buildings
+            .values()
+            .map(|b| {
+                (
+                    b.building_type,
+                    b.owner,
+                    b.site().map(|s| s.progress()),
+                    b.x,
+                    b.y,
+                )
+            })
+            .collect();
+        for (bt, owner, progress, x, y) in buildings {
+            let size = bt.size();
+            let center = self.footprint_center(
+                size,
+                x,
+                y,
+                param.camera.cameraoffset_x,
+                param.camera.cameraoffset_y,
+            );
+            let radius = building_radius(size);
+            let shape = Shape::Circle { center, radius };
+            match progress {
+                None => param.camera.mesh.fill(shape.clone(), bt.color()),
+                // Baustellen als Umriss, der Fortschritt wächst von innen
+                Some(progress) => {
+                    param.camera.mesh.stroke(shape.clone(), bt.color(), 2.0);
+                    if progress > 0f32 {
+                        param.camera.mesh.fill(
+                            Shape::Circle {
+                                center,
+                                radius: radius * progress,
+                            },
+                            bt.color(),
+                        );
+                    }
+                }
+            }
+            param.camera.mesh.stroke(shape, player_color(owner), 1.0);
+        }
+
+        let viewer = param.viewer;
+        let settlers: Vec<&Settler> = match viewer {
+            Some(player) => self.visible_settlers(player),
+            None => self.settlers.values().collect(),
+        };
+        for settler in settlers {
+            let center = self.map.tiles[settler.x as usize][settler.y as usize]
+                .get_center(param.camera.cameraoffset_x, param.camera.cameraoffset_y);
+            param.camera.mesh.fill(
+                Shape::Circle {
+                    center,
+                    radius: 2.5,
+                },
+                player_color(settler.owner),
+            );
+            if let Some(ware) = settler.carrying {
+                let good = self.transport.wares[&ware].good;
+                param.camera.mesh.fill(
+                    Shape::Rectangle(Rectangle {
+                        x: center.coords.x - 1.5,
+                        y: center.coords.y - 6.0,
+                        width: 3.0,
+                        height: 3.0,
+                    }),
+                    good.color(),
+                );
+            } else if let Some(good) = settler.carrying_good {
+                param.camera.mesh.fill(
+                    Shape::Rectangle(Rectangle {
+                        x: center.coords.x - 1.5,
+                        y: center.coords.y - 6.0,
+                        width: 3.0,
+                        height: 3.0,
+                    }),
+                    good.color(),
+                );
+            }
+        }
+
+        if let Some(player) = viewer {
+            self.draw_fog(param, player);
+        }
+    }
+}